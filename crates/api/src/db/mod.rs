@@ -0,0 +1,18 @@
+use once_cell::sync::Lazy;
+use sqlx::{PgPool, postgres::PgPoolOptions};
+
+pub mod orders;
+
+//left unset the api only answers from what the engine keeps in memory
+static POOL: Lazy<Option<PgPool>> = Lazy::new(|| {
+    let url = std::env::var("DATABASE_URL").ok()?;
+    PgPoolOptions::new()
+        .max_connections(5)
+        .connect_lazy(&url)
+        .inspect_err(|e| eprintln!("Invalid DATABASE_URL: {}", e))
+        .ok()
+});
+
+pub fn pool() -> Option<&'static PgPool> {
+    POOL.as_ref()
+}
@@ -0,0 +1,41 @@
+use serde::Serialize;
+
+use super::pool;
+
+// an order as last written from the engine's OrderUpdate messages, for orders the engine has
+// already dropped from its recent orders window
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct StoredOrder {
+    pub order_id: String,
+    pub executed_qty: String,
+    pub market: Option<String>,
+    pub price: Option<String>,
+    pub quantity: Option<String>,
+    pub side: Option<String>,
+    pub client_order_id: Option<String>,
+    pub order_type: Option<String>,
+    pub trigger_price: Option<String>,
+    pub group_id: Option<String>,
+    pub expire_at: Option<i64>,
+    pub status: Option<String>,
+    pub avg_fill_price: Option<String>,
+    pub updated_at: Option<i64>,
+}
+
+//none without a database or when the order was never stored
+pub async fn find_order(order_id: &str) -> anyhow::Result<Option<StoredOrder>> {
+    let Some(pool) = pool() else {
+        return Ok(None);
+    };
+
+    let order = sqlx::query_as::<_, StoredOrder>(
+        "SELECT order_id, executed_qty, market, price, quantity, side, client_order_id, \
+         order_type, trigger_price, group_id, expire_at, status, avg_fill_price, updated_at \
+         FROM orders WHERE order_id = $1",
+    )
+    .bind(order_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(order)
+}
@@ -1,7 +1,8 @@
 use actix_web::{App, HttpServer, web};
 
-use routes::order::order_router;
+use routes::{admin::admin_router, order::order_router, user::user_router};
 
+mod db;
 mod models;
 mod redis_manager;
 mod routes;
@@ -13,7 +14,12 @@ async fn main() -> std::io::Result<()> {
     HttpServer::new(|| {
         App::new()
             .wrap(actix_web::middleware::Logger::default())
-            .service(web::scope("/api/v1").configure(order_router))
+            .service(
+                web::scope("/api/v1")
+                    .configure(order_router)
+                    .configure(user_router)
+                    .configure(admin_router),
+            )
     })
     .bind(("127.0.0.1", 8000))?
     .run()
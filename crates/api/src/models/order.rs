@@ -1,3 +1,4 @@
+#[allow(dead_code)]
 pub struct Order {
     pub market: String,
     pub price: String,
@@ -1,2 +1,3 @@
 pub mod redis_actor;
+#[allow(clippy::module_inception)]
 pub mod redis_manager;
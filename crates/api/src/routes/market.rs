@@ -0,0 +1,59 @@
+use actix_web::{HttpResponse, Responder, web};
+use serde::Deserialize;
+
+use crate::{
+    redis_manager::redis_manager::RedisManager,
+    types::messages::{AUCTION, AuctionAction, MarketStatus, MessageToEngine, SET_MARKET_STATUS},
+};
+
+#[derive(Deserialize)]
+pub struct AuctionRequest {
+    market: String,
+    action: AuctionAction,
+}
+
+#[derive(Deserialize)]
+pub struct MarketStatusRequest {
+    market: String,
+    status: MarketStatus,
+    reason: Option<String>,
+}
+
+//mounted behind the admin guard
+pub fn market_admin_router(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::resource("/market/auction").route(web::post().to(auction)));
+    cfg.service(web::resource("/market/status").route(web::post().to(set_market_status)));
+}
+
+async fn auction(data: web::Json<AuctionRequest>) -> impl Responder {
+    let redis = RedisManager::get_instance();
+    let message = MessageToEngine {
+        type_: AUCTION.to_string(),
+        data: serde_json::json!({
+            "market": data.market,
+            "action": data.action
+        }),
+    };
+
+    match redis.send_and_await(message).await {
+        Ok(res) => HttpResponse::Ok().json(res),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+    }
+}
+
+async fn set_market_status(data: web::Json<MarketStatusRequest>) -> impl Responder {
+    let redis = RedisManager::get_instance();
+    let message = MessageToEngine {
+        type_: SET_MARKET_STATUS.to_string(),
+        data: serde_json::json!({
+            "market": data.market,
+            "status": data.status,
+            "reason": data.reason
+        }),
+    };
+
+    match redis.send_and_await(message).await {
+        Ok(res) => HttpResponse::Ok().json(res),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+    }
+}
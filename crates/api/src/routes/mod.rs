@@ -1 +1,4 @@
+pub mod admin;
+pub mod market;
 pub mod order;
+pub mod user;
@@ -2,23 +2,106 @@ use actix_web::{HttpResponse, Responder, web};
 use serde::Deserialize;
 
 use crate::{
+    db::orders::{StoredOrder, find_order},
     redis_manager::redis_manager::RedisManager,
-    types::messages::{CANCEL_ORDER, CREATE_ORDER, GET_OPEN_ORDERS, MessageToEngine, OrderSide},
+    types::messages::{
+        AMEND_ORDER, BATCH_ORDERS, CANCEL_ALL, CANCEL_ORDER, CREATE_ORDER, CREATE_ORDER_GROUP,
+        GET_OPEN_ORDERS, GET_ORDER, GroupType, MessageFromOrderbook, MessageToEngine, OrderSide,
+        OrderType, PegReference, TimeInForce,
+    },
 };
 
 #[derive(Deserialize)]
 pub struct CreateOrderRequest {
     market: String,
+    //not needed for market and stop market orders
+    #[serde(default)]
     price: String,
     quantity: String,
     side: OrderSide,
     user_id: String,
+    client_order_id: Option<String>,
+    #[serde(default)]
+    order_type: OrderType,
+    trigger_price: Option<String>,
+    trailing_offset: Option<String>,
+    trailing_percent: Option<String>,
+    //iceberg slice size, limit orders only
+    display_quantity: Option<String>,
+    #[serde(default)]
+    time_in_force: TimeInForce,
+    //millis, required for GTD orders
+    expire_at: Option<u64>,
+    //pegged limit orders treat price as an optional cap
+    peg: Option<PegReference>,
+    peg_offset: Option<String>,
 }
 
 #[derive(Deserialize)]
 pub struct CancelOrderRequest {
-    order_id: String,
+    order_id: Option<String>,
+    client_order_id: Option<String>,
+    user_id: Option<String>,
     market: String,
+    group_id: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct CreateOrderGroupRequest {
+    market: String,
+    user_id: String,
+    group_type: GroupType,
+    side: OrderSide,
+    quantity: String,
+    //left out for a market entry, not used by oco groups
+    #[serde(default)]
+    entry_price: String,
+    take_profit_price: String,
+    stop_trigger_price: String,
+    stop_limit_price: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct AmendOrderRequest {
+    order_id: Option<String>,
+    client_order_id: Option<String>,
+    user_id: Option<String>,
+    market: String,
+    price: Option<String>,
+    quantity: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct CancelAllRequest {
+    user_id: String,
+    market: Option<String>,
+    side: Option<OrderSide>,
+    min_price: Option<String>,
+    max_price: Option<String>,
+}
+
+//left without a user it cancels every matching order on the exchange
+#[derive(Deserialize)]
+pub struct AdminCancelAllRequest {
+    user_id: Option<String>,
+    market: Option<String>,
+    side: Option<OrderSide>,
+    min_price: Option<String>,
+    max_price: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum BatchOperationRequest {
+    Create(CreateOrderRequest),
+    Cancel(CancelOrderRequest),
+}
+
+#[derive(Deserialize)]
+pub struct BatchOrdersRequest {
+    orders: Vec<BatchOperationRequest>,
+    #[serde(default)]
+    all_or_none: bool,
 }
 
 #[derive(Deserialize)]
@@ -27,14 +110,31 @@ pub struct GetOpenOrdersQuery {
     market: String,
 }
 
+#[derive(Deserialize)]
+pub struct GetOrderQuery {
+    order_id: Option<String>,
+    client_order_id: Option<String>,
+    user_id: Option<String>,
+}
+
 pub fn order_router(cfg: &mut web::ServiceConfig) {
     cfg.service(
         web::resource("/order")
+            .route(web::get().to(get_order))
             .route(web::post().to(create_order))
+            .route(web::patch().to(amend_order))
             .route(web::delete().to(cancel_order)),
     );
 
     cfg.service(web::resource("/order/open").route(web::get().to(get_open_orders)));
+    cfg.service(web::resource("/order/group").route(web::post().to(create_order_group)));
+    cfg.service(web::resource("/orders").route(web::delete().to(cancel_all)));
+    cfg.service(web::resource("/orders/batch").route(web::post().to(batch_orders)));
+}
+
+//mounted behind the admin guard
+pub fn order_admin_router(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::resource("/orders").route(web::delete().to(admin_cancel_all)));
 }
 
 async fn create_order(data: web::Json<CreateOrderRequest>) -> impl Responder {
@@ -46,7 +146,17 @@ async fn create_order(data: web::Json<CreateOrderRequest>) -> impl Responder {
             "price": data.price,
             "quantity": data.quantity,
             "side": data.side,
-            "userId": data.user_id
+            "userId": data.user_id,
+            "clientOrderId": data.client_order_id,
+            "orderType": data.order_type,
+            "triggerPrice": data.trigger_price,
+            "trailingOffset": data.trailing_offset,
+            "trailingPercent": data.trailing_percent,
+            "displayQuantity": data.display_quantity,
+            "timeInForce": data.time_in_force,
+            "expireAt": data.expire_at,
+            "peg": data.peg,
+            "pegOffset": data.peg_offset
         }),
     };
 
@@ -72,13 +182,58 @@ async fn get_open_orders(query: web::Query<GetOpenOrdersQuery>) -> impl Responde
     }
 }
 
+async fn get_order(query: web::Query<GetOrderQuery>) -> impl Responder {
+    let redis = RedisManager::get_instance();
+    let message = MessageToEngine {
+        type_: GET_ORDER.to_string(),
+        data: serde_json::json!({
+            "orderId": query.order_id,
+            "clientOrderId": query.client_order_id,
+            "userId": query.user_id
+        }),
+    };
+
+    let response = match redis.send_and_await(message).await {
+        Ok(response) => response,
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string()),
+    };
+
+    //orders which left the engine's window are looked up in the database
+    let stored = match &response {
+        MessageFromOrderbook::OrderNotFound { order_id, .. } if !order_id.is_empty() => {
+            match find_order(order_id).await {
+                Ok(stored) => stored,
+                Err(e) => return HttpResponse::InternalServerError().body(e.to_string()),
+            }
+        }
+        _ => None,
+    };
+
+    order_response(response, stored)
+}
+
+fn order_response(response: MessageFromOrderbook, stored: Option<StoredOrder>) -> HttpResponse {
+    match (response, stored) {
+        (MessageFromOrderbook::OrderNotFound { .. }, Some(stored)) => {
+            HttpResponse::Ok().json(serde_json::json!({"type": "STORED_ORDER", "payload": stored}))
+        }
+        (response @ MessageFromOrderbook::OrderNotFound { .. }, None) => {
+            HttpResponse::NotFound().json(response)
+        }
+        (response, _) => HttpResponse::Ok().json(response),
+    }
+}
+
 async fn cancel_order(data: web::Json<CancelOrderRequest>) -> impl Responder {
     let redis = RedisManager::get_instance();
     let message = MessageToEngine {
         type_: CANCEL_ORDER.to_string(),
         data: serde_json::json!({
             "orderId": data.order_id,
-            "market": data.market
+            "clientOrderId": data.client_order_id,
+            "userId": data.user_id,
+            "market": data.market,
+            "groupId": data.group_id
         }),
     };
 
@@ -87,3 +242,181 @@ async fn cancel_order(data: web::Json<CancelOrderRequest>) -> impl Responder {
         Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
     }
 }
+
+async fn create_order_group(data: web::Json<CreateOrderGroupRequest>) -> impl Responder {
+    let redis = RedisManager::get_instance();
+    let message = MessageToEngine {
+        type_: CREATE_ORDER_GROUP.to_string(),
+        data: serde_json::json!({
+            "market": data.market,
+            "userId": data.user_id,
+            "groupType": data.group_type,
+            "side": data.side,
+            "quantity": data.quantity,
+            "entryPrice": data.entry_price,
+            "takeProfitPrice": data.take_profit_price,
+            "stopTriggerPrice": data.stop_trigger_price,
+            "stopLimitPrice": data.stop_limit_price
+        }),
+    };
+
+    match redis.send_and_await(message).await {
+        Ok(res) => HttpResponse::Ok().json(res),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+    }
+}
+
+async fn amend_order(data: web::Json<AmendOrderRequest>) -> impl Responder {
+    let redis = RedisManager::get_instance();
+    let message = MessageToEngine {
+        type_: AMEND_ORDER.to_string(),
+        data: serde_json::json!({
+            "orderId": data.order_id,
+            "clientOrderId": data.client_order_id,
+            "userId": data.user_id,
+            "market": data.market,
+            "price": data.price,
+            "quantity": data.quantity
+        }),
+    };
+
+    match redis.send_and_await(message).await {
+        Ok(res) => HttpResponse::Ok().json(res),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+    }
+}
+
+async fn cancel_all(data: web::Json<CancelAllRequest>) -> impl Responder {
+    send_cancel_all(serde_json::json!({
+        "userId": data.user_id,
+        "market": data.market,
+        "side": data.side,
+        "minPrice": data.min_price,
+        "maxPrice": data.max_price
+    }))
+    .await
+}
+
+async fn admin_cancel_all(data: web::Json<AdminCancelAllRequest>) -> impl Responder {
+    send_cancel_all(serde_json::json!({
+        "userId": data.user_id,
+        "market": data.market,
+        "side": data.side,
+        "minPrice": data.min_price,
+        "maxPrice": data.max_price
+    }))
+    .await
+}
+
+async fn send_cancel_all(data: serde_json::Value) -> HttpResponse {
+    let redis = RedisManager::get_instance();
+    let message = MessageToEngine {
+        type_: CANCEL_ALL.to_string(),
+        data,
+    };
+
+    match redis.send_and_await(message).await {
+        Ok(res) => HttpResponse::Ok().json(res),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+    }
+}
+
+async fn batch_orders(data: web::Json<BatchOrdersRequest>) -> impl Responder {
+    let orders: Vec<serde_json::Value> = data
+        .orders
+        .iter()
+        .map(|operation| match operation {
+            BatchOperationRequest::Create(order) => serde_json::json!({
+                "op": "create",
+                "market": order.market,
+                "price": order.price,
+                "quantity": order.quantity,
+                "side": order.side,
+                "userId": order.user_id,
+                "clientOrderId": order.client_order_id,
+                "orderType": order.order_type,
+                "triggerPrice": order.trigger_price,
+                "trailingOffset": order.trailing_offset,
+                "trailingPercent": order.trailing_percent,
+                "displayQuantity": order.display_quantity,
+                "timeInForce": order.time_in_force,
+                "expireAt": order.expire_at,
+                "peg": order.peg,
+                "pegOffset": order.peg_offset
+            }),
+            BatchOperationRequest::Cancel(order) => serde_json::json!({
+                "op": "cancel",
+                "orderId": order.order_id,
+                "clientOrderId": order.client_order_id,
+                "userId": order.user_id,
+                "market": order.market,
+                "groupId": order.group_id
+            }),
+        })
+        .collect();
+
+    let redis = RedisManager::get_instance();
+    let message = MessageToEngine {
+        type_: BATCH_ORDERS.to_string(),
+        data: serde_json::json!({
+            "orders": orders,
+            "allOrNone": data.all_or_none
+        }),
+    };
+
+    match redis.send_and_await(message).await {
+        Ok(res) => HttpResponse::Ok().json(res),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::http::StatusCode;
+
+    use super::*;
+
+    //as the engine serializes its reply
+    fn not_found() -> MessageFromOrderbook {
+        serde_json::from_str(
+            r#"{"type": "ORDER_NOT_FOUND", "payload": {
+                "order_id": "abc",
+                "reason": "Order is neither open nor among the last 10000 closed orders",
+                "code": "OUTSIDE_LOOKUP_WINDOW"
+            }}"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn orders_nobody_has_are_not_found() {
+        assert_eq!(
+            order_response(not_found(), None).status(),
+            StatusCode::NOT_FOUND
+        );
+    }
+
+    #[test]
+    fn stored_orders_answer_for_the_engine() {
+        let stored = StoredOrder {
+            order_id: "abc".to_string(),
+            executed_qty: "0.50000".to_string(),
+            market: Some("BTC_USDT".to_string()),
+            price: Some("60000.00".to_string()),
+            quantity: Some("0.50000".to_string()),
+            side: Some("buy".to_string()),
+            client_order_id: None,
+            order_type: Some("limit".to_string()),
+            trigger_price: None,
+            group_id: None,
+            expire_at: None,
+            status: Some("FILLED".to_string()),
+            avg_fill_price: Some("60000.00".to_string()),
+            updated_at: Some(1_000),
+        };
+        assert_eq!(
+            order_response(not_found(), Some(stored)).status(),
+            StatusCode::OK
+        );
+    }
+}
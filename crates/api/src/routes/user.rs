@@ -0,0 +1,96 @@
+use actix_web::{HttpResponse, Responder, web};
+use serde::Deserialize;
+
+use crate::{
+    redis_manager::redis_manager::RedisManager,
+    types::messages::{
+        AccountFlag, MessageToEngine, RiskLimits, SET_ACCOUNT_FLAG, SET_RISK_LIMITS, WITHDRAW,
+    },
+};
+
+#[derive(Deserialize)]
+pub struct RiskLimitsRequest {
+    //left out to set the defaults
+    user_id: Option<String>,
+    limits: RiskLimits,
+}
+
+#[derive(Deserialize)]
+pub struct AccountFlagRequest {
+    user_id: String,
+    flag: AccountFlag,
+    enabled: bool,
+    reason: String,
+    //freezing only
+    #[serde(default)]
+    cancel_orders: bool,
+}
+
+#[derive(Deserialize)]
+pub struct WithdrawRequest {
+    user_id: String,
+    asset: String,
+    amount: String,
+}
+
+pub fn user_router(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::resource("/user/withdraw").route(web::post().to(withdraw)));
+}
+
+//mounted behind the admin guard
+pub fn user_admin_router(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::resource("/user/risk-limits").route(web::post().to(set_risk_limits)));
+    cfg.service(web::resource("/user/flags").route(web::post().to(set_account_flag)));
+}
+
+async fn set_risk_limits(data: web::Json<RiskLimitsRequest>) -> impl Responder {
+    let redis = RedisManager::get_instance();
+    let message = MessageToEngine {
+        type_: SET_RISK_LIMITS.to_string(),
+        data: serde_json::json!({
+            "userId": data.user_id,
+            "limits": data.limits
+        }),
+    };
+
+    match redis.send_and_await(message).await {
+        Ok(res) => HttpResponse::Ok().json(res),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+    }
+}
+
+async fn set_account_flag(data: web::Json<AccountFlagRequest>) -> impl Responder {
+    let redis = RedisManager::get_instance();
+    let message = MessageToEngine {
+        type_: SET_ACCOUNT_FLAG.to_string(),
+        data: serde_json::json!({
+            "userId": data.user_id,
+            "flag": data.flag,
+            "enabled": data.enabled,
+            "reason": data.reason,
+            "cancelOrders": data.cancel_orders
+        }),
+    };
+
+    match redis.send_and_await(message).await {
+        Ok(res) => HttpResponse::Ok().json(res),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+    }
+}
+
+async fn withdraw(data: web::Json<WithdrawRequest>) -> impl Responder {
+    let redis = RedisManager::get_instance();
+    let message = MessageToEngine {
+        type_: WITHDRAW.to_string(),
+        data: serde_json::json!({
+            "userId": data.user_id,
+            "asset": data.asset,
+            "amount": data.amount
+        }),
+    };
+
+    match redis.send_and_await(message).await {
+        Ok(res) => HttpResponse::Ok().json(res),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+    }
+}
@@ -1,3 +1,5 @@
+use std::collections::HashMap;
+
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
@@ -5,6 +7,16 @@ pub const CREATE_ORDER: &str = "CREATE_ORDER";
 pub const CANCEL_ORDER: &str = "CANCEL_ORDER";
 // pub const ON_RAMP: &str = "ON_RAMP";
 pub const GET_OPEN_ORDERS: &str = "GET_OPEN_ORDERS";
+pub const GET_ORDER: &str = "GET_ORDER";
+pub const AMEND_ORDER: &str = "AMEND_ORDER";
+pub const CANCEL_ALL: &str = "CANCEL_ALL";
+pub const BATCH_ORDERS: &str = "BATCH_ORDERS";
+pub const CREATE_ORDER_GROUP: &str = "CREATE_ORDER_GROUP";
+pub const AUCTION: &str = "AUCTION";
+pub const SET_MARKET_STATUS: &str = "SET_MARKET_STATUS";
+pub const SET_RISK_LIMITS: &str = "SET_RISK_LIMITS";
+pub const SET_ACCOUNT_FLAG: &str = "SET_ACCOUNT_FLAG";
+pub const WITHDRAW: &str = "WITHDRAW";
 // pub const GET_DEPTH: &str = "GET_DEPTH";
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -13,8 +25,9 @@ pub struct MessageToEngine {
     pub data: Value,
 }
 
+//tags as the engine writes them, ORDER_PLACED for OrderPlaced
 #[derive(Serialize, Deserialize, Debug)]
-#[serde(tag = "type", content = "payload")]
+#[serde(tag = "type", content = "payload", rename_all = "SCREAMING_SNAKE_CASE")]
 pub enum MessageFromOrderbook {
     Depth {
         market: String,
@@ -23,28 +36,106 @@ pub enum MessageFromOrderbook {
     },
     OrderPlaced {
         order_id: String,
-        executed_qty: f64,
+        executed_qty: String,
         fills: Vec<Fill>,
     },
     OrderCancelled {
         order_id: String,
-        executed_qty: f64,
-        remaining_qty: f64,
+        executed_qty: String,
+        remaining_qty: String,
     },
     OpenOrders {
         order_id: String,
-        executed_qty: f64,
+        executed_qty: String,
         price: String,
         quantity: String,
         side: OrderSide,
         user_id: String,
     },
+    Order(Box<OrderInfo>),
+    //the engine only knows open and recently closed orders, older ones are outside its window
+    OrderNotFound {
+        order_id: String,
+        reason: String,
+        code: String,
+    },
+    OrderAmended {
+        order_id: String,
+        price: String,
+        quantity: String,
+        executed_qty: String,
+        fills: Vec<Fill>,
+    },
+    OrderRejected {
+        order_id: String,
+        reason: String,
+        code: Option<String>,
+    },
+    OrdersCancelled {
+        order_ids: Vec<String>,
+        group_ids: Vec<String>,
+    },
+    BatchResults(Vec<MessageFromOrderbook>),
+    OrderGroupPlaced {
+        group_id: String,
+        order_ids: Vec<String>,
+        executed_qty: String,
+    },
+    AuctionResult {
+        market: String,
+        phase: TradingPhase,
+        price: Option<String>,
+        volume: String,
+    },
+    MarketStatus {
+        market: String,
+        status: MarketStatus,
+        order_ids: Vec<String>,
+    },
+    RiskLimits {
+        user_id: Option<String>,
+        limits: RiskLimits,
+    },
+    AccountStatus {
+        user_id: String,
+        status: AccountStatus,
+        order_ids: Vec<String>,
+    },
+    Withdrawal {
+        user_id: String,
+        asset: String,
+        amount: String,
+        available: String,
+    },
+    RequestRejected {
+        reason: String,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct AccountStatus {
+    pub frozen: bool,
+    pub cancel_only: bool,
+    pub reduce_only: bool,
+    pub reason: Option<String>,
+}
+
+//unset limits are not enforced
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct RiskLimits {
+    pub max_order_quantity: Option<String>,
+    pub max_order_notional: Option<String>,
+    pub max_open_orders: Option<usize>,
+    //per quote asset
+    #[serde(default)]
+    pub max_open_notional: HashMap<String, String>,
+    pub max_orders_per_second: Option<usize>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Fill {
     price: String,
-    qty: f64,
+    qty: String,
     trade_id: u64,
 }
 
@@ -55,6 +146,120 @@ pub enum OrderSide {
     Sell,
 }
 
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum OrderType {
+    #[default]
+    Limit,
+    Market,
+    StopMarket,
+    StopLimit,
+    TrailingStop,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum GroupType {
+    Oco,
+    Bracket,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum PegReference {
+    BestBid,
+    BestAsk,
+    Mid,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum TimeInForce {
+    #[default]
+    Gtc,
+    Gtd,
+    Day,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum TradingPhase {
+    Continuous,
+    OpeningAuction,
+    ClosingAuction,
+    VolatilityAuction,
+    Batch,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum AccountFlag {
+    Frozen,
+    CancelOnly,
+    ReduceOnly,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum MarketStatus {
+    PreOpen,
+    Open,
+    Halted,
+    CancelOnly,
+    Closed,
+    Delisted,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum AuctionAction {
+    StartOpening,
+    StartClosing,
+    Uncross,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum OrderStatus {
+    New,
+    PartiallyFilled,
+    Filled,
+    Cancelled,
+    Rejected,
+    Expired,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct OrderInfo {
+    order_id: String,
+    user_id: String,
+    client_order_id: Option<String>,
+    side: OrderSide,
+    #[serde(default)]
+    order_type: OrderType,
+    //current effective trigger for trailing stops
+    trigger_price: Option<String>,
+    trailing_offset: Option<String>,
+    trailing_percent: Option<String>,
+    watermark: Option<String>,
+    group_id: Option<String>,
+    display_quantity: Option<String>,
+    visible_quantity: Option<String>,
+    #[serde(default)]
+    time_in_force: TimeInForce,
+    expire_at: Option<u64>,
+    peg: Option<PegReference>,
+    peg_offset: Option<String>,
+    peg_cap: Option<String>,
+    price: String,
+    quantity: String,
+    filled: String,
+    status: OrderStatus,
+    avg_fill_price: String,
+    created_at: u64,
+    updated_at: u64,
+}
+
 // #[derive(Serialize, Deserialize, Debug)]
 // pub struct OpenOrder {
 //     order_id: String,
@@ -0,0 +1,140 @@
+mod flow;
+
+use criterion::{BatchSize, Criterion, criterion_group, criterion_main};
+use engine::{
+    models::order::{Order, OrderType, TimeInForce},
+    runner::Runner,
+    trade::engine::Engine,
+    transport::channel,
+    types::api::{CancelOrderDAta, CreateOrderData, MessageFromApi},
+};
+use rand::{Rng, SeedableRng, rngs::StdRng};
+
+use flow::{Flow, SEED, scale};
+
+const MESSAGES: usize = 1_000;
+
+fn create_order(order: &Order) -> MessageFromApi {
+    let scale = scale();
+    MessageFromApi::CreateOrder {
+        data: CreateOrderData {
+            market: "BTC_USDT".to_string(),
+            price: scale.price(order.price).to_string(),
+            quantity: scale.quantity(order.quantity).to_string(),
+            side: order.side.as_str().to_string(),
+            user_id: order.user_id.clone(),
+            client_order_id: Some(order.order_id.clone()),
+            order_type: OrderType::Limit,
+            trigger_price: None,
+            trailing_offset: None,
+            trailing_percent: None,
+            display_quantity: None,
+            time_in_force: TimeInForce::Gtc,
+            expire_at: None,
+            peg: None,
+            peg_offset: None,
+        },
+        client_id: "bench".to_string(),
+    }
+}
+
+fn cancel_order(order: &Order) -> MessageFromApi {
+    MessageFromApi::CancelOrder {
+        data: CancelOrderDAta {
+            order_id: None,
+            client_order_id: Some(order.order_id.clone()),
+            user_id: Some(order.user_id.clone()),
+            market: "BTC_USDT".to_string(),
+            group_id: None,
+        },
+        client_id: "bench".to_string(),
+    }
+}
+
+// mostly passive quotes, a third of them cancelled again and one in ten orders crossing a few
+// levels, the same sequence every run
+fn workload() -> Vec<MessageFromApi> {
+    let mut flow = Flow::new();
+    let mut rng = StdRng::seed_from_u64(SEED);
+    let mut resting: Vec<Order> = Vec::new();
+
+    (0..MESSAGES)
+        .map(|_| match rng.gen_range(0..10) {
+            0..=5 => {
+                let order = flow.passive();
+                let message = create_order(&order);
+                resting.push(order);
+                message
+            }
+            6..=8 if !resting.is_empty() => {
+                let order = resting.swap_remove(rng.gen_range(0..resting.len()));
+                cancel_order(&order)
+            }
+            _ => {
+                let side = flow.side();
+                let price = Flow::aggressive_price(&side, rng.gen_range(1..=5));
+                let quantity = flow.quantity();
+                create_order(&flow.order(side, price, quantity))
+            }
+        })
+        .collect()
+}
+
+//the synchronous core on its own
+fn apply(c: &mut Criterion) {
+    c.bench_function("engine/apply", |b| {
+        b.iter_batched(
+            || (Engine::with_seed(SEED), workload()),
+            |(mut engine, messages)| {
+                let events: usize = messages
+                    .into_iter()
+                    .map(|message| engine.apply(message).len())
+                    .sum();
+                (engine, events)
+            },
+            BatchSize::LargeInput,
+        )
+    });
+}
+
+//every command through the async shell, events go to an in memory channel instead of redis
+fn process(c: &mut Criterion) {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+
+    //sanity check that the workload reaches the sink
+    let (sink, mut events) = channel::sink();
+    let mut runner = Runner::new(Engine::with_seed(SEED), Box::new(sink));
+    runtime.block_on(async {
+        for message in workload() {
+            runner.process(message).await;
+        }
+    });
+    assert!(events.try_recv().is_ok());
+
+    c.bench_function("engine/process", |b| {
+        b.iter_batched(
+            || {
+                let (sink, events) = channel::sink();
+                (
+                    Runner::new(Engine::with_seed(SEED), Box::new(sink)),
+                    events,
+                    workload(),
+                )
+            },
+            |(mut runner, events, messages)| {
+                runtime.block_on(async {
+                    for message in messages {
+                        runner.process(message).await;
+                    }
+                });
+                (runner, events)
+            },
+            BatchSize::LargeInput,
+        )
+    });
+}
+
+criterion_group!(benches, apply, process);
+criterion_main!(benches);
@@ -0,0 +1,133 @@
+#![allow(dead_code)]
+
+use engine::{
+    models::{
+        fixed::{Lots, Scale, Ticks},
+        order::{Order, OrderSide},
+    },
+    trade::orderbook::Orderbook,
+};
+use rand::{Rng, SeedableRng, rngs::StdRng};
+use rust_decimal_macros::dec;
+
+//every workload starts from the same seed so results compare between commits
+pub const SEED: u64 = 47;
+
+//60000.00 on BTC_USDT
+pub const MID: Ticks = Ticks(6_000_000);
+
+pub const USERS: [&str; 3] = ["1", "2", "3"];
+
+pub fn scale() -> Scale {
+    Scale {
+        tick_size: dec!(0.01),
+        lot_size: dec!(0.00001),
+    }
+}
+
+pub fn empty_book() -> Orderbook {
+    Orderbook::new(
+        "BTC".to_string(),
+        "USDT".to_string(),
+        Vec::new(),
+        Vec::new(),
+        0,
+        MID,
+    )
+}
+
+// order flow around a fixed mid, passive orders cluster near the touch and thin out with distance,
+// sizes are mostly small with a long tail of large ones
+pub struct Flow {
+    rng: StdRng,
+    next_id: u64,
+}
+
+impl Flow {
+    pub fn new() -> Self {
+        Self {
+            rng: StdRng::seed_from_u64(SEED),
+            next_id: 0,
+        }
+    }
+
+    pub fn side(&mut self) -> OrderSide {
+        if self.rng.gen_bool(0.5) {
+            OrderSide::Buy
+        } else {
+            OrderSide::Sell
+        }
+    }
+
+    //exponential with the given mean, at least one
+    fn distance(&mut self, mean: f64) -> i64 {
+        let u: f64 = self.rng.r#gen();
+        1 + (-(1.0 - u).ln() * mean) as i64
+    }
+
+    //log uniform between 1 and 1000 lots
+    pub fn quantity(&mut self) -> Lots {
+        let exponent: f64 = self.rng.gen_range(0.0..3.0);
+        Lots(10f64.powf(exponent) as i64)
+    }
+
+    //resting price this many ticks away from the mid on the order's own side
+    pub fn passive_price(&mut self, side: &OrderSide) -> Ticks {
+        let distance = Ticks(self.distance(20.0));
+        match side {
+            OrderSide::Buy => MID - distance,
+            OrderSide::Sell => MID + distance,
+        }
+    }
+
+    //limit price reaching `levels` ticks into the other side
+    pub fn aggressive_price(side: &OrderSide, levels: i64) -> Ticks {
+        match side {
+            OrderSide::Buy => MID + Ticks(levels),
+            OrderSide::Sell => MID - Ticks(levels),
+        }
+    }
+
+    pub fn order(&mut self, side: OrderSide, price: Ticks, quantity: Lots) -> Order {
+        let id = self.next_id;
+        self.next_id += 1;
+        Order::new(
+            format!("o{}", id),
+            USERS[id as usize % USERS.len()].to_string(),
+            side,
+            price,
+            quantity,
+            id,
+        )
+    }
+
+    pub fn passive(&mut self) -> Order {
+        let side = self.side();
+        let price = self.passive_price(&side);
+        let quantity = self.quantity();
+        self.order(side, price, quantity)
+    }
+
+    // a book of `orders` passive orders, none of which cross
+    pub fn deep_book(&mut self, orders: usize) -> Orderbook {
+        let mut book = empty_book();
+        for _ in 0..orders {
+            let mut order = self.passive();
+            book.add_order(&mut order);
+        }
+        book
+    }
+
+    // `orders_per_level` asks on every one of the first `levels` ticks above the mid
+    pub fn ask_ladder(&mut self, levels: i64, orders_per_level: usize) -> Orderbook {
+        let mut book = empty_book();
+        for level in 1..=levels {
+            for _ in 0..orders_per_level {
+                let quantity = self.quantity();
+                let mut order = self.order(OrderSide::Sell, MID + Ticks(level), quantity);
+                book.add_order(&mut order);
+            }
+        }
+        book
+    }
+}
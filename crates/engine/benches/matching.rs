@@ -0,0 +1,72 @@
+use criterion::{BatchSize, Criterion, criterion_group, criterion_main};
+use engine::{
+    models::{
+        fixed::{Scale, Ticks},
+        order::{Order, OrderSide},
+    },
+    trade::orderbook::Orderbook,
+};
+use rand::{Rng, SeedableRng, rngs::StdRng};
+use rust_decimal::Decimal;
+use rust_decimal_macros::dec;
+
+const ORDERS: usize = 2_000;
+
+// limit orders of ten users around a mid of 60000.00, both sides cross often
+fn order_flow(scale: &Scale) -> Vec<Order> {
+    let mut rng = StdRng::seed_from_u64(46);
+    (0..ORDERS)
+        .map(|i| {
+            let side = if rng.gen_bool(0.5) {
+                OrderSide::Buy
+            } else {
+                OrderSide::Sell
+            };
+            let price = dec!(60000) + Decimal::from(rng.gen_range(-200..=200)) * scale.tick_size;
+            let quantity = Decimal::from(rng.gen_range(1..=500)) * scale.lot_size;
+            Order::new(
+                i.to_string(),
+                (i % 10).to_string(),
+                side.clone(),
+                scale.to_ticks(price, &side).unwrap(),
+                scale.to_lots(quantity).unwrap(),
+                0,
+            )
+        })
+        .collect()
+}
+
+// the whole book, matching policy, fills and all
+fn add_order(c: &mut Criterion) {
+    let scale = Scale {
+        tick_size: dec!(0.01),
+        lot_size: dec!(0.00001),
+    };
+    let orders = order_flow(&scale);
+
+    c.bench_function("orderbook/add_order", |b| {
+        b.iter_batched(
+            || {
+                let book = Orderbook::new(
+                    "BTC".to_string(),
+                    "USDT".to_string(),
+                    Vec::new(),
+                    Vec::new(),
+                    0,
+                    Ticks::ZERO,
+                );
+                (book, orders.clone())
+            },
+            |(mut book, orders)| {
+                for mut order in orders {
+                    book.add_order(&mut order);
+                }
+                book.bids.len() + book.asks.len()
+            },
+            BatchSize::LargeInput,
+        )
+    });
+}
+
+criterion_group!(benches, add_order);
+criterion_main!(benches);
@@ -0,0 +1,101 @@
+mod flow;
+
+use criterion::{BatchSize, BenchmarkId, Criterion, criterion_group, criterion_main};
+use engine::models::{fixed::Lots, order::OrderSide};
+
+use flow::{Flow, MID};
+
+const DEPTHS: [usize; 3] = [1_000, 10_000, 50_000];
+
+//resting orders added to books which already hold `depth` of them
+fn add_order(c: &mut Criterion) {
+    let mut group = c.benchmark_group("add_order");
+
+    for depth in DEPTHS {
+        let mut flow = Flow::new();
+        let book = flow.deep_book(depth);
+        let orders: Vec<_> = (0..100).map(|_| flow.passive()).collect();
+
+        group.bench_with_input(BenchmarkId::new("deep_book", depth), &depth, |b, _| {
+            b.iter_batched(
+                || (book.clone(), orders.clone()),
+                |(mut book, orders)| {
+                    for mut order in orders {
+                        book.add_order(&mut order);
+                    }
+                    book
+                },
+                BatchSize::LargeInput,
+            )
+        });
+    }
+
+    group.finish();
+}
+
+//one buy taking out every level of a ladder of asks
+fn sweep(c: &mut Criterion) {
+    let mut group = c.benchmark_group("sweep");
+
+    for levels in [10, 100, 1_000] {
+        let mut flow = Flow::new();
+        let book = flow.ask_ladder(levels, 5);
+        let quantity: Lots = book.asks.iter().map(|o| o.remaining()).sum();
+        let sweep = flow.order(
+            OrderSide::Buy,
+            Flow::aggressive_price(&OrderSide::Buy, levels),
+            quantity,
+        );
+
+        group.bench_with_input(BenchmarkId::new("levels", levels), &levels, |b, _| {
+            b.iter_batched(
+                || (book.clone(), sweep.clone()),
+                |(mut book, mut sweep)| book.add_order(&mut sweep),
+                BatchSize::LargeInput,
+            )
+        });
+    }
+
+    group.finish();
+}
+
+//market makers requoting, nine in ten orders are cancelled before anything trades against them
+fn cancel_heavy(c: &mut Criterion) {
+    let mut flow = Flow::new();
+    let book = flow.deep_book(10_000);
+    let orders: Vec<_> = (0..1_000).map(|_| flow.passive()).collect();
+
+    c.bench_function("cancel_heavy", |b| {
+        b.iter_batched(
+            || (book.clone(), orders.clone()),
+            |(mut book, orders)| {
+                for (i, mut order) in orders.into_iter().enumerate() {
+                    book.add_order(&mut order);
+                    if i % 10 != 0 {
+                        book.remove_order(&order.order_id);
+                    }
+                }
+                book
+            },
+            BatchSize::LargeInput,
+        )
+    });
+}
+
+fn get_depth(c: &mut Criterion) {
+    let mut group = c.benchmark_group("get_depth");
+
+    for depth in DEPTHS {
+        let book = Flow::new().deep_book(depth);
+        assert!(book.best_bid().is_some_and(|bid| bid < MID));
+
+        group.bench_with_input(BenchmarkId::new("orders", depth), &depth, |b, _| {
+            b.iter(|| book.get_depth())
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, add_order, sweep, cancel_heavy, get_depth);
+criterion_main!(benches);
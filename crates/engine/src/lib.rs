@@ -0,0 +1,6 @@
+pub mod models;
+pub mod redis_manager;
+pub mod runner;
+pub mod trade;
+pub mod transport;
+pub mod types;
@@ -1,38 +1,88 @@
-use redis::AsyncCommands;
-use serde_json;
-use std::time::Duration;
-use tokio::time;
-use trade::engine::Engine;
+use engine::{
+    redis_manager::redis_manager::{RedisManager, RedisSource},
+    runner::Runner,
+    trade::engine::Engine,
+    transport::{
+        CommandSource, EventSink,
+        dispatcher::{Dispatcher, DispatcherConfig},
+    },
+    types::api::{MessageFromApi, TickData},
+};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::time::{self, Instant};
 
-mod models;
-mod redis_manager;
-mod trade;
-mod types;
+//how often the engine clock is advanced to expire gtd and day orders and close batches,
+//batch markets can't clear more often than this
+const TICK_INTERVAL: Duration = Duration::from_millis(100);
+
+//overridden by REDIS_URL
+const DEFAULT_REDIS_URL: &str = "redis://127.0.0.1/";
+
+//redis connections the outbound events are spread over, one dispatcher worker each
+const DISPATCH_CONNECTIONS: usize = 4;
+
+//how often the dispatcher's counters are logged
+const METRICS_INTERVAL: Duration = Duration::from_secs(10);
+
+//pause before polling again once the queue is empty, busy queues are drained back to back
+const IDLE_WAIT: Duration = Duration::from_millis(10);
 
 #[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
+async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     env_logger::init();
 
     log::info!("Strting trading engine");
-    let mut engine = Engine::new();
-    let redis_client = redis::Client::open("redis://127.0.0.1/")?;
-    let mut redis_conn = redis_client.get_async_connection().await?;
+    let redis_url = std::env::var("REDIS_URL").unwrap_or_else(|_| DEFAULT_REDIS_URL.to_string());
+    let mut sinks: Vec<Box<dyn EventSink>> = Vec::with_capacity(DISPATCH_CONNECTIONS);
+    for _ in 0..DISPATCH_CONNECTIONS {
+        sinks.push(Box::new(RedisManager::connect(&redis_url).await?));
+    }
+    let dispatcher = Dispatcher::spawn(sinks, DispatcherConfig::default());
+    let metrics = dispatcher.metrics();
+    let mut runner = Runner::new(Engine::new(), Box::new(dispatcher));
+    let mut source = RedisSource::connect(&redis_url).await?;
 
     log::info!("Connected to Redis");
 
+    let mut next_tick = Instant::now();
+    let mut next_report = Instant::now() + METRICS_INTERVAL;
+
     loop {
-        let response: Option<String> = redis_conn.rpop("messages", None).await?; //as we rpop then its get removed from queue as our server get down then how do we last unprocessed data
-        if let Some(message) = response {
-            match serde_json::from_str(&message) {
-                Ok(parsed) => {
-                    engine.process(parsed);
-                }
-                Err(e) => {
-                    log::error!("failed to parse message: {}", e);
-                }
-            }
+        let message = source.next().await?;
+        let idle = message.is_none();
+        if let Some(message) = message {
+            runner.process(message).await;
         }
 
-        time::sleep(Duration::from_millis(10)).await;
+        //expiry is driven by tick commands rather than the engine reading the clock itself
+        if Instant::now() >= next_tick {
+            let timestamp = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis() as u64;
+            runner
+                .process(MessageFromApi::Tick {
+                    data: TickData { timestamp },
+                })
+                .await;
+            next_tick += TICK_INTERVAL;
+        }
+
+        if Instant::now() >= next_report {
+            let metrics = metrics.snapshot();
+            log::info!(
+                "Dispatched {} events in {} batches, {} pending, {} failed, {} waited on a full queue",
+                metrics.dispatched,
+                metrics.batches,
+                metrics.pending(),
+                metrics.failed,
+                metrics.blocked
+            );
+            next_report += METRICS_INTERVAL;
+        }
+
+        if idle {
+            time::sleep(IDLE_WAIT).await;
+        }
     }
 }
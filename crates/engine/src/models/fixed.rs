@@ -0,0 +1,270 @@
+use std::{
+    iter::Sum,
+    ops::{Add, AddAssign, Neg, Sub, SubAssign},
+};
+
+use rust_decimal::{
+    Decimal, RoundingStrategy,
+    prelude::{FromPrimitive, ToPrimitive},
+};
+use serde::{Deserialize, Serialize};
+
+use super::order::OrderSide;
+
+//price as a whole number of the market's tick size
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+#[serde(transparent)]
+pub struct Ticks(pub i64);
+
+//quantity as a whole number of the market's lot size, negative only for differences
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+#[serde(transparent)]
+pub struct Lots(pub i64);
+
+impl Ticks {
+    pub const ZERO: Ticks = Ticks(0);
+
+    pub fn is_zero(&self) -> bool {
+        self.0 == 0
+    }
+}
+
+impl Lots {
+    pub const ZERO: Lots = Lots(0);
+
+    pub fn is_zero(&self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn abs(&self) -> Lots {
+        Lots(self.0.abs())
+    }
+}
+
+macro_rules! integer_ops {
+    ($unit:ident) => {
+        impl Add for $unit {
+            type Output = $unit;
+
+            fn add(self, other: $unit) -> $unit {
+                $unit(self.0 + other.0)
+            }
+        }
+
+        impl Sub for $unit {
+            type Output = $unit;
+
+            fn sub(self, other: $unit) -> $unit {
+                $unit(self.0 - other.0)
+            }
+        }
+
+        impl Neg for $unit {
+            type Output = $unit;
+
+            fn neg(self) -> $unit {
+                $unit(-self.0)
+            }
+        }
+
+        impl AddAssign for $unit {
+            fn add_assign(&mut self, other: $unit) {
+                self.0 += other.0;
+            }
+        }
+
+        impl SubAssign for $unit {
+            fn sub_assign(&mut self, other: $unit) {
+                self.0 -= other.0;
+            }
+        }
+
+        impl Sum for $unit {
+            fn sum<I: Iterator<Item = $unit>>(iter: I) -> $unit {
+                $unit(iter.map(|unit| unit.0).sum())
+            }
+        }
+
+        impl<'a> Sum<&'a $unit> for $unit {
+            fn sum<I: Iterator<Item = &'a $unit>>(iter: I) -> $unit {
+                $unit(iter.map(|unit| unit.0).sum())
+            }
+        }
+    };
+}
+
+integer_ops!(Ticks);
+integer_ops!(Lots);
+
+// tick and lot size of a market, the only place prices and quantities turn into decimals
+// incoming prices round away from the other side, buys down and sells up, so nothing trades
+// through its limit, and incoming quantities round down so nobody commits more than they asked
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Scale {
+    pub tick_size: Decimal,
+    pub lot_size: Decimal,
+}
+
+impl Default for Scale {
+    //cents for both, markets normally set their own
+    fn default() -> Self {
+        Self {
+            tick_size: Decimal::new(1, 2),
+            lot_size: Decimal::new(1, 2),
+        }
+    }
+}
+
+//largest count of ticks or lots a price or quantity may convert to, which keeps any price times
+//quantity well inside what a decimal holds
+const MAX_UNITS: i64 = 1 << 47;
+
+fn whole(value: Decimal, strategy: RoundingStrategy) -> Option<i64> {
+    value
+        .round_dp_with_strategy(0, strategy)
+        .to_i64()
+        .filter(|units| units.abs() <= MAX_UNITS)
+}
+
+impl Scale {
+    pub fn is_valid(&self) -> bool {
+        self.tick_size > Decimal::ZERO && self.lot_size > Decimal::ZERO
+    }
+
+    //limit prices, rounded so the order never gets a worse price than it asked for
+    pub fn to_ticks(
+        &self,
+        price: Decimal,
+        side: &OrderSide,
+    ) -> Result<Ticks, Box<dyn std::error::Error>> {
+        let strategy = match side {
+            OrderSide::Buy => RoundingStrategy::ToNegativeInfinity,
+            OrderSide::Sell => RoundingStrategy::ToPositiveInfinity,
+        };
+        price
+            .checked_div(self.tick_size)
+            .and_then(|ticks| whole(ticks, strategy))
+            .map(Ticks)
+            .ok_or_else(|| "Invalid price".into())
+    }
+
+    //triggers and offsets, to the nearest tick
+    pub fn nearest_ticks(&self, price: Decimal) -> Result<Ticks, Box<dyn std::error::Error>> {
+        price
+            .checked_div(self.tick_size)
+            .and_then(|ticks| whole(ticks, RoundingStrategy::MidpointAwayFromZero))
+            .map(Ticks)
+            .ok_or_else(|| "Invalid price".into())
+    }
+
+    pub fn to_lots(&self, quantity: Decimal) -> Result<Lots, Box<dyn std::error::Error>> {
+        quantity
+            .checked_div(self.lot_size)
+            .and_then(|lots| whole(lots, RoundingStrategy::ToZero))
+            .map(Lots)
+            .ok_or_else(|| "Invalid quantity".into())
+    }
+
+    pub fn price(&self, ticks: Ticks) -> Decimal {
+        Decimal::from(ticks.0) * self.tick_size
+    }
+
+    pub fn quantity(&self, lots: Lots) -> Decimal {
+        Decimal::from(lots.0) * self.lot_size
+    }
+
+    //exact quote value of a quantity at a price, None when it does not fit a decimal
+    pub fn checked_notional(&self, price: Ticks, quantity: Lots) -> Option<Decimal> {
+        Decimal::from_i128(price.0 as i128 * quantity.0 as i128)?
+            .checked_mul(self.tick_size)?
+            .checked_mul(self.lot_size)
+    }
+
+    //for orders already accepted, whose notional was checked when they came in
+    pub fn notional(&self, price: Ticks, quantity: Lots) -> Decimal {
+        self.checked_notional(price, quantity)
+            .unwrap_or(Decimal::MAX)
+    }
+
+    //average price of fills worth `value` ticks times lots over `quantity`
+    pub fn average_price(&self, value: i128, quantity: Lots) -> Decimal {
+        if quantity.is_zero() {
+            return Decimal::ZERO;
+        }
+        Decimal::from(value) / Decimal::from(quantity.0) * self.tick_size
+    }
+}
+
+// a percentage of a price in whole ticks, rounded down
+//trailing percents are below a hundred, so this never ends up larger than the price
+pub fn percent_of(price: Ticks, percent: Decimal) -> Ticks {
+    Decimal::from(price.0)
+        .checked_mul(percent)
+        .and_then(|ticks| whole(ticks, RoundingStrategy::ToZero))
+        .map_or(Ticks::ZERO, Ticks)
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal_macros::dec;
+
+    use super::*;
+
+    fn scale() -> Scale {
+        Scale {
+            tick_size: dec!(0.05),
+            lot_size: dec!(0.001),
+        }
+    }
+
+    #[test]
+    fn prices_round_away_from_the_other_side() {
+        let scale = scale();
+        assert_eq!(
+            scale.to_ticks(dec!(100.07), &OrderSide::Buy).unwrap(),
+            Ticks(2001)
+        );
+        assert_eq!(
+            scale.to_ticks(dec!(100.07), &OrderSide::Sell).unwrap(),
+            Ticks(2002)
+        );
+        assert_eq!(
+            scale.to_ticks(dec!(100.05), &OrderSide::Sell).unwrap(),
+            Ticks(2001)
+        );
+    }
+
+    #[test]
+    fn quantities_round_down_to_whole_lots() {
+        let scale = scale();
+        assert_eq!(scale.to_lots(dec!(1.2345)).unwrap(), Lots(1234));
+        assert_eq!(scale.to_lots(dec!(0.0009)).unwrap(), Lots::ZERO);
+    }
+
+    #[test]
+    fn conversions_round_trip() {
+        let scale = scale();
+        assert_eq!(scale.price(Ticks(2001)), dec!(100.05));
+        assert_eq!(scale.quantity(Lots(1234)), dec!(1.234));
+        assert_eq!(scale.notional(Ticks(2001), Lots(1234)), dec!(123.4617));
+        assert_eq!(scale.average_price(2001 * 3 + 2003, Lots(4)), dec!(100.075));
+    }
+
+    #[test]
+    fn values_too_large_to_hold_are_refused() {
+        let scale = scale();
+        assert!(scale.to_ticks(dec!(1e15), &OrderSide::Buy).is_err());
+        assert!(scale.nearest_ticks(dec!(1e15)).is_err());
+        assert!(scale.to_lots(dec!(1e15)).is_err());
+        assert!(scale.to_lots(Decimal::MAX).is_err());
+        assert!(
+            scale
+                .checked_notional(Ticks(i64::MAX), Lots(i64::MAX))
+                .is_none()
+        );
+    }
+}
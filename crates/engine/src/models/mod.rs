@@ -1,3 +1,4 @@
 pub mod balance;
+pub mod fixed;
 pub mod message;
 pub mod order;
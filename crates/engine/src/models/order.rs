@@ -1,14 +1,142 @@
 use rust_decimal::Decimal;
 use serde::{Deserialize, Serialize};
 
+use super::fixed::{Lots, Ticks};
+
+// prices and quantities are whole ticks and lots of the order's market, see `Scale`
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Order {
-    pub price: Decimal,
-    pub quantity: Decimal,
+    pub price: Ticks,
+    pub quantity: Lots,
     pub order_id: String,
-    pub filled: Decimal,
+    pub filled: Lots,
     pub side: OrderSide,
     pub user_id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_order_id: Option<String>,
+    #[serde(default)]
+    pub order_type: OrderType,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trigger_price: Option<Ticks>,
+    //trailing stops keep their trigger this far from the watermark, either as a price or a percentage
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trailing_offset: Option<Ticks>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trailing_percent: Option<Decimal>,
+    //best price seen since placement, the high for sell stops and the low for buy stops
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub watermark: Option<Ticks>,
+    //oco or bracket group the order belongs to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group_id: Option<String>,
+    //iceberg orders only show this much of their size at a time
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub display_quantity: Option<Lots>,
+    //what is left of the current iceberg slice
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub visible_quantity: Option<Lots>,
+    #[serde(default)]
+    pub time_in_force: TimeInForce,
+    //engine time in millis at which a gtd or day order is expired
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expire_at: Option<u64>,
+    //pegged orders follow a reference price instead of keeping a fixed one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub peg: Option<PegReference>,
+    //added to the reference price, negative to sit behind it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub peg_offset: Option<Ticks>,
+    //pegged buys never go above and pegged sells never go below this price
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub peg_cap: Option<Ticks>,
+    pub status: OrderStatus,
+    //sum of price times quantity over every fill, the average fill price is this over `filled`
+    #[serde(default)]
+    pub fill_value: i128,
+    pub created_at: u64,
+    pub updated_at: u64,
+}
+
+impl Order {
+    pub fn new(
+        order_id: String,
+        user_id: String,
+        side: OrderSide,
+        price: Ticks,
+        quantity: Lots,
+        timestamp: u64,
+    ) -> Self {
+        Self {
+            price,
+            quantity,
+            order_id,
+            filled: Lots::ZERO,
+            side,
+            user_id,
+            client_order_id: None,
+            order_type: OrderType::Limit,
+            trigger_price: None,
+            trailing_offset: None,
+            trailing_percent: None,
+            watermark: None,
+            group_id: None,
+            display_quantity: None,
+            visible_quantity: None,
+            time_in_force: TimeInForce::Gtc,
+            expire_at: None,
+            peg: None,
+            peg_offset: None,
+            peg_cap: None,
+            status: OrderStatus::New,
+            fill_value: 0,
+            created_at: timestamp,
+            updated_at: timestamp,
+        }
+    }
+
+    pub fn remaining(&self) -> Lots {
+        self.quantity - self.filled
+    }
+
+    //quantity shown in the book, the current slice for icebergs
+    pub fn visible(&self) -> Lots {
+        match self.visible_quantity {
+            Some(visible) => visible.min(self.remaining()),
+            None => self.remaining(),
+        }
+    }
+
+    //midpoint pegs are non-displayed liquidity
+    pub fn is_hidden(&self) -> bool {
+        self.peg == Some(PegReference::Mid)
+    }
+
+    //starts a new iceberg slice from the hidden reserve
+    pub fn refresh_slice(&mut self) {
+        if let Some(display_quantity) = self.display_quantity {
+            self.visible_quantity = Some(display_quantity.min(self.remaining()));
+        }
+    }
+
+    //records an execution against this order and moves it along the lifecycle
+    pub fn fill(&mut self, qty: Lots, price: Ticks, timestamp: u64) {
+        self.fill_value += price.0 as i128 * qty.0 as i128;
+        self.filled += qty;
+        if let Some(visible) = self.visible_quantity {
+            self.visible_quantity = Some((visible - qty).max(Lots::ZERO));
+        }
+        self.status = if self.filled >= self.quantity {
+            OrderStatus::Filled
+        } else {
+            OrderStatus::PartiallyFilled
+        };
+        self.updated_at = timestamp;
+    }
+
+    pub fn close(&mut self, status: OrderStatus, timestamp: u64) {
+        self.status = status;
+        self.updated_at = timestamp;
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
@@ -19,11 +147,98 @@ pub enum OrderSide {
     Sell,
 }
 
+impl OrderSide {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OrderSide::Buy => "buy",
+            OrderSide::Sell => "sell",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum OrderType {
+    #[default]
+    Limit,
+    //immediate or cancel, bounded by a protection price instead of a limit
+    Market,
+    //becomes a market order once the trigger price trades
+    StopMarket,
+    //becomes a limit order once the trigger price trades
+    StopLimit,
+    //stop market whose trigger follows the price by a fixed offset or percentage
+    TrailingStop,
+}
+
+impl OrderType {
+    pub fn is_conditional(&self) -> bool {
+        matches!(
+            self,
+            OrderType::StopMarket | OrderType::StopLimit | OrderType::TrailingStop
+        )
+    }
+
+    //immediate or cancel once it reaches the book
+    pub fn is_market(&self) -> bool {
+        matches!(
+            self,
+            OrderType::Market | OrderType::StopMarket | OrderType::TrailingStop
+        )
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PegReference {
+    BestBid,
+    BestAsk,
+    Mid,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum TimeInForce {
+    //good till cancelled
+    #[default]
+    Gtc,
+    //good till the order's expire_at
+    Gtd,
+    //expires at the end of the market's day session
+    Day,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum OrderStatus {
+    New,
+    PartiallyFilled,
+    Filled,
+    Cancelled,
+    Rejected,
+    Expired,
+}
+
+impl OrderStatus {
+    //terminal orders never return to the book
+    pub fn is_terminal(&self) -> bool {
+        matches!(
+            self,
+            OrderStatus::Filled
+                | OrderStatus::Cancelled
+                | OrderStatus::Rejected
+                | OrderStatus::Expired
+        )
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Fill {
-    pub price: String,
-    pub qty: Decimal,
+    pub price: Ticks,
+    pub qty: Lots,
     pub trade_id: u64,
     pub other_user_id: String,
     pub marker_order_id: String,
+    //what the maker has left after this fill, settles the lock of a resting buy
+    pub maker_remaining: Lots,
 }
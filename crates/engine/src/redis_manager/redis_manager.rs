@@ -1,57 +1,102 @@
-use std::sync::Arc;
+use futures::{FutureExt, future::BoxFuture};
+use log::error;
+use redis::{
+    AsyncCommands, Client, Pipeline,
+    aio::{Connection, MultiplexedConnection},
+};
 
-use redis::{AsyncCommands, Client};
-// use tokio::sync::Mutex;
+use crate::{
+    transport::{CommandSource, DB_QUEUE, Event, EventSink, TransportResult},
+    types::api::MessageFromApi,
+};
 
-use crate::types::{api::MessageToApi, db::DbMessage, ws::WsMessage};
+//queue the api server pushes commands onto
+const COMMAND_QUEUE: &str = "messages";
 
+// one multiplexed connection shared by every command, cloning it is cheap and doesn't reconnect
 pub struct RedisManager {
-    client: Client,
+    conn: MultiplexedConnection,
 }
 
 impl RedisManager {
-    pub fn new() -> Self {
-        let client = Client::open("redis://127.0.0.1/").expect("failed while connecting to redis");
-        Self { client }
+    pub async fn connect(url: &str) -> redis::RedisResult<Self> {
+        let conn = Client::open(url)?
+            .get_multiplexed_tokio_connection()
+            .await?;
+        Ok(Self { conn })
     }
 
-    // static mut INSTANCE: Option<Arc<Mutex<RedisManager>>> = None;
+    fn queue(pipe: &mut Pipeline, event: Event) -> serde_json::Result<()> {
+        match event {
+            //publishing message to database queue
+            Event::Db(message) => {
+                pipe.lpush(DB_QUEUE, serde_json::to_string(&message)?);
+            }
+            //publishing message for websocket queue
+            Event::Ws { channel, message } => {
+                pipe.lpush(channel, serde_json::to_string(&message)?);
+            }
+            //publishing message to api server waiting for response
+            Event::Api { client_id, message } => {
+                pipe.publish(client_id, serde_json::to_string(&message)?);
+            }
+        }
+        pipe.ignore();
+        Ok(())
+    }
+}
 
-    pub fn get_instance() -> Arc<RedisManager> {
-        static INSTANCE: once_cell::sync::Lazy<Arc<RedisManager>> =
-            once_cell::sync::Lazy::new(|| Arc::new(RedisManager::new()));
-        INSTANCE.clone()
+impl EventSink for RedisManager {
+    fn send(&self, event: Event) -> BoxFuture<'_, TransportResult<()>> {
+        self.send_batch(vec![event])
     }
 
-    //publishing message to database queue
-    pub async fn push_message(&self, message: DbMessage) -> redis::RedisResult<()> {
-        let mut conn = self.client.get_async_connection().await?;
-        let serialized = serde_json::to_string(&message).expect("Failed to serialized DB message");
+    //the whole batch goes out in one round trip
+    fn send_batch(&self, events: Vec<Event>) -> BoxFuture<'_, TransportResult<()>> {
+        async move {
+            let mut pipe = redis::pipe();
+            for event in events {
+                Self::queue(&mut pipe, event)?;
+            }
 
-        conn.lpush("db_processor", serialized).await
+            let mut conn = self.conn.clone();
+            pipe.query_async::<_, ()>(&mut conn).await?;
+            Ok(())
+        }
+        .boxed()
     }
+}
 
-    //publishing message for websocket queue
-    pub async fn publish_message(
-        &self,
-        channel: &str,
-        message: WsMessage,
-    ) -> redis::RedisResult<()> {
-        let mut conn = self.client.get_async_connection().await?;
-        let serialized = serde_json::to_string(&message).expect("Failed to serialized WS message");
+// pops commands off the api server's queue
+pub struct RedisSource {
+    conn: Connection,
+}
 
-        conn.lpush(channel, serialized).await
+impl RedisSource {
+    pub async fn connect(url: &str) -> redis::RedisResult<Self> {
+        let conn = Client::open(url)?.get_async_connection().await?;
+        Ok(Self { conn })
     }
+}
 
-    //publishing message to api server waiting for response
-    pub async fn send_to_api(
-        &self,
-        client_id: &str,
-        message: MessageToApi,
-    ) -> redis::RedisResult<()> {
-        let mut conn = self.client.get_async_connection().await?;
-        let serialized = serde_json::to_string(&message).expect("failed to serialized api message");
+impl CommandSource for RedisSource {
+    fn next(&mut self) -> BoxFuture<'_, TransportResult<Option<MessageFromApi>>> {
+        async move {
+            //as we rpop then its get removed from queue as our server get down then how do we last unprocessed data
+            let response: Option<String> = self.conn.rpop(COMMAND_QUEUE, None).await?;
+            let Some(message) = response else {
+                return Ok(None);
+            };
 
-        conn.publish(client_id, serialized).await
+            //a malformed command is dropped, the next one is still processed
+            match serde_json::from_str(&message) {
+                Ok(parsed) => Ok(Some(parsed)),
+                Err(e) => {
+                    error!("failed to parse message: {}", e);
+                    Ok(None)
+                }
+            }
+        }
+        .boxed()
     }
 }
@@ -0,0 +1,24 @@
+use log::error;
+
+use crate::{trade::engine::Engine, transport::EventSink, types::api::MessageFromApi};
+
+// the async shell around the engine, applies each command and sends its events out afterwards so
+// none of the io happens in the middle of matching
+pub struct Runner {
+    engine: Engine,
+    sink: Box<dyn EventSink>,
+}
+
+impl Runner {
+    pub fn new(engine: Engine, sink: Box<dyn EventSink>) -> Self {
+        Self { engine, sink }
+    }
+
+    pub async fn process(&mut self, message: MessageFromApi) {
+        for event in self.engine.apply(message) {
+            if let Err(e) = self.sink.send(event).await {
+                error!("Failed to send event: {}", e);
+            }
+        }
+    }
+}
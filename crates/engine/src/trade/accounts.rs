@@ -0,0 +1,93 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::models::order::OrderSide;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AccountFlag {
+    //no trading at all, cancels included
+    Frozen,
+    CancelOnly,
+    //only orders which reduce holdings, sells on a spot exchange
+    ReduceOnly,
+}
+
+// compliance flags of a user, set and cleared by admins without touching balances
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct AccountStatus {
+    pub frozen: bool,
+    pub cancel_only: bool,
+    pub reduce_only: bool,
+    //given with the last change
+    pub reason: Option<String>,
+}
+
+impl AccountStatus {
+    pub fn set(&mut self, flag: AccountFlag, enabled: bool) {
+        match flag {
+            AccountFlag::Frozen => self.frozen = enabled,
+            AccountFlag::CancelOnly => self.cancel_only = enabled,
+            AccountFlag::ReduceOnly => self.reduce_only = enabled,
+        }
+    }
+
+    //new orders and amends
+    pub fn check_order(&self, side: &OrderSide) -> Result<(), AccountRejection> {
+        if self.frozen {
+            Err(AccountRejection::Frozen)
+        } else if self.cancel_only {
+            Err(AccountRejection::CancelOnly)
+        } else if self.reduce_only && *side == OrderSide::Buy {
+            Err(AccountRejection::ReduceOnly)
+        } else {
+            Ok(())
+        }
+    }
+
+    pub fn check_cancel(&self) -> Result<(), AccountRejection> {
+        if self.frozen {
+            return Err(AccountRejection::Frozen);
+        }
+        Ok(())
+    }
+
+    //cancel-only and reduce-only accounts can still take their funds out
+    pub fn check_withdrawal(&self) -> Result<(), AccountRejection> {
+        if self.frozen {
+            return Err(AccountRejection::Frozen);
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AccountRejection {
+    Frozen,
+    CancelOnly,
+    ReduceOnly,
+}
+
+impl AccountRejection {
+    pub fn code(&self) -> &'static str {
+        match self {
+            AccountRejection::Frozen => "ACCOUNT_FROZEN",
+            AccountRejection::CancelOnly => "ACCOUNT_CANCEL_ONLY",
+            AccountRejection::ReduceOnly => "ACCOUNT_REDUCE_ONLY",
+        }
+    }
+}
+
+impl fmt::Display for AccountRejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let reason = match self {
+            AccountRejection::Frozen => "Account is frozen",
+            AccountRejection::CancelOnly => "Account can only cancel orders",
+            AccountRejection::ReduceOnly => "Account can only reduce its holdings",
+        };
+        write!(f, "{}", reason)
+    }
+}
+
+impl std::error::Error for AccountRejection {}
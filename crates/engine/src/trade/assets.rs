@@ -0,0 +1,87 @@
+use std::collections::HashMap;
+
+use rust_decimal::{Decimal, RoundingStrategy};
+use serde::{Deserialize, Serialize};
+
+//asset credited by on ramps which don't name one
+pub const BASE_CURRENCY: &str = "INR";
+
+// every balance is kept at its asset's precision, the rounding rules are
+// 1. quantities and transfers coming in are cut down, nobody commits more than they asked for
+// 2. limit prices round to the market's tick size, see `Scale`
+// 3. quote locked for a buy rounds up so the lock always covers the fills
+// 4. trade values round down and the same amount moves on both sides, fees would take the same
+//    rule
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Asset {
+    pub symbol: String,
+    //smallest unit is 10^-decimals
+    pub decimals: u32,
+    //decimals shown to users, at most `decimals`
+    pub display_precision: u32,
+    pub min_withdrawal: Decimal,
+}
+
+impl Asset {
+    pub fn normalize(&self, amount: Decimal) -> Decimal {
+        amount.round_dp_with_strategy(self.decimals, RoundingStrategy::ToZero)
+    }
+
+    pub fn round_up(&self, amount: Decimal) -> Decimal {
+        amount.round_dp_with_strategy(self.decimals, RoundingStrategy::AwayFromZero)
+    }
+
+    pub fn display(&self, amount: Decimal) -> String {
+        amount
+            .round_dp_with_strategy(self.display_precision, RoundingStrategy::ToZero)
+            .to_string()
+    }
+}
+
+// every asset the engine knows about, markets can only be opened between registered assets
+#[derive(Clone, Debug, Default)]
+pub struct AssetRegistry {
+    assets: HashMap<String, Asset>,
+}
+
+impl AssetRegistry {
+    pub fn register(&mut self, asset: Asset) {
+        self.assets.insert(asset.symbol.clone(), asset);
+    }
+
+    pub fn get(&self, symbol: &str) -> Option<&Asset> {
+        self.assets.get(symbol)
+    }
+
+    pub fn contains(&self, symbol: &str) -> bool {
+        self.assets.contains_key(symbol)
+    }
+
+    pub fn symbols(&self) -> impl Iterator<Item = &str> {
+        self.assets.keys().map(String::as_str)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal_macros::dec;
+
+    use super::*;
+
+    fn usdt() -> Asset {
+        Asset {
+            symbol: "USDT".to_string(),
+            decimals: 6,
+            display_precision: 2,
+            min_withdrawal: dec!(10),
+        }
+    }
+
+    #[test]
+    fn locks_round_up_and_values_round_down() {
+        let usdt = usdt();
+        assert_eq!(usdt.round_up(dec!(0.0000001)), dec!(0.000001));
+        assert_eq!(usdt.normalize(dec!(0.0000019)), dec!(0.000001));
+        assert_eq!(usdt.display(dec!(12.345678)), "12.34");
+    }
+}
@@ -0,0 +1,117 @@
+use serde::{Deserialize, Serialize};
+
+use crate::models::{
+    fixed::{Lots, Ticks},
+    order::Order,
+};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TradingPhase {
+    #[default]
+    Continuous,
+    //orders are collected without matching until the book is uncrossed
+    OpeningAuction,
+    ClosingAuction,
+    //entered when the circuit breaker trips, back to continuous after the uncross
+    VolatilityAuction,
+    //frequent batch auctions, the book is uncrossed on every batch boundary and never trades
+    //continuously
+    Batch,
+}
+
+impl TradingPhase {
+    pub fn is_auction(&self) -> bool {
+        matches!(
+            self,
+            TradingPhase::OpeningAuction
+                | TradingPhase::ClosingAuction
+                | TradingPhase::VolatilityAuction
+                | TradingPhase::Batch
+        )
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuctionAction {
+    StartOpening,
+    StartClosing,
+    //executes the auction at its clearing price and moves to the next phase
+    Uncross,
+}
+
+//price the auction would clear at right now and the volume it would execute
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Indicative {
+    pub price: Ticks,
+    pub volume: Lots,
+}
+
+// single price maximizing executed volume, ties broken by
+// 1. the smallest surplus left on either side
+// 2. market pressure, the highest price when buyers are left over and the lowest when sellers are
+// 3. closest to the reference price
+// 4. the lowest price
+pub fn clearing_price(bids: &[Order], asks: &[Order], reference: Ticks) -> Option<Indicative> {
+    let mut prices: Vec<Ticks> = bids.iter().chain(asks).map(|o| o.price).collect();
+    prices.sort();
+    prices.dedup();
+
+    //(price, volume, surplus), surplus positive when buyers are left over
+    let candidates: Vec<(Ticks, Lots, Lots)> = prices
+        .into_iter()
+        .map(|price| {
+            let demand: Lots = bids
+                .iter()
+                .filter(|o| o.price >= price)
+                .map(Order::remaining)
+                .sum();
+            let supply: Lots = asks
+                .iter()
+                .filter(|o| o.price <= price)
+                .map(Order::remaining)
+                .sum();
+            (price, demand.min(supply), demand - supply)
+        })
+        .filter(|(_, volume, _)| *volume > Lots::ZERO)
+        .collect();
+
+    let max_volume = candidates.iter().map(|(_, volume, _)| *volume).max()?;
+    let candidates: Vec<_> = candidates
+        .into_iter()
+        .filter(|(_, volume, _)| *volume == max_volume)
+        .collect();
+
+    let min_surplus = candidates
+        .iter()
+        .map(|(_, _, surplus)| surplus.abs())
+        .min()?;
+    let candidates: Vec<_> = candidates
+        .into_iter()
+        .filter(|(_, _, surplus)| surplus.abs() == min_surplus)
+        .collect();
+
+    let price = if candidates
+        .iter()
+        .all(|(_, _, surplus)| *surplus > Lots::ZERO)
+    {
+        candidates.iter().map(|(price, _, _)| *price).max()?
+    } else if candidates
+        .iter()
+        .all(|(_, _, surplus)| *surplus < Lots::ZERO)
+    {
+        candidates.iter().map(|(price, _, _)| *price).min()?
+    } else {
+        //candidates are ascending so the lowest price wins an equal distance
+        candidates
+            .iter()
+            .map(|(price, _, _)| *price)
+            .min_by_key(|price| (price.0 - reference.0).abs())?
+    };
+
+    Some(Indicative {
+        price,
+        volume: max_volume,
+    })
+}
@@ -0,0 +1,60 @@
+use std::collections::VecDeque;
+
+use serde::{Deserialize, Serialize};
+
+use crate::models::fixed::{Lots, Ticks};
+
+// recent trades of a market in engine time, kept for as long as the longest band or breaker
+// window needs them
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct PriceHistory {
+    //(engine time, price, quantity)
+    trades: VecDeque<(u64, Ticks, Lots)>,
+}
+
+impl PriceHistory {
+    pub fn record(&mut self, now: u64, price: Ticks, quantity: Lots, retention: u64) {
+        self.trades.push_back((now, price, quantity));
+
+        while self
+            .trades
+            .front()
+            .is_some_and(|(time, _, _)| *time + retention < now)
+        {
+            self.trades.pop_front();
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.trades.clear();
+    }
+
+    //volume weighted average price of the trades since the given time, rounded to the nearest tick
+    pub fn vwap(&self, since: u64) -> Option<Ticks> {
+        let (value, volume) = self
+            .trades
+            .iter()
+            .filter(|(time, _, _)| *time >= since)
+            .fold((0i128, 0i128), |(value, volume), (_, price, qty)| {
+                (
+                    value + price.0 as i128 * qty.0 as i128,
+                    volume + qty.0 as i128,
+                )
+            });
+
+        (volume != 0).then(|| Ticks(((2 * value + volume) / (2 * volume)) as i64))
+    }
+
+    //lowest and highest trade price since the given time
+    pub fn range(&self, since: u64) -> Option<(Ticks, Ticks)> {
+        let prices = self
+            .trades
+            .iter()
+            .filter(|(time, _, _)| *time >= since)
+            .map(|(_, price, _)| *price);
+
+        let low = prices.clone().min()?;
+        let high = prices.max()?;
+        Some((low, high))
+    }
+}
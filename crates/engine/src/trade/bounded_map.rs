@@ -0,0 +1,46 @@
+use std::{
+    borrow::Borrow,
+    collections::{HashMap, VecDeque},
+    hash::Hash,
+};
+
+// map holding at most `capacity` entries, the oldest key is evicted first
+// replacing the value of a key keeps its place in the queue
+pub struct BoundedMap<K, V> {
+    capacity: usize,
+    queue: VecDeque<K>,
+    entries: HashMap<K, V>,
+}
+
+impl<K: Eq + Hash + Clone, V> BoundedMap<K, V> {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            queue: VecDeque::with_capacity(capacity),
+            entries: HashMap::with_capacity(capacity),
+        }
+    }
+
+    pub fn insert(&mut self, key: K, value: V) {
+        if self.entries.insert(key.clone(), value).is_some() {
+            //already tracked, only the content changed
+            return;
+        }
+
+        self.queue.push_back(key);
+
+        while self.queue.len() > self.capacity {
+            if let Some(evicted) = self.queue.pop_front() {
+                self.entries.remove(&evicted);
+            }
+        }
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        self.entries.get(key)
+    }
+}
@@ -0,0 +1,45 @@
+use crate::types::api::MessageToApi;
+
+use super::bounded_map::BoundedMap;
+
+//(user_id, client_order_id)
+type ClientOrderKey = (String, String);
+
+pub struct ClientOrderEntry {
+    pub order_id: String,
+    //response sent for the first submission, replayed for duplicates
+    pub response: MessageToApi,
+}
+
+// bounded index of the client order ids of accepted orders, evicted in insertion order like the
+// recent orders cache. resting orders are also matched directly against the book so they stay
+// unique while open
+pub struct ClientOrders {
+    entries: BoundedMap<ClientOrderKey, ClientOrderEntry>,
+}
+
+impl ClientOrders {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            entries: BoundedMap::new(capacity),
+        }
+    }
+
+    pub fn insert(
+        &mut self,
+        user_id: &str,
+        client_order_id: &str,
+        order_id: String,
+        response: MessageToApi,
+    ) {
+        self.entries.insert(
+            (user_id.to_string(), client_order_id.to_string()),
+            ClientOrderEntry { order_id, response },
+        );
+    }
+
+    pub fn get(&self, user_id: &str, client_order_id: &str) -> Option<&ClientOrderEntry> {
+        self.entries
+            .get(&(user_id.to_string(), client_order_id.to_string()))
+    }
+}
@@ -0,0 +1,135 @@
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+use crate::models::{
+    fixed::{self, Ticks},
+    order::{Order, OrderSide},
+};
+
+// stop orders waiting for their trigger, kept apart from the bids and asks so they never show up in depth
+// funds are locked when the order is placed, the order is only matched once triggered
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct ConditionalOrders {
+    //insertion order, used as the time priority between equal trigger prices
+    orders: Vec<Order>,
+}
+
+impl ConditionalOrders {
+    pub fn insert(&mut self, order: Order) {
+        self.orders.push(order);
+    }
+
+    pub fn remove(&mut self, order_id: &str) -> Option<Order> {
+        let pos = self.orders.iter().position(|o| o.order_id == order_id)?;
+        Some(self.orders.remove(pos))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Order> {
+        self.orders.iter()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.orders.is_empty()
+    }
+
+    //moves the watermark of every trailing stop with the last traded price and drags its trigger along
+    pub fn update_watermarks(&mut self, last_price: Ticks) {
+        if last_price.is_zero() {
+            return;
+        }
+
+        for order in self.orders.iter_mut() {
+            let Some(watermark) = order.watermark else {
+                continue;
+            };
+
+            let improved = match order.side {
+                OrderSide::Buy => last_price < watermark,
+                OrderSide::Sell => last_price > watermark,
+            };
+
+            if improved {
+                order.watermark = Some(last_price);
+                order.trigger_price = trailing_trigger(order, last_price);
+            }
+        }
+    }
+
+    // removes every order triggered by the last traded price
+    // buy stops come first from the lowest trigger up, then sell stops from the highest trigger down,
+    // equal triggers keep placement order
+    pub fn take_triggered(&mut self, last_price: Ticks) -> Vec<Order> {
+        let (mut triggered, pending): (Vec<Order>, Vec<Order>) = self
+            .orders
+            .drain(..)
+            .partition(|o| is_triggered(o, last_price));
+        self.orders = pending;
+
+        triggered.sort_by(|a, b| {
+            let a_trigger = a.trigger_price.unwrap_or_default();
+            let b_trigger = b.trigger_price.unwrap_or_default();
+
+            match (&a.side, &b.side) {
+                (OrderSide::Buy, OrderSide::Buy) => a_trigger.cmp(&b_trigger),
+                (OrderSide::Sell, OrderSide::Sell) => b_trigger.cmp(&a_trigger),
+                (OrderSide::Buy, OrderSide::Sell) => std::cmp::Ordering::Less,
+                (OrderSide::Sell, OrderSide::Buy) => std::cmp::Ordering::Greater,
+            }
+        });
+
+        triggered
+    }
+}
+
+// sets the starting watermark and trigger of a trailing stop from the reference price
+// exactly one of trailing offset and trailing percent has to be given
+pub fn init_trailing_stop(
+    order: &mut Order,
+    reference: Ticks,
+) -> Result<(), Box<dyn std::error::Error>> {
+    match (order.trailing_offset, order.trailing_percent) {
+        (Some(offset), None) if offset > Ticks::ZERO => {}
+        (None, Some(percent)) if percent > Decimal::ZERO && percent < Decimal::ONE_HUNDRED => {}
+        (None, None) => return Err("Trailing stops require a trailing offset or percent".into()),
+        _ => return Err("Invalid trailing offset".into()),
+    }
+
+    if reference <= Ticks::ZERO {
+        return Err("No reference price for trailing stop".into());
+    }
+
+    order.watermark = Some(reference);
+    order.trigger_price = trailing_trigger(order, reference);
+
+    Ok(())
+}
+
+//trigger a trailing stop would have at the given watermark
+fn trailing_trigger(order: &Order, watermark: Ticks) -> Option<Ticks> {
+    let distance = match (order.trailing_offset, order.trailing_percent) {
+        (Some(offset), _) => offset,
+        (None, Some(percent)) => fixed::percent_of(watermark, percent / Decimal::ONE_HUNDRED),
+        (None, None) => return None,
+    };
+
+    match order.side {
+        OrderSide::Buy => Some(watermark + distance),
+        OrderSide::Sell => Some((watermark - distance).max(Ticks::ZERO)),
+    }
+}
+
+//buy stops fire when the price trades at or above the trigger, sell stops at or below it
+pub fn is_triggered(order: &Order, last_price: Ticks) -> bool {
+    let Some(trigger_price) = order.trigger_price else {
+        return false;
+    };
+
+    if last_price.is_zero() {
+        return false;
+    }
+
+    match order.side {
+        OrderSide::Buy => last_price >= trigger_price,
+        OrderSide::Sell => last_price <= trigger_price,
+    }
+}
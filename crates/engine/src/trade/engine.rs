@@ -1,82 +1,705 @@
 use log::error;
-use rand::{Rng, distributions::Alphanumeric, thread_rng};
+use rand::{Rng, SeedableRng, distributions::Alphanumeric, rngs::StdRng};
 use rust_decimal::Decimal;
-use rust_decimal::prelude::ToPrimitive;
+use rust_decimal_macros::dec;
 use std::{collections::HashMap, str::FromStr};
 
 use crate::{
     models::{
         balance::{AssetBalance, UserBalance},
-        order::{Fill, Order, OrderSide},
+        fixed::{self, Lots, Scale, Ticks},
+        order::{Fill, Order, OrderSide, OrderStatus, OrderType, TimeInForce},
     },
-    redis_manager::redis_manager::RedisManager,
+    transport::Event,
     types::{
         api::{
-            CancelOrderDAta, CreateOrderData, DepthPayload, FillInfo, GetDepthData,
-            GetOpenOrdersData, MessageFromApi, MessageToApi, OnRampData, OrderCancelledPayload,
-            OrderPlacedPayload,
+            AccountFlagData, AccountStatusPayload, AmendOrderData, AuctionData, AuctionPayload,
+            BatchOperation, BatchOrdersData, CancelAllData, CancelOrderDAta, CreateOrderData,
+            CreateOrderGroupData, DepthPayload, FillInfo, GetDepthData, GetOpenOrdersData,
+            GetOrderData, MAX_BATCH_ORDERS, MarketStatusData, MarketStatusPayload, MessageFromApi,
+            MessageToApi, OnRampData, OrderAmendedPayload, OrderCancelledPayload,
+            OrderGroupPlacedPayload, OrderInfo, OrderNotFoundPayload, OrderPlacedPayload,
+            OrderRejectedPayload, OrdersCancelledPayload, RequestRejectedPayload, RiskLimitsData,
+            RiskLimitsPayload, TickData, WithdrawData, WithdrawalPayload,
+        },
+        db::{AccountAuditData, DbMessage, OrderUpdateData, TradeAddedData},
+        ws::{
+            AuctionUpdateMessage, DepthUpdateData, DepthUpdateMessage, MarketStatusMessage,
+            OrderUpdateMessage, TradeAddedMessage, WsAuctionData, WsMarketStatusData, WsMessage,
+            WsOrderUpdateData, WsTradeAddedData,
         },
-        db::{DbMessage, OrderUpdateData, TradeAddedData},
-        ws::{DepthUpdateData, DepthUpdateMessage, TradeAddedMessage, WsMessage, WsTradeAddedData},
     },
 };
 
-use super::orderbook::{BASE_CURRENCY, Orderbook};
+use super::{
+    accounts::{AccountFlag, AccountRejection, AccountStatus},
+    assets::{Asset, AssetRegistry, BASE_CURRENCY},
+    auction::{AuctionAction, TradingPhase},
+    client_orders::ClientOrders,
+    conditional,
+    groups::{GroupType, OrderGroup},
+    market::{BreakerAction, MarketConfig, MarketStatus},
+    orderbook::{DepthLevel, OrderMatchResult, Orderbook},
+    recent_orders::{RECENT_ORDERS_CAPACITY, RecentOrders},
+    risk::{Exposure, RiskLimiter, RiskRejection},
+};
 
 pub struct Engine {
     orderbooks: Vec<Orderbook>,
+    assets: AssetRegistry,
     balances: HashMap<String, UserBalance>,
+    recent_orders: RecentOrders,
+    client_orders: ClientOrders,
+    //(market, group id, quantity) of brackets whose entry filled during the current step
+    pending_brackets: Vec<(String, String, Lots)>,
+    //engine time in millis, only moved forward by ticks
+    clock: u64,
+    risk: RiskLimiter,
+    //users with compliance flags, everyone else trades normally
+    accounts: HashMap<String, AccountStatus>,
+    //events of the command being applied, handed back by `apply`
+    outbox: Vec<Event>,
+    //order and group ids, seeded so a replay hands out the same ones
+    ids: StdRng,
+}
+
+// market orders are protected against sweeping the whole book, they trade at most this far
+// through their reference price and lock funds at that bound
+const MARKET_PROTECTION: Decimal = dec!(0.10);
+
+const DAY_MILLIS: u64 = 24 * 60 * 60 * 1000;
+
+//15:30 IST
+const TATA_INR_SESSION_END: u64 = 10 * 60 * 60 * 1000;
+
+//rounded towards the reference, the bound never lets an order further than the protection
+fn protection_price(reference: Ticks, side: &OrderSide) -> Ticks {
+    let distance = fixed::percent_of(reference, MARKET_PROTECTION);
+    match side {
+        OrderSide::Buy => reference + distance,
+        OrderSide::Sell => reference - distance,
+    }
+}
+
+//whole lots, an order has to be at least one
+fn normalize_quantity(
+    scale: &Scale,
+    quantity: Decimal,
+) -> Result<Lots, Box<dyn std::error::Error>> {
+    let quantity = scale.to_lots(quantity)?;
+    if quantity <= Lots::ZERO {
+        return Err("Invalid quantity".into());
+    }
+    Ok(quantity)
+}
+
+// trailing stops start trailing from the last traded price, or the touch when nothing has traded yet
+fn init_trailing_stop(
+    orderbook: &Orderbook,
+    order: &mut Order,
+    data: &CreateOrderData,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let scale = orderbook.config.scale;
+    order.trailing_offset = data
+        .trailing_offset
+        .as_deref()
+        .map(Decimal::from_str)
+        .transpose()?
+        .map(|offset| scale.nearest_ticks(offset))
+        .transpose()?;
+    order.trailing_percent = data
+        .trailing_percent
+        .as_deref()
+        .map(Decimal::from_str)
+        .transpose()?;
+
+    let reference = if orderbook.current_price.is_zero() {
+        match order.side {
+            OrderSide::Buy => orderbook.best_ask(),
+            OrderSide::Sell => orderbook.best_bid(),
+        }
+        .unwrap_or_default()
+    } else {
+        orderbook.current_price
+    };
+
+    conditional::init_trailing_stop(order, reference)
+}
+
+fn init_iceberg(
+    orderbook: &Orderbook,
+    order: &mut Order,
+    data: &CreateOrderData,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let Some(display_quantity) = data
+        .display_quantity
+        .as_deref()
+        .map(Decimal::from_str)
+        .transpose()?
+        .map(|quantity| orderbook.config.scale.to_lots(quantity))
+        .transpose()?
+    else {
+        return Ok(());
+    };
+
+    if order.order_type != OrderType::Limit {
+        return Err("Only limit orders can be icebergs".into());
+    }
+    if display_quantity <= Lots::ZERO || display_quantity > order.quantity {
+        return Err("Invalid display quantity".into());
+    }
+
+    order.display_quantity = Some(display_quantity);
+    Ok(())
+}
+
+fn init_peg(
+    orderbook: &Orderbook,
+    order: &mut Order,
+    data: &CreateOrderData,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let Some(peg) = data.peg else {
+        return Ok(());
+    };
+
+    if order.order_type != OrderType::Limit {
+        return Err("Only limit orders can be pegged".into());
+    }
+
+    let scale = orderbook.config.scale;
+    order.peg = Some(peg);
+    order.peg_offset = data
+        .peg_offset
+        .as_deref()
+        .map(Decimal::from_str)
+        .transpose()?
+        .map(|offset| scale.nearest_ticks(offset))
+        .transpose()?;
+    order.peg_cap = if data.price.is_empty() {
+        None
+    } else {
+        Some(scale.to_ticks(Decimal::from_str(&data.price)?, &order.side)?)
+    };
+
+    Ok(())
+}
+
+// gtd orders carry their own expiry, day orders expire at the first session end after placement
+fn init_expiry(
+    orderbook: &Orderbook,
+    order: &mut Order,
+    data: &CreateOrderData,
+    clock: u64,
+) -> Result<(), Box<dyn std::error::Error>> {
+    order.time_in_force = data.time_in_force;
+    order.expire_at = match data.time_in_force {
+        TimeInForce::Gtc => None,
+        TimeInForce::Gtd => Some(data.expire_at.ok_or("GTD orders require expire_at")?),
+        TimeInForce::Day => {
+            let session_end = orderbook
+                .config
+                .session_end
+                .ok_or("Market has no day session")?;
+            let mut expire_at = order.created_at - order.created_at % DAY_MILLIS + session_end;
+            if expire_at <= order.created_at {
+                expire_at += DAY_MILLIS;
+            }
+            Some(expire_at)
+        }
+    };
+
+    if order.expire_at.is_some_and(|expire_at| expire_at <= clock) {
+        return Err("Order already expired".into());
+    }
+
+    Ok(())
+}
+
+//price the order's funds are locked at, the limit for limit orders and the protection bound otherwise
+fn order_lock_price(
+    orderbook: &Orderbook,
+    order: &Order,
+    price: &str,
+) -> Result<Ticks, Box<dyn std::error::Error>> {
+    if order.order_type.is_conditional() && order.trigger_price.is_none() {
+        return Err("Stop orders require a trigger price".into());
+    }
+
+    let price = match order.order_type {
+        OrderType::Limit if order.peg.is_some() => orderbook
+            .peg_price(order)
+            .ok_or("No reference price for pegged order")?,
+        OrderType::Limit | OrderType::StopLimit => orderbook
+            .config
+            .scale
+            .to_ticks(Decimal::from_str(price)?, &order.side)?,
+        OrderType::StopMarket | OrderType::TrailingStop => {
+            protection_price(order.trigger_price.unwrap_or_default(), &order.side)
+        }
+        OrderType::Market => {
+            let reference = match order.side {
+                OrderSide::Buy => orderbook.best_ask(),
+                OrderSide::Sell => orderbook.best_bid(),
+            }
+            .ok_or("No liquidity for market order")?;
+            protection_price(reference, &order.side)
+        }
+    };
+
+    if price <= Ticks::ZERO {
+        return Err("Invalid price".into());
+    }
+    //limits and locks are worked out from the order's value, so it has to fit
+    if orderbook
+        .config
+        .scale
+        .checked_notional(price, order.quantity)
+        .is_none()
+    {
+        return Err("Invalid quantity".into());
+    }
+
+    Ok(price)
+}
+
+// limit orders priced outside the market's band are rejected, market orders are capped at its
+// edge so they can't trade through it
+fn apply_price_band(
+    orderbook: &Orderbook,
+    order: &Order,
+    price: Ticks,
+    now: u64,
+) -> Result<Ticks, Box<dyn std::error::Error>> {
+    let Some((low, high)) = orderbook.price_band(now) else {
+        return Ok(price);
+    };
+
+    match (order.order_type, &order.side) {
+        (OrderType::Limit, _) if price < low || price > high => {
+            let scale = orderbook.config.scale;
+            Err(format!(
+                "Price outside of the band {} - {}",
+                scale.price(low),
+                scale.price(high)
+            )
+            .into())
+        }
+        (OrderType::Market, OrderSide::Buy) => Ok(price.min(high)),
+        (OrderType::Market, OrderSide::Sell) => Ok(price.max(low)),
+        _ => Ok(price),
+    }
+}
+
+fn parse_side(side: &str) -> OrderSide {
+    if side == "buy" {
+        OrderSide::Buy
+    } else {
+        OrderSide::Sell
+    }
+}
+
+fn rejection_code(e: &(dyn std::error::Error + 'static)) -> Option<String> {
+    e.downcast_ref::<RiskRejection>()
+        .map(|rejection| rejection.code().to_string())
+}
+
+fn fill_info(scale: &Scale, fill: &Fill) -> FillInfo {
+    FillInfo {
+        price: scale.price(fill.price).to_string(),
+        qty: scale.quantity(fill.qty).to_string(),
+        trade_id: fill.trade_id,
+    }
+}
+
+//depth levels as sent over the wire
+fn wire_levels(scale: &Scale, levels: Vec<DepthLevel>) -> Vec<(String, String)> {
+    levels
+        .into_iter()
+        .map(|(price, qty)| {
+            (
+                scale.price(price).to_string(),
+                scale.quantity(qty).to_string(),
+            )
+        })
+        .collect()
+}
+
+impl Default for Engine {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Engine {
     pub fn new() -> Self {
-        let engine = Engine {
+        Self::with_seed(rand::random())
+    }
+
+    //same seed and same commands give the same events
+    pub fn with_seed(seed: u64) -> Self {
+        let mut assets = AssetRegistry::default();
+        for (symbol, decimals, display_precision, min_withdrawal) in [
+            (BASE_CURRENCY, 2, 2, dec!(100)),
+            ("TATA", 2, 2, dec!(1)),
+            ("USDT", 6, 2, dec!(10)),
+            ("BTC", 8, 6, dec!(0.0005)),
+            ("ETH", 8, 6, dec!(0.005)),
+        ] {
+            assets.register(Asset {
+                symbol: symbol.to_string(),
+                decimals,
+                display_precision,
+                min_withdrawal,
+            });
+        }
+
+        let mut engine = Engine {
             orderbooks: Vec::new(),
+            assets,
             balances: HashMap::new(),
+            recent_orders: RecentOrders::new(RECENT_ORDERS_CAPACITY),
+            client_orders: ClientOrders::new(RECENT_ORDERS_CAPACITY),
+            pending_brackets: Vec::new(),
+            clock: 0,
+            risk: RiskLimiter::default(),
+            accounts: HashMap::new(),
+            outbox: Vec::new(),
+            ids: StdRng::seed_from_u64(seed),
         };
-        engine
         // will implement snap shot later
+        let markets = [
+            Orderbook::new(
+                "TATA".to_string(),
+                BASE_CURRENCY.to_string(),
+                Vec::new(),
+                Vec::new(),
+                0,
+                Ticks::ZERO,
+            )
+            .with_config(MarketConfig {
+                session_end: Some(TATA_INR_SESSION_END),
+                ..Default::default()
+            }),
+            Orderbook::new(
+                "BTC".to_string(),
+                "USDT".to_string(),
+                Vec::new(),
+                Vec::new(),
+                0,
+                Ticks::ZERO,
+            )
+            .with_config(MarketConfig {
+                scale: Scale {
+                    tick_size: dec!(0.01),
+                    lot_size: dec!(0.00001),
+                },
+                ..Default::default()
+            }),
+            Orderbook::new(
+                "ETH".to_string(),
+                "BTC".to_string(),
+                Vec::new(),
+                Vec::new(),
+                0,
+                Ticks::ZERO,
+            )
+            .with_config(MarketConfig {
+                scale: Scale {
+                    tick_size: dec!(0.000001),
+                    lot_size: dec!(0.0001),
+                },
+                ..Default::default()
+            }),
+        ];
+        for orderbook in markets {
+            if let Err(e) = engine.add_market(orderbook) {
+                error!("Failed to add market: {}", e);
+            }
+        }
+
+        engine.set_base_balances();
+        engine
+    }
+
+    // both sides of a market have to be registered assets, and its tick and lot size have to be
+    // representable in the quote and base asset
+    fn add_market(&mut self, orderbook: Orderbook) -> Result<(), Box<dyn std::error::Error>> {
+        let scale = orderbook.config.scale;
+        if !scale.is_valid() {
+            return Err(format!("Invalid tick or lot size for {}", orderbook.ticker()).into());
+        }
+
+        for (asset, step) in [
+            (&orderbook.base_asset, scale.lot_size),
+            (&orderbook.quote_asset, scale.tick_size),
+        ] {
+            let Some(asset) = self.assets.get(asset) else {
+                return Err(format!("Unknown asset {}", asset).into());
+            };
+            if asset.normalize(step) != step {
+                return Err(
+                    format!("{} is finer than the precision of {}", step, asset.symbol).into(),
+                );
+            }
+        }
+        if self
+            .orderbooks
+            .iter()
+            .any(|o| o.ticker() == orderbook.ticker())
+        {
+            return Err(format!("Market {} already exists", orderbook.ticker()).into());
+        }
+
+        self.orderbooks.push(orderbook);
+        Ok(())
+    }
+
+    // applies one command and returns everything it sends out, in order, without doing any io
+    // itself, time only moves with tick commands
+    pub fn apply(&mut self, message: MessageFromApi) -> Vec<Event> {
+        self.dispatch(message);
+        std::mem::take(&mut self.outbox)
+    }
+
+    fn generate_order_id(&mut self) -> String {
+        (&mut self.ids)
+            .sample_iter(&Alphanumeric)
+            .take(30)
+            .map(char::from)
+            .collect::<String>()
     }
 
-    pub async fn process(&mut self, message: MessageFromApi) {
+    fn dispatch(&mut self, message: MessageFromApi) {
+        //account flags are enforced before any handler sees the command
+        if let Err(rejection) = self.check_account(&message) {
+            self.reject_command(&message, rejection);
+            return;
+        }
+
         match message {
             MessageFromApi::CreateOrder { data, client_id } => {
-                self.handle_create_order(data, &client_id).await;
+                self.handle_create_order(data, &client_id);
             }
             MessageFromApi::CancelOrder { data, client_id } => {
-                self.handle_cancel_order(data, &client_id).await;
+                self.handle_cancel_order(data, &client_id);
             }
             MessageFromApi::GetOpenOrders { data, client_id } => {
-                self.handle_get_open_orders(data, &client_id).await;
+                self.handle_get_open_orders(data, &client_id);
             }
             MessageFromApi::OnRame { data, client_id: _ } => {
-                self.handle_on_ramp(data).await;
+                self.handle_on_ramp(data);
             }
             MessageFromApi::GetDepth { data, client_id } => {
-                self.handle_get_depth(data, &client_id).await
+                self.handle_get_depth(data, &client_id);
+            }
+            MessageFromApi::GetOrder { data, client_id } => {
+                self.handle_get_order(data, &client_id);
+            }
+            MessageFromApi::AmendOrder { data, client_id } => {
+                self.handle_amend_order(data, &client_id);
+            }
+            MessageFromApi::CancelAll { data, client_id } => {
+                self.handle_cancel_all(data, &client_id);
+            }
+            MessageFromApi::BatchOrders { data, client_id } => {
+                self.handle_batch_orders(data, &client_id);
+            }
+            MessageFromApi::CreateOrderGroup { data, client_id } => {
+                self.handle_create_order_group(data, &client_id);
+            }
+            MessageFromApi::Tick { data } => {
+                self.handle_tick(data);
+            }
+            MessageFromApi::Auction { data, client_id } => {
+                self.handle_auction(data, &client_id);
+            }
+            MessageFromApi::SetMarketStatus { data, client_id } => {
+                self.handle_set_market_status(data, &client_id);
+            }
+            MessageFromApi::SetRiskLimits { data, client_id } => {
+                self.handle_set_risk_limits(data, &client_id);
+            }
+            MessageFromApi::SetAccountFlag { data, client_id } => {
+                self.handle_set_account_flag(data, &client_id);
+            }
+            MessageFromApi::Withdraw { data, client_id } => {
+                self.handle_withdraw(data, &client_id);
+            }
+        }
+    }
+
+    fn check_account(&self, message: &MessageFromApi) -> Result<(), AccountRejection> {
+        let check_order = |user_id: &str, side: &OrderSide| {
+            self.accounts
+                .get(user_id)
+                .map_or(Ok(()), |account| account.check_order(side))
+        };
+        let check_cancel = |user_id: &str| {
+            self.accounts
+                .get(user_id)
+                .map_or(Ok(()), |account| account.check_cancel())
+        };
+
+        match message {
+            MessageFromApi::CreateOrder { data, .. } => {
+                check_order(&data.user_id, &parse_side(&data.side))
+            }
+            MessageFromApi::CreateOrderGroup { data, .. } => {
+                check_order(&data.user_id, &parse_side(&data.side))
+            }
+            MessageFromApi::CancelOrder { data, .. } => match self.cancel_owner(data) {
+                Some(user_id) => check_cancel(&user_id),
+                None => Ok(()),
+            },
+            MessageFromApi::Withdraw { data, .. } => self
+                .accounts
+                .get(&data.user_id)
+                .map_or(Ok(()), |account| account.check_withdrawal()),
+            MessageFromApi::CancelAll { data, .. } => match &data.user_id {
+                Some(user_id) => check_cancel(user_id),
+                None => Ok(()),
+            },
+            MessageFromApi::AmendOrder { data, .. } => {
+                let order = self
+                    .resolve_order_id(
+                        data.order_id.clone(),
+                        data.client_order_id.clone(),
+                        data.user_id.clone(),
+                    )
+                    .and_then(|id| {
+                        self.orderbooks
+                            .iter()
+                            .find(|o| o.ticker() == data.market)
+                            .and_then(|o| o.get_order(&id))
+                    });
+
+                match order {
+                    Some(order) => check_order(&order.user_id, &order.side),
+                    None => Ok(()),
+                }
+            }
+            _ => Ok(()),
+        }
+    }
+
+    //batches are checked item by item, a flagged user only loses their own items
+    fn check_batch_item(&self, operation: &BatchOperation) -> Result<(), AccountRejection> {
+        let user_id = match operation {
+            BatchOperation::Create(data) => Some(data.user_id.clone()),
+            BatchOperation::Cancel(data) => self.cancel_owner(data),
+        };
+        let Some(account) = user_id.and_then(|user_id| self.accounts.get(&user_id)) else {
+            return Ok(());
+        };
+
+        match operation {
+            BatchOperation::Create(data) => account.check_order(&parse_side(&data.side)),
+            BatchOperation::Cancel(_) => account.check_cancel(),
+        }
+    }
+
+    //user behind a cancel request, from the request itself or the order or group it targets
+    fn cancel_owner(&self, data: &CancelOrderDAta) -> Option<String> {
+        if let Some(user_id) = &data.user_id {
+            return Some(user_id.clone());
+        }
+
+        let orderbook = self.orderbooks.iter().find(|o| o.ticker() == data.market)?;
+
+        if let Some(group) = data.group_id.as_ref().and_then(|g| orderbook.groups.get(g)) {
+            return Some(group.user_id.clone());
+        }
+
+        data.order_id
+            .as_ref()
+            .and_then(|id| orderbook.get_order(id))
+            .map(|order| order.user_id.clone())
+    }
+
+    fn reject_command(&mut self, message: &MessageFromApi, rejection: AccountRejection) {
+        let Some(client_id) = message.client_id() else {
+            return;
+        };
+
+        let message = MessageToApi::OrderRejected {
+            payload: OrderRejectedPayload {
+                order_id: String::new(),
+                reason: rejection.to_string(),
+                code: Some(rejection.code().to_string()),
+            },
+        };
+
+        self.send_to_api(client_id, message);
+    }
+
+    fn handle_set_account_flag(&mut self, data: AccountFlagData, client_id: &str) {
+        let account = self.accounts.entry(data.user_id.clone()).or_default();
+        account.set(data.flag, data.enabled);
+        account.reason = Some(data.reason.clone());
+        let status = account.clone();
+
+        //clearing every flag drops the record
+        if !status.frozen && !status.cancel_only && !status.reduce_only {
+            self.accounts.remove(&data.user_id);
+        }
+
+        let audit = DbMessage::AccountAudit {
+            data: AccountAuditData {
+                user_id: data.user_id.clone(),
+                flag: data.flag,
+                enabled: data.enabled,
+                reason: data.reason.clone(),
+                timestamp: self.clock,
+            },
+        };
+        self.push_message(audit);
+
+        //resting orders of a frozen account can't be cancelled by the user any more
+        let mut order_ids = Vec::new();
+        if data.flag == AccountFlag::Frozen && data.enabled && data.cancel_orders {
+            let cancel = CancelAllData {
+                user_id: Some(data.user_id.clone()),
+                market: None,
+                side: None,
+                min_price: None,
+                max_price: None,
+            };
+
+            match self.cancel_all(&cancel) {
+                Ok((cancelled, _)) => order_ids = cancelled,
+                Err(e) => error!("Failed to cancel orders of {}: {}", data.user_id, e),
             }
         }
+
+        let message = MessageToApi::AccountStatus {
+            payload: AccountStatusPayload {
+                user_id: data.user_id,
+                status,
+                order_ids,
+            },
+        };
+
+        self.send_to_api(client_id, message);
     }
 
-    async fn handle_get_depth(&self, data: GetDepthData, client_id: &str) {
+    fn handle_get_depth(&mut self, data: GetDepthData, client_id: &str) {
         let market = data.market;
 
         if let Some(orderbook) = self.orderbooks.iter().find(|o| o.ticker() == market) {
+            let scale = orderbook.config.scale;
             let (bids, asks) = orderbook.get_depth();
 
-            let redis_manager = RedisManager::get_instance();
-
             let message = MessageToApi::Depth {
-                payload: DepthPayload { bids, asks },
+                payload: DepthPayload {
+                    bids: wire_levels(&scale, bids),
+                    asks: wire_levels(&scale, asks),
+                },
             };
 
-            if let Err(e) = redis_manager.send_to_api(client_id, message).await {
-                error!("Failed to send depth message: {}", e);
-            }
+            self.send_to_api(client_id, message);
         } else {
             error!("Orderbook not found for market: {}", market);
 
-            let redis_manager = RedisManager::get_instance();
             let message = MessageToApi::Depth {
                 payload: DepthPayload {
                     bids: Vec::new(),
@@ -84,149 +707,839 @@ impl Engine {
                 },
             };
 
-            if let Err(e) = redis_manager.send_to_api(client_id, message).await {
-                error!("Failed to send empth depth message: {}", e);
-            }
+            self.send_to_api(client_id, message);
         }
     }
 
     //handling create order function
-    async fn handle_create_order(&mut self, data: CreateOrderData, client_id: &str) {
-        let market = data.market;
-        let price_str = data.price;
-        let quantity_str = data.quantity;
-        let side_str = data.side;
-        let user_id = data.user_id;
+    fn handle_create_order(&mut self, data: CreateOrderData, client_id: &str) {
+        let message = self.place_order(&data);
 
-        let side = if side_str == "buy" {
-            OrderSide::Buy
-        } else {
-            OrderSide::Sell
-        };
+        self.send_to_api(client_id, message);
+    }
 
-        match self
-            .create_order(&market, &price_str, &quantity_str, side, &user_id)
-            .await
+    fn place_order(&mut self, data: &CreateOrderData) -> MessageToApi {
+        //a retried submission gets the original result back instead of placing a second order
+        if let Some(client_order_id) = &data.client_order_id
+            && let Some(message) = self.duplicate_submission(&data.user_id, client_order_id)
         {
-            Ok((executed_qty, fills, order_id)) => {
-                let fill_infos = fills
-                    .into_iter()
-                    .map(|f| FillInfo {
-                        price: f.price,
-                        qty: f.qty.to_string(),
-                        trade_id: f.trade_id,
-                    })
-                    .collect();
+            return message;
+        }
 
-                let manager = RedisManager::get_instance();
+        let order_id = self.generate_order_id();
 
-                let message = MessageToApi::OrderPlaced {
+        let scale = self.scale(&data.market);
+        let message = match self.create_order(&order_id, data) {
+            Ok((order, fills)) => {
+                let fill_infos = fills.iter().map(|f| fill_info(&scale, f)).collect();
+
+                MessageToApi::OrderPlaced {
                     payload: OrderPlacedPayload {
-                        order_id,
-                        executed_qty,
+                        order_id: order.order_id,
+                        executed_qty: scale.quantity(order.filled),
                         fills: fill_infos,
                     },
-                };
-
-                if let Err(e) = manager.send_to_api(client_id, message).await {
-                    error!("Failed to send order placed message: {}", e);
                 }
             }
+            //risk limit breaches also tell the user which limit was hit
             Err(e) => {
                 error!("Failed to create order: {}", e);
 
-                let manager = RedisManager::get_instance();
-
-                let message = MessageToApi::OrderCancelled {
-                    payload: OrderCancelledPayload {
-                        order_id: "".to_string(),
-                        executed_qty: Decimal::ZERO,
-                        remaining_qty: Decimal::ZERO,
+                MessageToApi::OrderRejected {
+                    payload: OrderRejectedPayload {
+                        order_id: order_id.clone(),
+                        reason: e.to_string(),
+                        code: rejection_code(e.as_ref()),
                     },
-                };
-
-                if let Err(e) = manager.send_to_api(client_id, message).await {
-                    error!("Failed to send order cancelled message: {}", e);
                 }
             }
+        };
+
+        //a rejected order can be fixed and resubmitted under the same client order id
+        if let Some(client_order_id) = &data.client_order_id
+            && matches!(message, MessageToApi::OrderPlaced { .. })
+        {
+            self.client_orders
+                .insert(&data.user_id, client_order_id, order_id, message.clone());
         }
+
+        message
     }
 
-    async fn create_order(
-        &mut self,
-        market: &str,
-        price_str: &str,
-        quantity_str: &str,
-        side: OrderSide,
-        user_id: &str,
-    ) -> Result<(Decimal, Vec<Fill>, String), Box<dyn std::error::Error>> {
-        let parts: Vec<&str> = market.split("_").collect();
-        let base_asset = parts[0];
-        let quote_asset = parts.get(1).unwrap_or(&BASE_CURRENCY);
-        let price = Decimal::from_str(price_str)?;
-        let quantity = Decimal::from_str(quantity_str)?;
-        self.check_and_lock_funds(base_asset, quote_asset, &side, user_id, price, quantity)?;
-
-        let order_id = thread_rng()
-            .sample_iter(&Alphanumeric)
-            .take(30)
-            .map(char::from)
-            .collect::<String>();
+    fn duplicate_submission(&self, user_id: &str, client_order_id: &str) -> Option<MessageToApi> {
+        if let Some(entry) = self.client_orders.get(user_id, client_order_id) {
+            return Some(entry.response.clone());
+        }
 
-        let orderbook = self
-            .orderbooks
-            .iter_mut()
-            .find(|o| o.ticker() == market)
-            .ok_or("No orderbook found")?;
+        //still resting but already evicted from the index
+        self.orderbooks.iter().find_map(|o| {
+            let order = o.get_order_by_client_id(user_id, client_order_id)?;
+            Some(MessageToApi::OrderPlaced {
+                payload: OrderPlacedPayload {
+                    order_id: order.order_id.clone(),
+                    executed_qty: o.config.scale.quantity(order.filled),
+                    fills: Vec::new(),
+                },
+            })
+        })
+    }
+
+    //cancel and query calls may reference an order by the user's client order id instead
+    fn resolve_order_id(
+        &self,
+        order_id: Option<String>,
+        client_order_id: Option<String>,
+        user_id: Option<String>,
+    ) -> Option<String> {
+        if order_id.is_some() {
+            return order_id;
+        }
 
-        // Generate a random order ID
+        let (client_order_id, user_id) = (client_order_id?, user_id?);
 
-        let order = Order {
-            price,
+        self.orderbooks
+            .iter()
+            .find_map(|o| o.get_order_by_client_id(&user_id, &client_order_id))
+            .map(|order| order.order_id.clone())
+            .or_else(|| {
+                self.client_orders
+                    .get(&user_id, &client_order_id)
+                    .map(|entry| entry.order_id.clone())
+            })
+    }
+
+    fn create_order(
+        &mut self,
+        order_id: &str,
+        data: &CreateOrderData,
+    ) -> Result<(Order, Vec<Fill>), Box<dyn std::error::Error>> {
+        let market = data.market.as_str();
+        let user_id = data.user_id.as_str();
+        let side = if data.side == "buy" {
+            OrderSide::Buy
+        } else {
+            OrderSide::Sell
+        };
+
+        let (base_asset, quote_asset) = self.market_assets(market)?;
+        let scale = self.scale(market);
+        let quantity = normalize_quantity(&scale, Decimal::from_str(&data.quantity)?)?;
+        let trigger_price = data
+            .trigger_price
+            .as_deref()
+            .map(Decimal::from_str)
+            .transpose()?
+            .map(|price| scale.nearest_ticks(price))
+            .transpose()?;
+
+        let mut order = Order::new(
+            order_id.to_string(),
+            user_id.to_string(),
+            side.clone(),
+            Ticks::ZERO,
             quantity,
-            order_id: order_id.clone(),
-            filled: Decimal::ZERO,
-            side: side.clone(),
-            user_id: user_id.to_string(),
+            self.clock,
+        );
+        order.client_order_id = data.client_order_id.clone();
+        order.order_type = data.order_type;
+        order.trigger_price = trigger_price;
+
+        let Some(orderbook) = self.orderbooks.iter().find(|o| o.ticker() == market) else {
+            self.reject_order(order, market);
+            return Err("No orderbook found".into());
         };
 
-        let order_clone = order.clone();
+        if !orderbook.status.accepts_orders() {
+            let reason = format!("Market is {}", orderbook.status.as_str());
+            self.reject_order(order, market);
+            return Err(reason.into());
+        }
 
-        let result = orderbook.add_order(order);
+        //nothing trades until the uncross, so there is no price to protect a market order with
+        if orderbook.phase.is_auction() && order.order_type == OrderType::Market {
+            self.reject_order(order, market);
+            return Err("Market orders are not accepted during auctions".into());
+        }
+        if orderbook.config.batch.is_some() && order.order_type.is_conditional() {
+            self.reject_order(order, market);
+            return Err("Stop orders are not supported on batch markets".into());
+        }
 
-        //updating balance based on fills
-        self.update_balance(user_id, base_asset, quote_asset, &side, &result.fills);
+        let current_price = orderbook.current_price;
+        if let Err(e) = init_iceberg(orderbook, &mut order, data) {
+            self.reject_order(order, market);
+            return Err(e);
+        }
+        if let Err(e) = init_peg(orderbook, &mut order, data) {
+            self.reject_order(order, market);
+            return Err(e);
+        }
+        if let Err(e) = init_expiry(orderbook, &mut order, data, self.clock) {
+            self.reject_order(order, market);
+            return Err(e);
+        }
+        if order.order_type == OrderType::TrailingStop
+            && let Err(e) = init_trailing_stop(orderbook, &mut order, data)
+        {
+            self.reject_order(order, market);
+            return Err(e);
+        }
 
-        //creating database record for trades
-        self.create_db_trades(&result.fills, market).await;
+        let price = match order_lock_price(orderbook, &order, &data.price)
+            .and_then(|price| apply_price_band(orderbook, &order, price, self.clock))
+        {
+            Ok(price) => price,
+            Err(e) => {
+                self.reject_order(order, market);
+                return Err(e);
+            }
+        };
+        order.price = price;
 
-        //updating database
-        self.update_db_orders(&order_clone, result.executed_qty, &result.fills, market)
-            .await;
+        if order.order_type.is_conditional() && conditional::is_triggered(&order, current_price) {
+            self.reject_order(order, market);
+            return Err("Stop price already reached".into());
+        }
 
-        //publish websocket depth updates
+        if let Err(e) = self.check_risk(user_id, market, price, quantity, None) {
+            self.reject_order(order, market);
+            return Err(e.into());
+        }
 
-        self.publish_ws_depth_updates(&result.fills, price_str, &side, market)
-            .await;
+        if let Err(e) = self.check_and_lock_funds(
+            &base_asset,
+            &quote_asset,
+            &scale,
+            &side,
+            user_id,
+            price,
+            quantity,
+        ) {
+            self.reject_order(order, market);
+            return Err(e);
+        }
+        self.risk.record(user_id, self.clock);
 
-        //publish websocket trades
-        self.publish_ws_trades(&result.fills, user_id, market).await;
+        //indexed up front, orders which are done before then are skipped when they come due
+        if let Some(expire_at) = order.expire_at
+            && let Some(orderbook) = self.orderbooks.iter_mut().find(|o| o.ticker() == market)
+        {
+            orderbook.expiries.insert(expire_at, order.order_id.clone());
+        }
+
+        //stop orders wait in the conditional store with their funds already reserved
+        if order.order_type.is_conditional() {
+            if let Some(orderbook) = self.orderbooks.iter_mut().find(|o| o.ticker() == market) {
+                orderbook.conditional.insert(order.clone());
+            }
+            self.update_db_orders(&order, &[], &[], market);
+
+            return Ok((order, Vec::new()));
+        }
+
+        let fills = self.match_order(&mut order, market)?;
 
-        Ok((result.executed_qty, result.fills, order_id))
+        Ok((order, fills))
     }
 
-    fn check_and_lock_funds(
+    //matches an order and then any stop orders its trades have triggered
+    fn match_order(
         &mut self,
-        base_asset: &str,
-        quote_asset: &str,
-        side: &OrderSide,
-        user_id: &str,
-        price: Decimal,
-        quantity: Decimal,
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        match side {
-            OrderSide::Buy => {
-                let required_funds = price * quantity;
+        order: &mut Order,
+        market: &str,
+    ) -> Result<Vec<Fill>, Box<dyn std::error::Error>> {
+        let fills = self.execute_order(order, market)?;
+
+        if !fills.is_empty() {
+            self.trigger_conditional_orders(market);
+        }
+        self.settle_follow_ups(market);
+
+        Ok(fills)
+    }
+
+    //work left over once trades settled, bracket exits and the orders that react to the new book
+    fn settle_follow_ups(&mut self, market: &str) {
+        //brackets whose entry filled place their exit legs, which can trade and trigger stops in turn
+        while !self.pending_brackets.is_empty() {
+            for (market, group_id, quantity) in std::mem::take(&mut self.pending_brackets) {
+                self.activate_bracket(&market, &group_id, quantity);
+            }
+            self.trigger_conditional_orders(market);
+        }
+
+        self.book_changed(market);
+    }
+
+    //called whenever resting orders changed, pegs follow the new book and auctions republish
+    //their indicative price
+    fn book_changed(&mut self, market: &str) {
+        self.reprice_pegged(market);
+
+        if self
+            .orderbooks
+            .iter()
+            .any(|o| o.ticker() == market && o.phase.is_auction())
+        {
+            self.publish_ws_auction_update(market);
+        }
+    }
+
+    // triggered orders may trade and trigger further stops, so this runs until the
+    // last price no longer triggers anything
+    fn trigger_conditional_orders(&mut self, market: &str) {
+        loop {
+            let Some(book) = self.orderbooks.iter().position(|o| o.ticker() == market) else {
+                return;
+            };
+            let orderbook = &mut self.orderbooks[book];
+
+            //stops wait for continuous trading, an auction has no trades to trigger them
+            if orderbook.conditional.is_empty()
+                || orderbook.phase.is_auction()
+                || orderbook.status != MarketStatus::Open
+            {
+                return;
+            }
+
+            orderbook
+                .conditional
+                .update_watermarks(orderbook.current_price);
+
+            //stops of accounts flagged since they were placed are cancelled instead of trading
+            let blocked: Vec<String> = orderbook
+                .conditional
+                .iter()
+                .filter(|o| conditional::is_triggered(o, orderbook.current_price))
+                .filter(|o| {
+                    self.accounts
+                        .get(&o.user_id)
+                        .is_some_and(|account| account.check_order(&o.side).is_err())
+                })
+                .map(|o| o.order_id.clone())
+                .collect();
+            if !blocked.is_empty() {
+                self.cancel_orders(book, blocked);
+                continue;
+            }
+
+            let orderbook = &mut self.orderbooks[book];
+            let triggered = orderbook
+                .conditional
+                .take_triggered(orderbook.current_price);
+
+            if triggered.is_empty() {
+                return;
+            }
+
+            for mut order in triggered {
+                order.updated_at = self.clock;
+
+                //a sell trailing stop's trigger has only moved up since placement, so protect from there
+                //buys keep the bound their funds were locked at
+                if order.order_type == OrderType::TrailingStop
+                    && order.side == OrderSide::Sell
+                    && let Some(trigger_price) = order.trigger_price
+                {
+                    order.price = protection_price(trigger_price, &order.side);
+                }
+
+                if let Err(e) = self.execute_order(&mut order, market) {
+                    error!(
+                        "Failed to execute triggered order {}: {}",
+                        order.order_id, e
+                    );
+                }
+            }
+        }
+    }
+
+    //runs an order whose funds are already locked through the book and settles the result
+    fn execute_order(
+        &mut self,
+        order: &mut Order,
+        market: &str,
+    ) -> Result<Vec<Fill>, Box<dyn std::error::Error>> {
+        let (base_asset, quote_asset) = self.market_assets(market)?;
+
+        let orderbook = self
+            .orderbooks
+            .iter_mut()
+            .find(|o| o.ticker() == market)
+            .ok_or("No orderbook found")?;
+        let scale = orderbook.config.scale;
+
+        let result = orderbook.add_order(order);
+        orderbook.record_trades(self.clock, &result.fills);
+        let tripped = orderbook.breaker_tripped(self.clock);
+
+        //updating balance based on fills
+        self.update_balance(order, &base_asset, &quote_asset, &scale, &result.fills);
+
+        //a fill on a grouped order resolves its group
+        self.settle_groups(order, &result, market);
+
+        //market orders cancel their remainder instead of resting
+        if order.order_type.is_market() && order.status == OrderStatus::Cancelled {
+            let (asset, amount) = match order.side {
+                OrderSide::Buy => (
+                    &quote_asset,
+                    self.buy_lock(&quote_asset, &scale, order.price, order.remaining()),
+                ),
+                OrderSide::Sell => (&base_asset, scale.quantity(order.remaining())),
+            };
+            self.release_funds(&order.user_id, asset, amount);
+        }
+
+        //creating database record for trades
+        self.create_db_trades(&result.fills, market);
+
+        //updating database
+        self.update_db_orders(order, &result.fills, &result.filled_orders, market);
+
+        //orders which left the book are kept around for status queries
+        for filled in result.filled_orders {
+            self.recent_orders.insert(filled, scale);
+        }
+        if order.status.is_terminal() {
+            self.recent_orders.insert(order.clone(), scale);
+        }
+
+        //publish websocket depth updates
+
+        self.publish_ws_depth_updates(&result.fills, order.price, &order.side, market);
+
+        //publish websocket trades
+        self.publish_ws_trades(&result.fills, &order.user_id, market);
+
+        if tripped {
+            self.trip_circuit_breaker(market);
+        }
+
+        Ok(result.fills)
+    }
+
+    // moves resting pegs after their reference changed, buy pegs lock funds at their current
+    // price so moving up needs the difference and a peg without it stays where it is
+    fn reprice_pegged(&mut self, market: &str) {
+        let Some(book) = self.orderbooks.iter().position(|o| o.ticker() == market) else {
+            return;
+        };
+
+        let updates = self.orderbooks[book].peg_updates();
+        if updates.is_empty() {
+            return;
+        }
+
+        let quote_asset = self.orderbooks[book].quote_asset.clone();
+        let scale = self.orderbooks[book].config.scale;
+        let mut prices: Vec<Ticks> = Vec::new();
+
+        for (order_id, price) in updates {
+            let Some(order) = self.orderbooks[book].get_order(&order_id).cloned() else {
+                continue;
+            };
+
+            if order.side == OrderSide::Buy
+                && self
+                    .adjust_locked_funds(
+                        &order.user_id,
+                        &quote_asset,
+                        self.buy_lock(&quote_asset, &scale, price, order.remaining())
+                            - self.buy_lock(&quote_asset, &scale, order.price, order.remaining()),
+                    )
+                    .is_err()
+            {
+                continue;
+            }
+
+            //a peg that moves queues behind the orders already at its new price
+            let orderbook = &mut self.orderbooks[book];
+            if let Some(mut resting) = orderbook.remove_order(&order_id) {
+                resting.price = price;
+                match resting.side {
+                    OrderSide::Buy => orderbook.bids.push(resting),
+                    OrderSide::Sell => orderbook.asks.push(resting),
+                }
+            }
+
+            if !order.is_hidden() {
+                for level in [order.price, price] {
+                    if !prices.contains(&level) {
+                        prices.push(level);
+                    }
+                }
+            }
+        }
+
+        if !prices.is_empty() {
+            self.send_updated_depth_at(&prices, market);
+        }
+    }
+
+    //moves funds from locked back to available
+    //base and quote asset of a market, taken from its book rather than parsed from the ticker
+    fn market_assets(&self, market: &str) -> Result<(String, String), Box<dyn std::error::Error>> {
+        self.orderbooks
+            .iter()
+            .find(|o| o.ticker() == market)
+            .map(|o| (o.base_asset.clone(), o.quote_asset.clone()))
+            .ok_or_else(|| "No orderbook found".into())
+    }
+
+    //tick and lot size of a market, the default for unknown markets which have no orders to convert
+    fn scale(&self, market: &str) -> Scale {
+        self.orderbooks
+            .iter()
+            .find(|o| o.ticker() == market)
+            .map(|o| o.config.scale)
+            .unwrap_or_default()
+    }
+
+    //quote a buy holds for a quantity at its limit, rounded up to the quote's precision
+    fn buy_lock(&self, quote_asset: &str, scale: &Scale, price: Ticks, quantity: Lots) -> Decimal {
+        let amount = scale.notional(price, quantity);
+        self.assets
+            .get(quote_asset)
+            .map_or(amount, |asset| asset.round_up(amount))
+    }
+
+    //quote moving from buyer to seller for a trade, rounded down
+    fn trade_value(
+        &self,
+        quote_asset: &str,
+        scale: &Scale,
+        price: Ticks,
+        quantity: Lots,
+    ) -> Decimal {
+        let amount = scale.notional(price, quantity);
+        self.assets
+            .get(quote_asset)
+            .map_or(amount, |asset| asset.normalize(amount))
+    }
+
+    fn asset_balance(&mut self, user_id: &str, asset: &str) -> &mut AssetBalance {
+        self.balances
+            .entry(user_id.to_string())
+            .or_default()
+            .entry(asset.to_string())
+            .or_insert_with(|| AssetBalance::new(Decimal::ZERO, Decimal::ZERO))
+    }
+
+    fn release_funds(&mut self, user_id: &str, asset: &str, amount: Decimal) {
+        if amount <= Decimal::ZERO {
+            return;
+        }
+
+        if let Some(balance) = self.balances.get_mut(user_id)
+            && let Some(asset_balance) = balance.get_mut(asset)
+        {
+            asset_balance.available += amount;
+            asset_balance.locked -= amount;
+        }
+    }
+
+    fn handle_amend_order(&mut self, data: AmendOrderData, client_id: &str) {
+        let order_id = self
+            .resolve_order_id(
+                data.order_id.clone(),
+                data.client_order_id.clone(),
+                data.user_id.clone(),
+            )
+            .unwrap_or_default();
+
+        let scale = self.scale(&data.market);
+        let message = match self.amend_order(&order_id, &data) {
+            Ok((order, fills)) => MessageToApi::OrderAmended {
+                payload: OrderAmendedPayload {
+                    order_id: order.order_id,
+                    price: scale.price(order.price).to_string(),
+                    quantity: scale.quantity(order.quantity).to_string(),
+                    executed_qty: scale.quantity(order.filled),
+                    fills: fills.iter().map(|f| fill_info(&scale, f)).collect(),
+                },
+            },
+            Err(e) => {
+                error!("Failed to amend order {}: {}", order_id, e);
+
+                MessageToApi::OrderRejected {
+                    payload: OrderRejectedPayload {
+                        order_id,
+                        reason: e.to_string(),
+                        code: rejection_code(e.as_ref()),
+                    },
+                }
+            }
+        };
+
+        self.send_to_api(client_id, message);
+    }
+
+    // reducing quantity at the same price keeps time priority,
+    // a price change or a quantity increase sends the order to the back of the queue
+    fn amend_order(
+        &mut self,
+        order_id: &str,
+        data: &AmendOrderData,
+    ) -> Result<(Order, Vec<Fill>), Box<dyn std::error::Error>> {
+        let market = data.market.as_str();
+        let (base_asset, quote_asset) = self.market_assets(market)?;
+
+        let orderbook = self
+            .orderbooks
+            .iter()
+            .find(|o| o.ticker() == market)
+            .ok_or("No orderbook found")?;
+        if !orderbook.status.accepts_orders() {
+            return Err(format!("Market is {}", orderbook.status.as_str()).into());
+        }
+
+        let mut order = orderbook
+            .get_order(order_id)
+            .cloned()
+            .ok_or("Order not found")?;
+
+        if orderbook.conditional.iter().any(|o| o.order_id == order_id) {
+            return Err("Untriggered stop orders cannot be amended".into());
+        }
+        if order
+            .group_id
+            .as_ref()
+            .is_some_and(|g| orderbook.groups.get(g).is_some())
+        {
+            return Err("Grouped orders cannot be amended".into());
+        }
+        if order.peg.is_some() && data.price.is_some() {
+            return Err("Pegged orders follow their reference price".into());
+        }
+
+        let scale = orderbook.config.scale;
+        let price = match &data.price {
+            Some(price) => scale.to_ticks(Decimal::from_str(price)?, &order.side)?,
+            None => order.price,
+        };
+        let quantity = match &data.quantity {
+            Some(quantity) => normalize_quantity(&scale, Decimal::from_str(quantity)?)?,
+            None => order.quantity,
+        };
+
+        if price <= Ticks::ZERO {
+            return Err("Invalid price".into());
+        }
+        if scale.checked_notional(price, quantity).is_none() {
+            return Err("Invalid quantity".into());
+        }
+        if quantity <= order.filled {
+            return Err("Order already filled past the new quantity".into());
+        }
+        if price != order.price {
+            apply_price_band(orderbook, &order, price, self.clock)?;
+        }
+
+        //growing an order goes through the same limits as placing it
+        let grows = quantity > order.quantity
+            || scale.notional(price, quantity) > scale.notional(order.price, order.quantity);
+        if grows {
+            let user_id = order.user_id.clone();
+            self.check_risk(&user_id, market, price, quantity, Some(order_id))?;
+        }
+
+        //locked funds move by the difference between the old and new remaining requirement
+        let (asset, delta) = match order.side {
+            OrderSide::Buy => (
+                &quote_asset,
+                self.buy_lock(&quote_asset, &scale, price, quantity - order.filled)
+                    - self.buy_lock(&quote_asset, &scale, order.price, order.remaining()),
+            ),
+            OrderSide::Sell => (&base_asset, scale.quantity(quantity - order.quantity)),
+        };
+        self.adjust_locked_funds(&order.user_id, asset, delta)?;
+        if grows {
+            self.risk.record(&order.user_id, self.clock);
+        }
+
+        let old_price = order.price;
+        let keeps_priority = price == order.price && quantity <= order.quantity;
+
+        order.price = price;
+        order.quantity = quantity;
+        order.updated_at = self.clock;
+
+        let fills = if keeps_priority {
+            if let Some(resting) = self
+                .orderbooks
+                .iter_mut()
+                .find(|o| o.ticker() == market)
+                .and_then(|o| o.get_order_mut(order_id))
+            {
+                resting.quantity = quantity;
+                resting.updated_at = order.updated_at;
+            }
+
+            self.push_order_status(&order, &scale);
+            self.send_updated_depth_at(&[price], market);
+            self.book_changed(market);
+
+            Vec::new()
+        } else {
+            if let Some(orderbook) = self.orderbooks.iter_mut().find(|o| o.ticker() == market) {
+                match order.side {
+                    OrderSide::Buy => orderbook.cancel_bid(&order),
+                    OrderSide::Sell => orderbook.cancel_ask(&order),
+                };
+            }
+
+            let fills = self.match_order(&mut order, market)?;
+
+            if old_price != price {
+                self.send_updated_depth_at(&[old_price], market);
+            }
+
+            fills
+        };
+
+        Ok((order, fills))
+    }
+
+    //moves funds between available and locked, positive delta locks more
+    fn adjust_locked_funds(
+        &mut self,
+        user_id: &str,
+        asset: &str,
+        delta: Decimal,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let asset_balance = self
+            .balances
+            .get_mut(user_id)
+            .and_then(|balance| balance.get_mut(asset))
+            .ok_or("Insufficient funds")?;
+
+        if asset_balance.available < delta {
+            return Err("Insufficient funds".into());
+        }
+
+        asset_balance.available -= delta;
+        asset_balance.locked += delta;
+
+        Ok(())
+    }
+
+    fn reject_order(&mut self, mut order: Order, market: &str) {
+        order.close(OrderStatus::Rejected, order.created_at);
+        let scale = self.scale(market);
+
+        let message = DbMessage::OrderUpdate {
+            data: OrderUpdateData {
+                order_id: order.order_id.clone(),
+                executed_qty: scale.quantity(order.filled).to_string(),
+                market: Some(market.to_string()),
+                price: Some(scale.price(order.price).to_string()),
+                quantity: Some(scale.quantity(order.quantity).to_string()),
+                side: Some(order.side.as_str().to_string()),
+                client_order_id: order.client_order_id.clone(),
+                order_type: Some(order.order_type),
+                trigger_price: order.trigger_price.map(|p| scale.price(p).to_string()),
+                group_id: order.group_id.clone(),
+                status: Some(order.status),
+                updated_at: Some(order.updated_at),
+                ..Default::default()
+            },
+        };
+
+        self.push_message(message);
+
+        self.recent_orders.insert(order, scale);
+    }
+
+    //open orders and open value the user would have with a new order in the market, the value
+    //only counts markets quoted in the same asset
+    //an amend replaces the order it changes, so that one is left out of what is already open
+    fn exposure(
+        &self,
+        user_id: &str,
+        market: &str,
+        price: Ticks,
+        quantity: Lots,
+        replacing: Option<&str>,
+    ) -> Exposure {
+        let scale = self.scale(market);
+        let quote_asset = self
+            .market_assets(market)
+            .map(|(_, quote_asset)| quote_asset)
+            .unwrap_or_default();
+        let notional = scale.notional(price, quantity);
+        let mut open_orders = 1;
+        let mut open_notional = notional;
+
+        for orderbook in &self.orderbooks {
+            for order in orderbook
+                .orders()
+                .filter(|o| o.user_id == user_id && Some(o.order_id.as_str()) != replacing)
+            {
+                if orderbook.ticker() == market {
+                    open_orders += 1;
+                }
+                if orderbook.quote_asset == quote_asset {
+                    open_notional += orderbook
+                        .config
+                        .scale
+                        .notional(order.price, order.remaining());
+                }
+            }
+        }
+
+        Exposure {
+            quantity: scale.quantity(quantity),
+            notional,
+            open_orders,
+            quote_asset,
+            open_notional,
+            pending: 0,
+        }
+    }
+
+    //user limits are checked before any funds are locked, the order only counts towards the rate
+    //once it has been accepted
+    fn check_risk(
+        &mut self,
+        user_id: &str,
+        market: &str,
+        price: Ticks,
+        quantity: Lots,
+        replacing: Option<&str>,
+    ) -> Result<(), RiskRejection> {
+        let exposure = self.exposure(user_id, market, price, quantity, replacing);
+        self.risk.check(user_id, &exposure, self.clock)
+    }
+
+    fn handle_set_risk_limits(&mut self, data: RiskLimitsData, client_id: &str) {
+        self.risk
+            .set_limits(data.user_id.as_deref(), data.limits.clone());
+
+        let message = MessageToApi::RiskLimits {
+            payload: RiskLimitsPayload {
+                user_id: data.user_id,
+                limits: data.limits,
+            },
+        };
+
+        self.send_to_api(client_id, message);
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn check_and_lock_funds(
+        &mut self,
+        base_asset: &str,
+        quote_asset: &str,
+        scale: &Scale,
+        side: &OrderSide,
+        user_id: &str,
+        price: Ticks,
+        quantity: Lots,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        match side {
+            OrderSide::Buy => {
+                let required_funds = self.buy_lock(quote_asset, scale, price, quantity);
 
                 // Get or create user balance
                 if !self.balances.contains_key(user_id) {
@@ -255,6 +1568,8 @@ impl Engine {
                 asset_balance.locked += required_funds;
             }
             OrderSide::Sell => {
+                let quantity = scale.quantity(quantity);
+
                 // Get or create user balance
                 if !self.balances.contains_key(user_id) {
                     self.balances.insert(user_id.to_string(), HashMap::new());
@@ -286,289 +1601,236 @@ impl Engine {
         Ok(())
     }
 
+    // settles an order's fills, a buyer's lock is released in step with its remaining quantity so
+    // whatever the lock rounded up comes back by the time the order is done
     fn update_balance(
         &mut self,
-        user_id: &str,
+        order: &Order,
         base_asset: &str,
         quote_asset: &str,
-        side: &OrderSide,
+        scale: &Scale,
         fills: &[Fill],
-        // executed_qty: Decimal,
     ) {
-        match side {
-            OrderSide::Buy => {
-                for fill in fills {
-                    let fill_price = Decimal::from_str(&fill.price).unwrap_or(Decimal::ZERO);
-                    let fill_value = fill.qty * fill_price;
-
-                    // asset , base , quote
-
-                    // seller gets quote currency
-                    if let Some(maker_balance) = self.balances.get_mut(&fill.other_user_id) {
-                        if let Some(asset_balance) = maker_balance.get_mut(quote_asset) {
-                            asset_balance.available += fill_value;
-                        }
-                    }
-
-                    //buyers locked funds are decreased
-                    if let Some(taker_balance) = self.balances.get_mut(user_id) {
-                        if let Some(asset_balance) = taker_balance.get_mut(quote_asset) {
-                            asset_balance.locked -= fill_value;
-                        }
-                    }
-
-                    //seller locked base decreases
-                    if let Some(maker_balance) = self.balances.get_mut(&fill.other_user_id) {
-                        if let Some(asset_balance) = maker_balance.get_mut(base_asset) {
-                            asset_balance.locked -= fill.qty;
-                        }
-                    }
+        let mut remaining = order.remaining() + fills.iter().map(|f| f.qty).sum::<Lots>();
 
-                    //buyers get base currency
-                    if let Some(taker_balance) = self.balances.get_mut(user_id) {
-                        if let Some(asset_balance) = taker_balance.get_mut(base_asset) {
-                            asset_balance.available += fill.qty;
-                        } else {
-                            //create base asset balance if it doesn't exist
-                            taker_balance.insert(
-                                base_asset.to_string(),
-                                AssetBalance {
-                                    available: fill.qty,
-                                    locked: Decimal::ZERO,
-                                },
-                            );
-                        }
-                    }
-                }
-            }
-            OrderSide::Sell => {
-                for fill in fills {
-                    let fill_price = Decimal::from_str(&fill.price).unwrap_or(Decimal::ZERO);
-                    let fill_value = fill_price * fill.qty;
-
-                    //buyer locked quote get decrease
-                    if let Some(maker_balance) = self.balances.get_mut(&fill.other_user_id) {
-                        if let Some(asset_balance) = maker_balance.get_mut(quote_asset) {
-                            asset_balance.locked -= fill_value;
-                        }
-                    }
+        for fill in fills {
+            let value = self.trade_value(quote_asset, scale, fill.price, fill.qty);
+            let quantity = scale.quantity(fill.qty);
+            remaining -= fill.qty;
 
-                    //seller get quote currency
-                    if let Some(taker_balance) = self.balances.get_mut(user_id) {
-                        if let Some(asset_balance) = taker_balance.get_mut(quote_asset) {
-                            asset_balance.available += fill_value;
-                        } else {
-                            //creating quote asset balance if not exits
-                            taker_balance.insert(
-                                quote_asset.to_string(),
-                                AssetBalance {
-                                    available: fill_value,
-                                    locked: Decimal::ZERO,
-                                },
-                            );
-                        }
-                    }
+            //resting buys are locked at their own price, which is the fill price
+            let (buyer, seller, released) = match order.side {
+                OrderSide::Buy => (
+                    &order.user_id,
+                    &fill.other_user_id,
+                    self.buy_lock(quote_asset, scale, order.price, remaining + fill.qty)
+                        - self.buy_lock(quote_asset, scale, order.price, remaining),
+                ),
+                OrderSide::Sell => (
+                    &fill.other_user_id,
+                    &order.user_id,
+                    self.buy_lock(
+                        quote_asset,
+                        scale,
+                        fill.price,
+                        fill.maker_remaining + fill.qty,
+                    ) - self.buy_lock(quote_asset, scale, fill.price, fill.maker_remaining),
+                ),
+            };
 
-                    //buyer get base currency
-                    if let Some(maker_balance) = self.balances.get_mut(&fill.other_user_id) {
-                        if let Some(asset_balance) = maker_balance.get_mut(base_asset) {
-                            asset_balance.available += fill.qty;
-                        }
-                    }
+            //buyer pays the trade value out of its lock and gets back whatever was held beyond it
+            let buyer_quote = self.asset_balance(buyer, quote_asset);
+            buyer_quote.locked -= released;
+            buyer_quote.available += released - value;
+            self.asset_balance(buyer, base_asset).available += quantity;
 
-                    //sellers locked base decrease
-                    if let Some(taker_balance) = self.balances.get_mut(user_id) {
-                        if let Some(asset_balance) = taker_balance.get_mut(base_asset) {
-                            asset_balance.locked -= fill.qty;
-                        }
-                    }
-                }
-            }
+            self.asset_balance(seller, base_asset).locked -= quantity;
+            self.asset_balance(seller, quote_asset).available += value;
         }
     }
 
-    async fn create_db_trades(&self, fills: &[Fill], market: &str) {
-        let redis_manager = RedisManager::get_instance();
+    fn create_db_trades(&mut self, fills: &[Fill], market: &str) {
+        let scale = self.scale(market);
 
         for fill in fills {
-            let qty_decimal = fill.qty;
-            let price_decimal = Decimal::from_str(&fill.price).unwrap_or(Decimal::ZERO);
-            let quote_qty = qty_decimal * price_decimal;
+            let quote_qty = match self.market_assets(market) {
+                Ok((_, quote_asset)) => {
+                    self.trade_value(&quote_asset, &scale, fill.price, fill.qty)
+                }
+                Err(_) => scale.notional(fill.price, fill.qty),
+            };
 
             let message = DbMessage::TradeAdded {
                 data: TradeAddedData {
                     id: fill.trade_id.to_string(),
                     is_buyer_maket: true, //todo here to check if this is correct
-                    price: fill.price.clone(),
-                    quantity: fill.qty.to_string(),
+                    price: scale.price(fill.price).to_string(),
+                    quantity: scale.quantity(fill.qty).to_string(),
                     quote_quantity: quote_qty.to_string(),
-                    timestamp: std::time::SystemTime::now()
-                        .duration_since(std::time::UNIX_EPOCH)
-                        .unwrap_or_default()
-                        .as_millis() as u64,
+                    timestamp: self.clock,
                     market: market.to_string(),
                 },
             };
 
-            if let Err(e) = redis_manager.push_message(message).await {
-                error!("Failed to push trade added message: {}", e)
-            }
+            self.push_message(message);
         }
     }
 
-    async fn update_db_orders(
+    fn update_db_orders(
         &mut self,
         ordr: &Order,
-        executed_qty: Decimal,
         fills: &[Fill],
+        filled_orders: &[Order],
         market: &str,
     ) {
-        let redis_manager = RedisManager::get_instance();
+        let scale = self.scale(market);
 
         //updating the taker message
         let message = DbMessage::OrderUpdate {
             data: OrderUpdateData {
                 order_id: ordr.order_id.clone(),
-                executed_qty: executed_qty.to_f64().unwrap_or(0.0),
+                executed_qty: scale.quantity(ordr.filled).to_string(),
                 market: Some(market.to_string()),
-                price: Some(ordr.price.to_string()),
-                quantity: Some(ordr.quantity.to_string()),
-                side: Some(match ordr.side {
-                    OrderSide::Buy => "buy".to_string(),
-                    OrderSide::Sell => "sell".to_string(),
-                }),
+                price: Some(scale.price(ordr.price).to_string()),
+                quantity: Some(scale.quantity(ordr.quantity).to_string()),
+                side: Some(ordr.side.as_str().to_string()),
+                client_order_id: ordr.client_order_id.clone(),
+                order_type: Some(ordr.order_type),
+                trigger_price: ordr.trigger_price.map(|p| scale.price(p).to_string()),
+                group_id: ordr.group_id.clone(),
+                expire_at: ordr.expire_at,
+                status: Some(ordr.status),
+                avg_fill_price: Some(
+                    scale
+                        .average_price(ordr.fill_value, ordr.filled)
+                        .to_string(),
+                ),
+                updated_at: Some(ordr.updated_at),
             },
         };
 
-        if let Err(e) = redis_manager.push_message(message).await {
-            error!("Failed to push order update message: {}", e);
-        }
+        self.push_message(message);
 
         //update maker order
         for fill in fills {
-            let message = DbMessage::OrderUpdate {
-                data: OrderUpdateData {
-                    order_id: fill.marker_order_id.clone(),
-                    executed_qty: fill.qty.to_f64().unwrap_or(0.0),
-                    market: None,
-                    price: None,
-                    quantity: None,
-                    side: None,
-                },
+            let status = if filled_orders
+                .iter()
+                .any(|o| o.order_id == fill.marker_order_id)
+            {
+                OrderStatus::Filled
+            } else {
+                OrderStatus::PartiallyFilled
             };
 
-            if let Err(e) = redis_manager.push_message(message).await {
-                error!("Failed to push update message for fill: {}", e);
-            }
+            let message = DbMessage::OrderUpdate {
+                data: OrderUpdateData {
+                    order_id: fill.marker_order_id.clone(),
+                    executed_qty: scale.quantity(fill.qty).to_string(),
+                    status: Some(status),
+                    updated_at: Some(ordr.updated_at),
+                    ..Default::default()
+                },
+            };
+
+            self.push_message(message);
         }
     }
 
-    async fn publish_ws_depth_updates(
-        &self,
+    fn publish_ws_depth_updates(
+        &mut self,
         fills: &[Fill],
-        price: &str,
+        price: Ticks,
         side: &OrderSide,
         market: &str,
     ) {
         if let Some(orderbook) = self.orderbooks.iter().find(|o| o.ticker() == market) {
+            let scale = orderbook.config.scale;
             let (bids, asks) = orderbook.get_depth();
+            let fill_prices: Vec<Ticks> = fills.iter().map(|f| f.price).collect();
 
             match side {
                 OrderSide::Buy => {
-                    let fill_prices: Vec<String> = fills.iter().map(|f| f.price.clone()).collect();
-                    let updated_asks: Vec<(String, String)> = asks
+                    let updated_asks: Vec<DepthLevel> = asks
                         .into_iter()
                         .filter(|(p, _)| fill_prices.contains(p))
                         .collect();
+                    let updated_asks = wire_levels(&scale, updated_asks);
 
-                    let updated_bids = bids.into_iter().find(|(p, _)| p == price);
-
-                    let redis_manager = RedisManager::get_instance();
+                    let updated_bids = wire_levels(
+                        &scale,
+                        bids.into_iter().filter(|(p, _)| *p == price).collect(),
+                    );
 
                     let message = WsMessage::DepthUpdate(DepthUpdateMessage {
                         stream: format!("depth@{}", market),
                         data: DepthUpdateData {
                             a: Some(updated_asks),
-                            b: updated_bids.map(|b| vec![b]).or(Some(vec![])),
+                            b: Some(updated_bids),
                             e: "depth".to_string(),
                         },
                     });
-                    if let Err(e) = redis_manager
-                        .publish_message(&format!("depth@{}", market), message)
-                        .await
-                    {
-                        error!("Failed to publish depth update message: {}", e);
-                    }
+                    self.publish_message(&format!("depth@{}", market), message);
                 }
                 OrderSide::Sell => {
-                    let fill_prices: Vec<String> = fills.iter().map(|f| f.price.clone()).collect();
-                    let updated_bids: Vec<(String, String)> = bids
+                    let updated_bids: Vec<DepthLevel> = bids
                         .into_iter()
                         .filter(|(p, _)| fill_prices.contains(p))
                         .collect();
+                    let updated_bids = wire_levels(&scale, updated_bids);
 
-                    let updated_asks = asks.into_iter().find(|(p, _)| p == price);
-                    let redis_manager = RedisManager::get_instance();
+                    let updated_asks = wire_levels(
+                        &scale,
+                        asks.into_iter().filter(|(p, _)| *p == price).collect(),
+                    );
                     let message = WsMessage::DepthUpdate(DepthUpdateMessage {
                         stream: format!("depth@{}", market),
                         data: DepthUpdateData {
-                            a: updated_asks.map(|a| vec![a]).or(Some(vec![])),
+                            a: Some(updated_asks),
                             b: Some(updated_bids),
                             e: "depth".to_string(),
                         },
                     });
 
-                    if let Err(e) = redis_manager
-                        .publish_message(&format!("depth@{}", market), message)
-                        .await
-                    {
-                        error!("Failed to publish depth message: {}", e);
-                    }
+                    self.publish_message(&format!("depth@{}", market), message);
                 }
             }
         }
     }
 
-    async fn send_updated_depth_at(&self, price: &str, market: &str) {
+    fn send_updated_depth_at(&mut self, prices: &[Ticks], market: &str) {
         if let Some(orderbook) = self.orderbooks.iter().find(|o| o.ticker() == market) {
+            let scale = orderbook.config.scale;
             let (bids, asks) = orderbook.get_depth();
 
-            let updated_bids: Vec<(String, String)> =
-                bids.into_iter().filter(|(p, _)| p == price).collect();
-
-            let updated_asks: Vec<(String, String)> =
-                asks.into_iter().filter(|(p, _)| p == price).collect();
+            //levels which no longer exist are sent with a zero quantity
+            let level = |levels: &[DepthLevel], price: &Ticks| {
+                levels
+                    .iter()
+                    .find(|(p, _)| p == price)
+                    .copied()
+                    .unwrap_or((*price, Lots::ZERO))
+            };
 
-            let redis_manager = RedisManager::get_instance();
+            let updated_bids =
+                wire_levels(&scale, prices.iter().map(|p| level(&bids, p)).collect());
+            let updated_asks =
+                wire_levels(&scale, prices.iter().map(|p| level(&asks, p)).collect());
 
             let message = WsMessage::DepthUpdate(DepthUpdateMessage {
                 stream: format!("depth@{}", market),
                 data: DepthUpdateData {
-                    a: if updated_asks.is_empty() {
-                        Some(vec![(price.to_string(), "0".to_string())])
-                    } else {
-                        Some(updated_asks)
-                    },
-                    b: if (updated_bids.is_empty()) {
-                        Some(vec![(price.to_string(), "0".to_string())])
-                    } else {
-                        Some(updated_bids)
-                    },
+                    a: Some(updated_asks),
+                    b: Some(updated_bids),
                     e: "depth".to_string(),
                 },
             });
 
-            if let Err(e) = redis_manager
-                .publish_message(&format!("depth@{}", market), message)
-                .await
-            {
-                error!("Failed to publish depth update message: {}", e);
-            }
+            self.publish_message(&format!("depth@{}", market), message);
         }
     }
 
-    async fn publish_ws_trades(&self, fills: &[Fill], user_id: &str, market: &str) {
-        let redis_manager = RedisManager::get_instance();
+    fn publish_ws_trades(&mut self, fills: &[Fill], user_id: &str, market: &str) {
+        let scale = self.scale(market);
 
         for fill in fills {
             let message = WsMessage::TradeAdded(TradeAddedMessage {
@@ -577,155 +1839,3590 @@ impl Engine {
                     e: "trade".to_string(),
                     t: fill.trade_id,
                     m: fill.other_user_id == user_id,
-                    p: fill.price.clone(),
-                    q: fill.qty.to_string(),
+                    p: scale.price(fill.price).to_string(),
+                    q: scale.quantity(fill.qty).to_string(),
                     s: market.to_string(),
                 },
             });
 
-            if let Err(e) = redis_manager
-                .publish_message(&format!("trade@{}", market), message)
-                .await
-            {
-                error!("Failed to publish trade message: {}", e);
-            }
+            self.publish_message(&format!("trade@{}", market), message);
         }
     }
 
-    async fn handle_cancel_order(&mut self, data: CancelOrderDAta, client_id: &str) {
-        let order_id = data.order_id;
+    fn handle_cancel_order(&mut self, data: CancelOrderDAta, client_id: &str) {
+        let message = self.cancel_order(data);
+
+        self.send_to_api(client_id, message);
+    }
+
+    fn cancel_order(&mut self, data: CancelOrderDAta) -> MessageToApi {
         let market = data.market;
+        let order_id = self
+            .resolve_order_id(data.order_id, data.client_order_id, data.user_id)
+            .unwrap_or_default();
+
+        if let Some(orderbook) = self.orderbooks.iter().find(|o| o.ticker() == market)
+            && !orderbook.status.accepts_cancels()
+        {
+            return MessageToApi::OrderRejected {
+                payload: OrderRejectedPayload {
+                    order_id,
+                    reason: format!("Market is {}", orderbook.status.as_str()),
+                    code: None,
+                },
+            };
+        }
+
+        let (mut cancelled, group_id) =
+            match self.orderbooks.iter().position(|o| o.ticker() == market) {
+                Some(book) => match data
+                    .group_id
+                    .filter(|g| self.orderbooks[book].groups.get(g).is_some())
+                {
+                    Some(group_id) => (
+                        self.cancel_group(book, &group_id, OrderStatus::Cancelled),
+                        Some(group_id),
+                    ),
+                    None => self.cancel_with_group(book, &order_id, OrderStatus::Cancelled),
+                },
+                None => {
+                    error!("Orderbook not found for market: {}", market);
+                    (Vec::new(), None)
+                }
+            };
+
+        let scale = self.scale(&market);
+
+        //cancelling any member of a live group takes the whole group down
+        if let Some(group_id) = group_id {
+            let mut prices: Vec<Ticks> = Vec::new();
+            let mut order_ids = Vec::new();
+
+            for order in cancelled {
+                self.push_order_status(&order, &scale);
+
+                if !prices.contains(&order.price) {
+                    prices.push(order.price);
+                }
+
+                order_ids.push(order.order_id.clone());
+                self.recent_orders.insert(order, scale);
+            }
+
+            if !prices.is_empty() {
+                self.send_updated_depth_at(&prices, &market);
+            }
+            self.book_changed(&market);
+
+            return MessageToApi::OrdersCancelled {
+                payload: OrdersCancelledPayload {
+                    order_ids,
+                    group_ids: vec![group_id],
+                },
+            };
+        }
+
+        let Some(order) = cancelled.pop() else {
+            error!("Order not found: {}", order_id);
+
+            return MessageToApi::OrderRejected {
+                payload: OrderRejectedPayload {
+                    order_id,
+                    reason: "Order not found".to_string(),
+                    code: None,
+                },
+            };
+        };
+
+        self.push_order_status(&order, &scale);
+
+        // Update depth if price level changed
+        self.send_updated_depth_at(&[order.price], &market);
+        self.book_changed(&market);
+
+        let message = MessageToApi::OrderCancelled {
+            payload: OrderCancelledPayload {
+                order_id,
+                executed_qty: scale.quantity(order.filled),
+                remaining_qty: scale.quantity(order.remaining()),
+            },
+        };
+
+        self.recent_orders.insert(order, scale);
+
+        message
+    }
+
+    fn handle_batch_orders(&mut self, data: BatchOrdersData, client_id: &str) {
+        let message = if data.orders.len() > MAX_BATCH_ORDERS {
+            MessageToApi::OrderRejected {
+                payload: OrderRejectedPayload {
+                    order_id: String::new(),
+                    reason: format!("Batch exceeds {} orders", MAX_BATCH_ORDERS),
+                    code: None,
+                },
+            }
+        } else {
+            MessageToApi::BatchResults {
+                payload: self.process_batch(data),
+            }
+        };
 
-        if let Some(orderbook) = self.orderbooks.iter_mut().find(|o| o.ticker() == market) {
-            let quote_asset = market.split('_').nth(1).unwrap_or(BASE_CURRENCY);
+        self.send_to_api(client_id, message);
+    }
 
-            // Find the order in either bids or asks
-            let order = orderbook
-                .bids
+    //items run one after another within this engine step, each reports its own result
+    fn process_batch(&mut self, data: BatchOrdersData) -> Vec<MessageToApi> {
+        if data.all_or_none
+            && let Err((index, reason)) = self.validate_batch(&data.orders)
+        {
+            return data
+                .orders
                 .iter()
-                .find(|o| o.order_id == order_id)
-                .or_else(|| orderbook.asks.iter().find(|o| o.order_id == order_id))
-                .cloned();
-
-            if let Some(order) = order {
-                let price_opt = if order.side == OrderSide::Buy {
-                    // Cancel bid
-                    let price = orderbook.cancel_bid(&order);
-                    // Unlock funds
-                    if let Some(balance) = self.balances.get_mut(&order.user_id) {
-                        if let Some(asset_balance) = balance.get_mut(BASE_CURRENCY) {
-                            let left_quantity = (order.quantity - order.filled) * order.price;
-                            asset_balance.available += left_quantity;
-                            asset_balance.locked -= left_quantity;
-                        }
+                .enumerate()
+                .map(|(i, operation)| MessageToApi::OrderRejected {
+                    payload: OrderRejectedPayload {
+                        order_id: operation.reference(),
+                        reason: if i == index {
+                            reason.clone()
+                        } else {
+                            "Batch rejected".to_string()
+                        },
+                        code: None,
+                    },
+                })
+                .collect();
+        }
+
+        let mut results = Vec::with_capacity(data.orders.len());
+
+        for operation in data.orders {
+            let result = match self.check_batch_item(&operation) {
+                Err(rejection) => MessageToApi::OrderRejected {
+                    payload: OrderRejectedPayload {
+                        order_id: operation.reference(),
+                        reason: rejection.to_string(),
+                        code: Some(rejection.code().to_string()),
+                    },
+                },
+                Ok(()) => match operation {
+                    BatchOperation::Create(data) => self.place_order(&data),
+                    BatchOperation::Cancel(data) => self.cancel_order(data),
+                },
+            };
+            results.push(result);
+        }
+
+        results
+    }
+
+    // dry run for all-or-none batches, funds needed by the creates are accumulated per user and asset
+    // and each create counts against the risk limits of the ones before it, so the batch as a whole
+    // has to fit, returns the index of the first failing item
+    fn validate_batch(&self, operations: &[BatchOperation]) -> Result<(), (usize, String)> {
+        let mut required: HashMap<(String, String), Decimal> = HashMap::new();
+        let mut pending: HashMap<String, usize> = HashMap::new();
+        let mut pending_orders: HashMap<(String, String), usize> = HashMap::new();
+        let mut pending_notional: HashMap<(String, String), Decimal> = HashMap::new();
+
+        for (index, operation) in operations.iter().enumerate() {
+            self.check_batch_item(operation)
+                .map_err(|e| (index, e.to_string()))?;
+
+            match operation {
+                BatchOperation::Create(data) => {
+                    if data
+                        .client_order_id
+                        .as_ref()
+                        .is_some_and(|c| self.duplicate_submission(&data.user_id, c).is_some())
+                    {
+                        continue;
                     }
-                    price
-                } else {
-                    // Cancel ask
-                    let price = orderbook.cancel_ask(&order);
-                    // Unlock funds
-                    if let Some(balance) = self.balances.get_mut(&order.user_id) {
-                        if let Some(asset_balance) = balance.get_mut(quote_asset) {
-                            let left_quantity = order.quantity - order.filled;
-                            asset_balance.available += left_quantity;
-                            asset_balance.locked -= left_quantity;
-                        }
+
+                    let orderbook = self
+                        .orderbooks
+                        .iter()
+                        .find(|o| o.ticker() == data.market)
+                        .ok_or((index, "No orderbook found".to_string()))?;
+                    if !orderbook.status.accepts_orders() {
+                        return Err((index, format!("Market is {}", orderbook.status.as_str())));
                     }
-                    price
-                };
+                    if orderbook.phase.is_auction() && data.order_type == OrderType::Market {
+                        return Err((
+                            index,
+                            "Market orders are not accepted during auctions".to_string(),
+                        ));
+                    }
+                    if orderbook.config.batch.is_some() && data.order_type.is_conditional() {
+                        return Err((
+                            index,
+                            "Stop orders are not supported on batch markets".to_string(),
+                        ));
+                    }
+
+                    let scale = orderbook.config.scale;
+                    let quantity = Decimal::from_str(&data.quantity)
+                        .map_err(|e| e.into())
+                        .and_then(|quantity| normalize_quantity(&scale, quantity))
+                        .map_err(|e| (index, e.to_string()))?;
+
+                    let mut order = Order::new(
+                        String::new(),
+                        data.user_id.clone(),
+                        if data.side == "buy" {
+                            OrderSide::Buy
+                        } else {
+                            OrderSide::Sell
+                        },
+                        Ticks::ZERO,
+                        quantity,
+                        0,
+                    );
+                    order.order_type = data.order_type;
+                    order.trigger_price = data
+                        .trigger_price
+                        .as_deref()
+                        .map(Decimal::from_str)
+                        .transpose()
+                        .map_err(|e| (index, e.to_string()))?
+                        .map(|price| scale.nearest_ticks(price))
+                        .transpose()
+                        .map_err(|e| (index, e.to_string()))?;
+                    order.created_at = self.clock;
+                    init_iceberg(orderbook, &mut order, data)
+                        .map_err(|e| (index, e.to_string()))?;
+                    init_peg(orderbook, &mut order, data).map_err(|e| (index, e.to_string()))?;
+                    init_expiry(orderbook, &mut order, data, self.clock)
+                        .map_err(|e| (index, e.to_string()))?;
+                    if order.order_type == OrderType::TrailingStop {
+                        init_trailing_stop(orderbook, &mut order, data)
+                            .map_err(|e| (index, e.to_string()))?;
+                    }
+
+                    let price = order_lock_price(orderbook, &order, &data.price)
+                        .and_then(|price| apply_price_band(orderbook, &order, price, self.clock))
+                        .map_err(|e| (index, e.to_string()))?;
+                    order.price = price;
+                    if order.order_type.is_conditional()
+                        && conditional::is_triggered(&order, orderbook.current_price)
+                    {
+                        return Err((index, "Stop price already reached".to_string()));
+                    }
+
+                    let user_market = (data.user_id.clone(), data.market.clone());
+                    let mut exposure =
+                        self.exposure(&data.user_id, &data.market, price, quantity, None);
+                    exposure.pending = pending.get(&data.user_id).copied().unwrap_or(0);
+                    exposure.open_orders += pending_orders.get(&user_market).copied().unwrap_or(0);
+                    let user_quote = (data.user_id.clone(), exposure.quote_asset.clone());
+                    exposure.open_notional += pending_notional
+                        .get(&user_quote)
+                        .copied()
+                        .unwrap_or(Decimal::ZERO);
+                    self.risk
+                        .check(&data.user_id, &exposure, self.clock)
+                        .map_err(|e| (index, e.to_string()))?;
+
+                    let (asset, amount) = if data.side == "buy" {
+                        (
+                            orderbook.quote_asset.clone(),
+                            self.buy_lock(&orderbook.quote_asset, &scale, price, quantity),
+                        )
+                    } else {
+                        (orderbook.base_asset.clone(), scale.quantity(quantity))
+                    };
+
+                    let available = self
+                        .balances
+                        .get(&data.user_id)
+                        .and_then(|balance| balance.get(&asset))
+                        .map(|b| b.available)
+                        .unwrap_or(Decimal::ZERO);
 
-                // Update depth if price level changed
-                if let Some(price) = price_opt {
-                    self.send_updated_depth_at(&price.to_string(), &market)
-                        .await;
+                    let total = required
+                        .entry((data.user_id.clone(), asset))
+                        .or_insert(Decimal::ZERO);
+                    *total += amount;
+
+                    if *total > available {
+                        return Err((index, "Insufficient funds".to_string()));
+                    }
+
+                    *pending.entry(data.user_id.clone()).or_default() += 1;
+                    *pending_orders.entry(user_market).or_default() += 1;
+                    *pending_notional.entry(user_quote).or_default() += exposure.notional;
                 }
+                BatchOperation::Cancel(data) => {
+                    let order_id = self.resolve_order_id(
+                        data.order_id.clone(),
+                        data.client_order_id.clone(),
+                        data.user_id.clone(),
+                    );
 
-                // Send confirmation to client
-                let manager = RedisManager::get_instance();
-                let message = MessageToApi::OrderCancelled {
-                    payload: OrderCancelledPayload {
-                        order_id,
-                        executed_qty: Decimal::ZERO,
-                        remaining_qty: Decimal::ZERO,
-                    },
-                };
+                    let orderbook = self.orderbooks.iter().find(|o| o.ticker() == data.market);
+                    if let Some(orderbook) = orderbook
+                        && !orderbook.status.accepts_cancels()
+                    {
+                        return Err((index, format!("Market is {}", orderbook.status.as_str())));
+                    }
+
+                    let found = order_id
+                        .is_some_and(|id| orderbook.is_some_and(|o| o.get_order(&id).is_some()));
 
-                if let Err(e) = manager.send_to_api(client_id, message).await {
-                    error!("Failed to send order cancelled message: {}", e);
+                    if !found {
+                        return Err((index, "Order not found".to_string()));
+                    }
                 }
-            } else {
-                error!("Order not found: {}", order_id);
             }
-        } else {
-            error!("Orderbook not found for market: {}", market);
+        }
+
+        Ok(())
+    }
+
+    //takes an order off the book and releases whatever it still had locked
+    fn cancel_resting_order(
+        &mut self,
+        book: usize,
+        order_id: &str,
+        status: OrderStatus,
+    ) -> Option<Order> {
+        let orderbook = &mut self.orderbooks[book];
+        let mut order = orderbook.remove_order(order_id)?;
+        let (base_asset, quote_asset) =
+            (orderbook.base_asset.clone(), orderbook.quote_asset.clone());
+        let scale = orderbook.config.scale;
+
+        let (asset, amount) = match order.side {
+            OrderSide::Buy => (
+                quote_asset.clone(),
+                self.buy_lock(&quote_asset, &scale, order.price, order.remaining()),
+            ),
+            OrderSide::Sell => (base_asset, scale.quantity(order.remaining())),
+        };
+
+        self.release_funds(&order.user_id, &asset, amount);
+
+        order.close(status, self.clock);
+        Some(order)
+    }
+
+    // cancels an order together with its group when it belongs to a live one,
+    // returns every order taken off the book and the group torn down if any
+    fn cancel_with_group(
+        &mut self,
+        book: usize,
+        order_id: &str,
+        status: OrderStatus,
+    ) -> (Vec<Order>, Option<String>) {
+        let group_id = self.orderbooks[book]
+            .get_order(order_id)
+            .and_then(|o| o.group_id.clone())
+            .filter(|g| self.orderbooks[book].groups.get(g).is_some());
+
+        match group_id {
+            Some(group_id) => (self.cancel_group(book, &group_id, status), Some(group_id)),
+            None => (
+                self.cancel_resting_order(book, order_id, status)
+                    .into_iter()
+                    .collect(),
+                None,
+            ),
         }
     }
 
-    async fn handle_get_open_orders(&mut self, data: GetOpenOrdersData, client_id: &str) {
-        let market = data.market;
-        let user_id = data.user_id;
+    // a live oco has not traded yet so the shared lock is released in full,
+    // a bracket only has its entry live and pending exit legs are dropped
+    fn cancel_group(&mut self, book: usize, group_id: &str, status: OrderStatus) -> Vec<Order> {
+        let Some(group) = self.orderbooks[book].groups.remove(group_id) else {
+            return Vec::new();
+        };
 
-        if let Some(orderbook) = self.orderbooks.iter().find(|o| o.ticker() == market) {
-            let open_orders = orderbook.get_open_orders(&user_id);
+        let mut orders = Vec::new();
 
-            let redis_manager = RedisManager::get_instance();
+        match group.group_type {
+            GroupType::Bracket => {
+                for order_id in &group.order_ids {
+                    if let Some(order) = self.cancel_resting_order(book, order_id, status) {
+                        orders.push(order);
+                    }
+                }
+            }
+            GroupType::Oco => {
+                let orderbook = &mut self.orderbooks[book];
+                let now = self.clock;
 
-            let message = MessageToApi::OpenOrders {
-                payload: open_orders,
-            };
+                for order_id in &group.order_ids {
+                    if let Some(mut order) = orderbook.remove_order(order_id) {
+                        order.close(status, now);
+                        orders.push(order);
+                    }
+                }
 
-            if let Err(e) = redis_manager.send_to_api(client_id, message).await {
-                error!("Failed to send open orders message: {}", e);
-            } else {
-                error!("Orderbook not found for market: {}", market);
+                if let Some(leg) = orders.first() {
+                    let asset = match leg.side {
+                        OrderSide::Buy => orderbook.quote_asset.clone(),
+                        OrderSide::Sell => orderbook.base_asset.clone(),
+                    };
+                    self.release_funds(&group.user_id, &asset, group.locked);
+                }
             }
         }
+
+        orders
     }
 
-    async fn handle_on_ramp(&mut self, data: OnRampData) {
-        let user_id = data.user_id;
+    fn handle_create_order_group(&mut self, data: CreateOrderGroupData, client_id: &str) {
+        let scale = self.scale(&data.market);
+        let message = match self.create_order_group(&data) {
+            Ok((group_id, order_ids, executed_qty)) => MessageToApi::OrderGroupPlaced {
+                payload: OrderGroupPlacedPayload {
+                    group_id,
+                    order_ids,
+                    executed_qty: scale.quantity(executed_qty),
+                },
+            },
+            Err(e) => {
+                error!("Failed to create order group: {}", e);
 
-        let amount = Decimal::from_str(&data.amount).unwrap_or_else(|_| Decimal::ZERO);
+                MessageToApi::OrderRejected {
+                    payload: OrderRejectedPayload {
+                        order_id: String::new(),
+                        reason: e.to_string(),
+                        code: rejection_code(e.as_ref()),
+                    },
+                }
+            }
+        };
 
-        self.on_ramp(&user_id, amount);
+        self.send_to_api(client_id, message);
     }
 
-    fn on_ramp(&mut self, user_id: &str, amount: Decimal) {
-        if !self.balances.contains_key(user_id) {
-            let mut balance = HashMap::new();
+    // oco: take profit limit and stop loss on `side`, funds locked once at the higher of the two
+    // bracket: entry on `side`, the exit pair on the other side is placed once the entry fills
+    fn create_order_group(
+        &mut self,
+        data: &CreateOrderGroupData,
+    ) -> Result<(String, Vec<String>, Lots), Box<dyn std::error::Error>> {
+        let market = data.market.as_str();
+        let user_id = data.user_id.as_str();
+        let book = self
+            .orderbooks
+            .iter()
+            .position(|o| o.ticker() == market)
+            .ok_or("No orderbook found")?;
+
+        let status = self.orderbooks[book].status;
+        if !status.accepts_orders() {
+            return Err(format!("Market is {}", status.as_str()).into());
+        }
 
-            balance.insert(
-                BASE_CURRENCY.to_string(),
-                AssetBalance::new(amount, Decimal::ZERO),
-            );
-        } else if let Some(user_balance) = self.balances.get_mut(user_id) {
-            if !user_balance.contains_key(BASE_CURRENCY) {
-                user_balance.insert(
-                    BASE_CURRENCY.to_string(),
-                    AssetBalance::new(amount, Decimal::ZERO),
+        //every group has a stop leg
+        if self.orderbooks[book].config.batch.is_some() {
+            return Err("Order groups are not supported on batch markets".into());
+        }
+
+        let side = if data.side == "buy" {
+            OrderSide::Buy
+        } else {
+            OrderSide::Sell
+        };
+        let exit_side = match (data.group_type, &side) {
+            (GroupType::Oco, _) => side.clone(),
+            (GroupType::Bracket, OrderSide::Buy) => OrderSide::Sell,
+            (GroupType::Bracket, OrderSide::Sell) => OrderSide::Buy,
+        };
+
+        let group_id = self.generate_order_id();
+        let leg_ids = [self.generate_order_id(), self.generate_order_id()];
+        let now = self.clock;
+        let orderbook = &self.orderbooks[book];
+        let (base_asset, quote_asset) =
+            (orderbook.base_asset.clone(), orderbook.quote_asset.clone());
+        let scale = orderbook.config.scale;
+        let quantity = normalize_quantity(&scale, Decimal::from_str(&data.quantity)?)?;
+
+        let [take_profit_id, stop_loss_id] = leg_ids;
+        let mut take_profit = Order::new(
+            take_profit_id,
+            user_id.to_string(),
+            exit_side.clone(),
+            scale.to_ticks(Decimal::from_str(&data.take_profit_price)?, &exit_side)?,
+            quantity,
+            now,
+        );
+        take_profit.group_id = Some(group_id.clone());
+        if take_profit.price <= Ticks::ZERO {
+            return Err("Invalid price".into());
+        }
+        apply_price_band(orderbook, &take_profit, take_profit.price, self.clock)?;
+
+        let mut stop_loss = Order::new(
+            stop_loss_id,
+            user_id.to_string(),
+            exit_side,
+            Ticks::ZERO,
+            quantity,
+            now,
+        );
+        stop_loss.order_type = if data.stop_limit_price.is_some() {
+            OrderType::StopLimit
+        } else {
+            OrderType::StopMarket
+        };
+        stop_loss.trigger_price =
+            Some(scale.nearest_ticks(Decimal::from_str(&data.stop_trigger_price)?)?);
+        stop_loss.group_id = Some(group_id.clone());
+        stop_loss.price = order_lock_price(
+            orderbook,
+            &stop_loss,
+            data.stop_limit_price.as_deref().unwrap_or_default(),
+        )?;
+
+        match data.group_type {
+            GroupType::Oco => {
+                if conditional::is_triggered(&stop_loss, orderbook.current_price) {
+                    return Err("Stop price already reached".into());
+                }
+
+                let lock_price = take_profit.price.max(stop_loss.price);
+                self.check_risk(user_id, market, lock_price, quantity, None)?;
+                self.check_and_lock_funds(
+                    &base_asset,
+                    &quote_asset,
+                    &scale,
+                    &side,
+                    user_id,
+                    lock_price,
+                    quantity,
+                )?;
+                self.risk.record(user_id, self.clock);
+
+                let locked = match side {
+                    OrderSide::Buy => self.buy_lock(&quote_asset, &scale, lock_price, quantity),
+                    OrderSide::Sell => scale.quantity(quantity),
+                };
+                let order_ids = vec![stop_loss.order_id.clone(), take_profit.order_id.clone()];
+                let orderbook = &mut self.orderbooks[book];
+                orderbook.groups.insert(OrderGroup {
+                    group_id: group_id.clone(),
+                    group_type: GroupType::Oco,
+                    user_id: user_id.to_string(),
+                    order_ids: order_ids.clone(),
+                    pending: Vec::new(),
+                    locked,
+                });
+
+                //the stop goes in first so a take profit which trades straight away can cancel it
+                orderbook.conditional.insert(stop_loss.clone());
+                self.update_db_orders(&stop_loss, &[], &[], market);
+                self.match_order(&mut take_profit, market)?;
+
+                Ok((group_id, order_ids, take_profit.filled))
+            }
+            GroupType::Bracket => {
+                let entry_id = self.generate_order_id();
+                let orderbook = &self.orderbooks[book];
+                let mut entry = Order::new(
+                    entry_id,
+                    user_id.to_string(),
+                    side.clone(),
+                    Ticks::ZERO,
+                    quantity,
+                    now,
                 );
-            } else if let Some(asset_balance) = user_balance.get_mut(BASE_CURRENCY) {
-                asset_balance.available += amount;
+                entry.order_type = if data.entry_price.is_empty() {
+                    OrderType::Market
+                } else {
+                    OrderType::Limit
+                };
+                entry.group_id = Some(group_id.clone());
+                entry.price = order_lock_price(orderbook, &entry, &data.entry_price)?;
+                entry.price = apply_price_band(orderbook, &entry, entry.price, self.clock)?;
+
+                self.check_risk(user_id, market, entry.price, quantity, None)?;
+                self.check_and_lock_funds(
+                    &base_asset,
+                    &quote_asset,
+                    &scale,
+                    &side,
+                    user_id,
+                    entry.price,
+                    quantity,
+                )?;
+                self.risk.record(user_id, self.clock);
+
+                let order_ids = vec![
+                    entry.order_id.clone(),
+                    take_profit.order_id.clone(),
+                    stop_loss.order_id.clone(),
+                ];
+                self.orderbooks[book].groups.insert(OrderGroup {
+                    group_id: group_id.clone(),
+                    group_type: GroupType::Bracket,
+                    user_id: user_id.to_string(),
+                    order_ids: vec![entry.order_id.clone()],
+                    pending: vec![take_profit, stop_loss],
+                    locked: Decimal::ZERO,
+                });
+
+                self.match_order(&mut entry, market)?;
+
+                Ok((group_id, order_ids, entry.filled))
             }
         }
     }
 
-    fn set_base_balances(&mut self) {
-        let users = ["1", "2", "3"];
-        let assets = [BASE_CURRENCY, "TATA"];
-        let initial_amount = Decimal::from(10_000_000);
+    // looks at the taker and every maker it traded with, a fill or close on an oco leg cancels
+    // the rest of the oco, a bracket entry which is done queues its exit legs
+    fn settle_groups(&mut self, order: &Order, result: &OrderMatchResult, market: &str) {
+        let Some(book) = self.orderbooks.iter().position(|o| o.ticker() == market) else {
+            return;
+        };
 
-        for user_id in users.iter() {
-            let mut user_balance = HashMap::new();
+        let mut members: Vec<Order> = Vec::new();
 
-            for asset in assets.iter() {
-                user_balance.insert(
-                    asset.to_string(),
-                    AssetBalance::new(initial_amount, Decimal::ZERO),
-                );
+        if order.group_id.is_some() && (!order.filled.is_zero() || order.status.is_terminal()) {
+            members.push(order.clone());
+        }
+
+        for fill in &result.fills {
+            let maker = result
+                .filled_orders
+                .iter()
+                .find(|o| o.order_id == fill.marker_order_id)
+                .or_else(|| self.orderbooks[book].get_order(&fill.marker_order_id));
+
+            if let Some(maker) = maker
+                && maker.group_id.is_some()
+                && !members.iter().any(|m| m.order_id == maker.order_id)
+            {
+                members.push(maker.clone());
             }
+        }
 
-            self.balances.insert(user_id.to_string(), user_balance);
+        for member in members {
+            let Some(group_id) = member.group_id.clone() else {
+                continue;
+            };
+            let Some(group) = self.orderbooks[book].groups.get(&group_id) else {
+                continue;
+            };
+
+            match group.group_type {
+                GroupType::Oco => self.resolve_oco(book, &group_id, &member),
+                GroupType::Bracket if member.status.is_terminal() => {
+                    if member.filled.is_zero() {
+                        self.orderbooks[book].groups.remove(&group_id);
+                    } else {
+                        self.pending_brackets
+                            .push((market.to_string(), group_id, member.filled));
+                    }
+                }
+                GroupType::Bracket => {}
+            }
+        }
+    }
+
+    // cancels the other legs and hands the survivor back its own lock, anything the group
+    // held beyond that is released
+    fn resolve_oco(&mut self, book: usize, group_id: &str, survivor: &Order) {
+        let Some(group) = self.orderbooks[book].groups.remove(group_id) else {
+            return;
+        };
+
+        let orderbook = &mut self.orderbooks[book];
+        let market = orderbook.ticker();
+        let scale = orderbook.config.scale;
+        let asset = match survivor.side {
+            OrderSide::Buy => orderbook.quote_asset.clone(),
+            OrderSide::Sell => orderbook.base_asset.clone(),
+        };
+        let now = self.clock;
+
+        let mut cancelled = Vec::new();
+        for order_id in group
+            .order_ids
+            .iter()
+            .filter(|id| **id != survivor.order_id)
+        {
+            if let Some(mut order) = orderbook.remove_order(order_id) {
+                order.close(OrderStatus::Cancelled, now);
+                cancelled.push(order);
+            }
+        }
+
+        //the survivor keeps what it holds for its full quantity
+        let survivor_lock = match survivor.side {
+            OrderSide::Buy => self.buy_lock(&asset, &scale, survivor.price, survivor.quantity),
+            OrderSide::Sell => scale.quantity(survivor.quantity),
+        };
+        self.release_funds(&group.user_id, &asset, group.locked - survivor_lock);
+
+        for order in cancelled {
+            self.push_order_status(&order, &scale);
+            self.send_updated_depth_at(&[order.price], &market);
+            self.recent_orders.insert(order, scale);
+        }
+    }
+
+    // places the exit legs of a bracket for the quantity its entry filled, locking funds for
+    // them once as an oco
+    fn activate_bracket(&mut self, market: &str, group_id: &str, quantity: Lots) {
+        let Some(book) = self.orderbooks.iter().position(|o| o.ticker() == market) else {
+            return;
+        };
+
+        let orderbook = &mut self.orderbooks[book];
+        let (base_asset, quote_asset) =
+            (orderbook.base_asset.clone(), orderbook.quote_asset.clone());
+        let scale = orderbook.config.scale;
+        let Some(group) = orderbook.groups.get_mut(group_id) else {
+            return;
+        };
+
+        let mut legs = std::mem::take(&mut group.pending);
+        let user_id = group.user_id.clone();
+        let Some(side) = legs.first().map(|leg| leg.side.clone()) else {
+            orderbook.groups.remove(group_id);
+            return;
+        };
+
+        let now = self.clock;
+        for leg in legs.iter_mut() {
+            leg.quantity = quantity;
+            leg.created_at = now;
+            leg.updated_at = now;
         }
+
+        let lock_price = legs.iter().map(|leg| leg.price).max().unwrap_or_default();
+
+        if let Err(e) = self.check_and_lock_funds(
+            &base_asset,
+            &quote_asset,
+            &scale,
+            &side,
+            &user_id,
+            lock_price,
+            quantity,
+        ) {
+            error!("Failed to activate bracket {}: {}", group_id, e);
+
+            self.orderbooks[book].groups.remove(group_id);
+            for leg in legs {
+                self.reject_order(leg, market);
+            }
+            return;
+        }
+
+        let locked = match side {
+            OrderSide::Buy => self.buy_lock(&quote_asset, &scale, lock_price, quantity),
+            OrderSide::Sell => scale.quantity(quantity),
+        };
+        if let Some(group) = self.orderbooks[book].groups.get_mut(group_id) {
+            group.group_type = GroupType::Oco;
+            group.order_ids = legs.iter().map(|leg| leg.order_id.clone()).collect();
+            group.locked = locked;
+        }
+
+        //stops go in first so a take profit which trades straight away can cancel them
+        legs.sort_by_key(|leg| !leg.order_type.is_conditional());
+
+        for mut leg in legs {
+            if leg.order_type.is_conditional() {
+                self.orderbooks[book].conditional.insert(leg.clone());
+                self.update_db_orders(&leg, &[], &[], market);
+            } else if let Err(e) = self.execute_order(&mut leg, market) {
+                error!("Failed to place bracket leg {}: {}", leg.order_id, e);
+            }
+        }
+    }
+
+    // engine time only moves with ticks, so replaying the same commands expires the same orders
+    // in the same order whatever the wall clock says
+    fn handle_tick(&mut self, data: TickData) {
+        self.clock = self.clock.max(data.timestamp);
+        self.expire_orders();
+        self.end_volatility_auctions();
+        self.run_batches();
+    }
+
+    // clears every batch market whose batch ended, boundaries are multiples of the interval in
+    // engine time so a replay cuts the same batches
+    fn run_batches(&mut self) {
+        for book in 0..self.orderbooks.len() {
+            if !self.orderbooks[book].batch_due(self.clock) {
+                continue;
+            }
+
+            let market = self.orderbooks[book].ticker();
+            self.uncross(book);
+
+            let orderbook = &mut self.orderbooks[book];
+            if let Some(batch) = &orderbook.config.batch {
+                orderbook.last_batch = self.clock - self.clock % batch.interval.max(1);
+            }
+
+            self.settle_follow_ups(&market);
+        }
+    }
+
+    //expired orders go through the cancel path, releasing funds and taking their group with them
+    fn expire_orders(&mut self) {
+        for book in 0..self.orderbooks.len() {
+            //a halted book is frozen, its orders expire once it is moved on
+            if self.orderbooks[book].status == MarketStatus::Halted {
+                continue;
+            }
+
+            let market = self.orderbooks[book].ticker();
+            let scale = self.orderbooks[book].config.scale;
+            let order_ids = self.orderbooks[book].expiries.take_expired(self.clock);
+
+            let mut prices: Vec<Ticks> = Vec::new();
+
+            for order_id in order_ids {
+                let (orders, _) = self.cancel_with_group(book, &order_id, OrderStatus::Expired);
+
+                for mut order in orders {
+                    order.updated_at = self.clock;
+                    self.push_order_status(&order, &scale);
+                    self.publish_ws_order_update(&order, &market);
+
+                    if !prices.contains(&order.price) {
+                        prices.push(order.price);
+                    }
+
+                    self.recent_orders.insert(order, scale);
+                }
+            }
+
+            if !prices.is_empty() {
+                self.send_updated_depth_at(&prices, &market);
+            }
+            self.book_changed(&market);
+        }
+    }
+
+    fn handle_auction(&mut self, data: AuctionData, client_id: &str) {
+        let message = match self.auction(&data) {
+            Ok(payload) => MessageToApi::AuctionResult { payload },
+            Err(e) => {
+                error!("Failed to run auction action: {}", e);
+
+                MessageToApi::RequestRejected {
+                    payload: RequestRejectedPayload {
+                        reason: e.to_string(),
+                    },
+                }
+            }
+        };
+
+        self.send_to_api(client_id, message);
+    }
+
+    fn auction(
+        &mut self,
+        data: &AuctionData,
+    ) -> Result<AuctionPayload, Box<dyn std::error::Error>> {
+        let market = data.market.as_str();
+        let book = self
+            .orderbooks
+            .iter()
+            .position(|o| o.ticker() == market)
+            .ok_or("No orderbook found")?;
+        if self.orderbooks[book].config.batch.is_some() {
+            return Err("Batch markets clear on the engine clock".into());
+        }
+        if self.orderbooks[book].status != MarketStatus::Open {
+            return Err(format!("Market is {}", self.orderbooks[book].status.as_str()).into());
+        }
+        let phase = self.orderbooks[book].phase;
+
+        let (price, volume) = match data.action {
+            AuctionAction::StartOpening | AuctionAction::StartClosing => {
+                if phase.is_auction() {
+                    return Err("Market is already in an auction".into());
+                }
+
+                self.orderbooks[book].phase = if data.action == AuctionAction::StartOpening {
+                    TradingPhase::OpeningAuction
+                } else {
+                    TradingPhase::ClosingAuction
+                };
+
+                self.publish_ws_auction_update(market);
+
+                let indicative = self.orderbooks[book].indicative();
+                (
+                    indicative.map(|i| i.price),
+                    indicative.map_or(Lots::ZERO, |i| i.volume),
+                )
+            }
+            AuctionAction::Uncross => {
+                if !phase.is_auction() {
+                    return Err("Market is not in an auction".into());
+                }
+
+                //a closed market collects orders for the next opening
+                let next = match phase {
+                    TradingPhase::ClosingAuction => TradingPhase::OpeningAuction,
+                    _ => TradingPhase::Continuous,
+                };
+                let uncrossed = self.end_auction(book, next);
+
+                (
+                    uncrossed.map(|(price, _)| price),
+                    uncrossed.map_or(Lots::ZERO, |(_, volume)| volume),
+                )
+            }
+        };
+
+        let scale = self.orderbooks[book].config.scale;
+        Ok(AuctionPayload {
+            market: market.to_string(),
+            phase: self.orderbooks[book].phase,
+            price: price.map(|price| scale.price(price).to_string()),
+            volume: scale.quantity(volume),
+        })
+    }
+
+    // uncrosses an auction, moves the market to its next phase and lets stops and pegs react to
+    // the new price
+    fn end_auction(&mut self, book: usize, next: TradingPhase) -> Option<(Ticks, Lots)> {
+        let market = self.orderbooks[book].ticker();
+        let uncrossed = self.uncross(book);
+
+        self.orderbooks[book].phase = next;
+        self.orderbooks[book].auction_end = None;
+
+        self.trigger_conditional_orders(&market);
+        self.settle_follow_ups(&market);
+        self.publish_ws_auction_update(&market);
+
+        uncrossed
+    }
+
+    // stops continuous trading after a move larger than the breaker allows
+    // the history is dropped so the same move doesn't trip it again once trading resumes
+    fn trip_circuit_breaker(&mut self, market: &str) {
+        let clock = self.clock;
+        let Some(orderbook) = self.orderbooks.iter_mut().find(|o| o.ticker() == market) else {
+            return;
+        };
+        let Some(breaker) = orderbook.config.circuit_breaker.clone() else {
+            return;
+        };
+
+        orderbook.history.clear();
+
+        //batch markets are always in an auction, so they halt
+        if breaker.action == BreakerAction::Auction && orderbook.config.batch.is_none() {
+            orderbook.phase = TradingPhase::VolatilityAuction;
+            orderbook.auction_end = breaker.auction_duration.map(|duration| clock + duration);
+            self.publish_ws_auction_update(market);
+        } else {
+            orderbook.status = MarketStatus::Halted;
+            self.publish_ws_market_status(market, Some("Circuit breaker".to_string()));
+        }
+    }
+
+    //volatility auctions with a duration uncross on their own once it is over
+    fn end_volatility_auctions(&mut self) {
+        for book in 0..self.orderbooks.len() {
+            let orderbook = &self.orderbooks[book];
+            if orderbook.phase == TradingPhase::VolatilityAuction
+                && orderbook.status == MarketStatus::Open
+                && orderbook.auction_end.is_some_and(|end| self.clock >= end)
+            {
+                self.end_auction(book, TradingPhase::Continuous);
+            }
+        }
+    }
+
+    // executes the auction and settles every buy against the sells it matched, the same way a
+    // taker is settled in continuous trading, returns the clearing price and executed volume
+    fn uncross(&mut self, book: usize) -> Option<(Ticks, Lots)> {
+        let market = self.orderbooks[book].ticker();
+        let base_asset = self.orderbooks[book].base_asset.clone();
+        let quote_asset = self.orderbooks[book].quote_asset.clone();
+        let scale = self.orderbooks[book].config.scale;
+
+        //every level that traded was on the book before the uncross
+        let (bids, asks) = self.orderbooks[book].get_depth();
+        let mut prices: Vec<Ticks> = Vec::new();
+        for (price, _) in bids.into_iter().chain(asks) {
+            if !prices.contains(&price) {
+                prices.push(price);
+            }
+        }
+
+        let (price, results) = self.orderbooks[book].uncross(self.clock)?;
+        let mut volume = Lots::ZERO;
+
+        for (buy, result) in results {
+            volume += result.executed_qty;
+            self.orderbooks[book].record_trades(self.clock, &result.fills);
+
+            self.update_balance(&buy, &base_asset, &quote_asset, &scale, &result.fills);
+
+            self.settle_groups(&buy, &result, &market);
+
+            self.create_db_trades(&result.fills, &market);
+            self.update_db_orders(&buy, &result.fills, &result.filled_orders, &market);
+
+            self.publish_ws_trades(&result.fills, &buy.user_id, &market);
+
+            for filled in result.filled_orders {
+                self.recent_orders.insert(filled, scale);
+            }
+            if buy.status.is_terminal() {
+                self.recent_orders.insert(buy, scale);
+            }
+        }
+
+        if !prices.is_empty() {
+            self.send_updated_depth_at(&prices, &market);
+        }
+
+        Some((price, volume))
+    }
+
+    fn handle_set_market_status(&mut self, data: MarketStatusData, client_id: &str) {
+        let message = match self.set_market_status(&data) {
+            Ok(order_ids) => MessageToApi::MarketStatus {
+                payload: MarketStatusPayload {
+                    market: data.market,
+                    status: data.status,
+                    order_ids,
+                },
+            },
+            Err(e) => {
+                error!("Failed to change market status: {}", e);
+
+                MessageToApi::RequestRejected {
+                    payload: RequestRejectedPayload {
+                        reason: e.to_string(),
+                    },
+                }
+            }
+        };
+
+        self.send_to_api(client_id, message);
+    }
+
+    // moves a market to a new lifecycle state, returns the orders cancelled on the way
+    // pre-open holds the book in the opening auction and opening from it uncrosses the book
+    fn set_market_status(
+        &mut self,
+        data: &MarketStatusData,
+    ) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let market = data.market.as_str();
+        let book = self
+            .orderbooks
+            .iter()
+            .position(|o| o.ticker() == market)
+            .ok_or("No orderbook found")?;
+        let current = self.orderbooks[book].status;
+
+        if current == MarketStatus::Delisted {
+            return Err("Market is delisted".into());
+        }
+        if current == data.status {
+            return Err(format!("Market is already {}", current.as_str()).into());
+        }
+
+        let mut order_ids = Vec::new();
+
+        match data.status {
+            MarketStatus::PreOpen => {
+                let orderbook = &mut self.orderbooks[book];
+                if orderbook.config.batch.is_none() {
+                    orderbook.phase = TradingPhase::OpeningAuction;
+                }
+                orderbook.status = data.status;
+            }
+            MarketStatus::Open => {
+                self.orderbooks[book].status = data.status;
+
+                if current == MarketStatus::PreOpen
+                    && self.orderbooks[book].phase == TradingPhase::OpeningAuction
+                {
+                    self.end_auction(book, TradingPhase::Continuous);
+                }
+            }
+            MarketStatus::Delisted => {
+                let resting: Vec<String> = self.orderbooks[book]
+                    .orders()
+                    .map(|o| o.order_id.clone())
+                    .collect();
+                (order_ids, _) = self.cancel_orders(book, resting);
+                self.orderbooks[book].status = data.status;
+            }
+            MarketStatus::Halted | MarketStatus::CancelOnly | MarketStatus::Closed => {
+                self.orderbooks[book].status = data.status;
+            }
+        }
+
+        self.publish_ws_market_status(market, data.reason.clone());
+
+        Ok(order_ids)
+    }
+
+    fn publish_ws_market_status(&mut self, market: &str, reason: Option<String>) {
+        let Some(orderbook) = self.orderbooks.iter().find(|o| o.ticker() == market) else {
+            return;
+        };
+
+        let stream = format!("status@{}", market);
+
+        let message = WsMessage::MarketStatus(MarketStatusMessage {
+            stream: stream.clone(),
+            data: WsMarketStatusData {
+                e: "status".to_string(),
+                s: market.to_string(),
+                x: orderbook.status,
+                r: reason,
+            },
+        });
+
+        self.publish_message(&stream, message);
+    }
+
+    //indicative price and volume of an auction, and the phase change once it is over
+    fn publish_ws_auction_update(&mut self, market: &str) {
+        let Some(orderbook) = self.orderbooks.iter().find(|o| o.ticker() == market) else {
+            return;
+        };
+
+        let indicative = orderbook
+            .phase
+            .is_auction()
+            .then(|| orderbook.indicative())
+            .flatten();
+        let scale = orderbook.config.scale;
+        let stream = format!("auction@{}", market);
+
+        let message = WsMessage::AuctionUpdate(AuctionUpdateMessage {
+            stream: stream.clone(),
+            data: WsAuctionData {
+                e: "auction".to_string(),
+                s: market.to_string(),
+                x: orderbook.phase,
+                p: indicative.map(|i| scale.price(i.price).to_string()),
+                v: scale
+                    .quantity(indicative.map_or(Lots::ZERO, |i| i.volume))
+                    .to_string(),
+            },
+        });
+
+        self.publish_message(&stream, message);
+    }
+
+    fn publish_ws_order_update(&mut self, order: &Order, market: &str) {
+        let stream = format!("order@{}", order.user_id);
+        let scale = self.scale(market);
+
+        let message = WsMessage::OrderUpdate(OrderUpdateMessage {
+            stream: stream.clone(),
+            data: WsOrderUpdateData {
+                e: "order".to_string(),
+                i: order.order_id.clone(),
+                s: market.to_string(),
+                x: order.status,
+                p: scale.price(order.price).to_string(),
+                q: scale.quantity(order.quantity).to_string(),
+                z: scale.quantity(order.filled).to_string(),
+                t: order.updated_at,
+            },
+        });
+
+        self.publish_message(&stream, message);
+    }
+
+    fn handle_cancel_all(&mut self, data: CancelAllData, client_id: &str) {
+        let (order_ids, group_ids) = match self.cancel_all(&data) {
+            Ok(cancelled) => cancelled,
+            Err(e) => {
+                error!("Failed to cancel orders: {}", e);
+                (Vec::new(), Vec::new())
+            }
+        };
+
+        let message = MessageToApi::OrdersCancelled {
+            payload: OrdersCancelledPayload {
+                order_ids,
+                group_ids,
+            },
+        };
+
+        self.send_to_api(client_id, message);
+    }
+
+    //cancels every resting order matching the filters, across all markets, in a single step
+    fn cancel_all(
+        &mut self,
+        data: &CancelAllData,
+    ) -> Result<(Vec<String>, Vec<String>), Box<dyn std::error::Error>> {
+        let side = match data.side.as_deref() {
+            Some("buy") => Some(OrderSide::Buy),
+            Some("sell") => Some(OrderSide::Sell),
+            Some(other) => return Err(format!("Invalid side: {}", other).into()),
+            None => None,
+        };
+        //compared in each market's own units
+        let min_price = data
+            .min_price
+            .as_deref()
+            .map(Decimal::from_str)
+            .transpose()?;
+        let max_price = data
+            .max_price
+            .as_deref()
+            .map(Decimal::from_str)
+            .transpose()?;
+
+        let mut cancelled_ids = Vec::new();
+        let mut group_ids = Vec::new();
+
+        for book in 0..self.orderbooks.len() {
+            let market = self.orderbooks[book].ticker();
+            if data.market.as_ref().is_some_and(|m| *m != market)
+                || !self.orderbooks[book].status.accepts_cancels()
+            {
+                continue;
+            }
+
+            let scale = self.orderbooks[book].config.scale;
+            let min_price = min_price
+                .map(|p| scale.to_ticks(p, &OrderSide::Sell))
+                .transpose()?;
+            let max_price = max_price
+                .map(|p| scale.to_ticks(p, &OrderSide::Buy))
+                .transpose()?;
+
+            let order_ids: Vec<String> = self.orderbooks[book]
+                .orders()
+                .filter(|o| data.user_id.as_ref().is_none_or(|u| *u == o.user_id))
+                .filter(|o| side.as_ref().is_none_or(|s| *s == o.side))
+                .filter(|o| min_price.is_none_or(|p| o.price >= p))
+                .filter(|o| max_price.is_none_or(|p| o.price <= p))
+                .map(|o| o.order_id.clone())
+                .collect();
+
+            let (order_ids, groups) = self.cancel_orders(book, order_ids);
+            cancelled_ids.extend(order_ids);
+            group_ids.extend(groups);
+        }
+
+        Ok((cancelled_ids, group_ids))
+    }
+
+    //cancels the given orders of one market with their groups, returns the cancelled order ids
+    //and the groups torn down
+    fn cancel_orders(&mut self, book: usize, order_ids: Vec<String>) -> (Vec<String>, Vec<String>) {
+        let market = self.orderbooks[book].ticker();
+        let scale = self.orderbooks[book].config.scale;
+        let mut cancelled_ids = Vec::new();
+        let mut group_ids = Vec::new();
+        let mut prices: Vec<Ticks> = Vec::new();
+
+        for order_id in order_ids {
+            //members of a group cancelled earlier in the loop are already gone
+            let (orders, group_id) =
+                self.cancel_with_group(book, &order_id, OrderStatus::Cancelled);
+            group_ids.extend(group_id);
+
+            for order in orders {
+                self.push_order_status(&order, &scale);
+
+                if !prices.contains(&order.price) {
+                    prices.push(order.price);
+                }
+
+                cancelled_ids.push(order.order_id.clone());
+                self.recent_orders.insert(order, scale);
+            }
+        }
+
+        //one aggregated depth update per market
+        if !prices.is_empty() {
+            self.send_updated_depth_at(&prices, &market);
+        }
+        self.book_changed(&market);
+
+        (cancelled_ids, group_ids)
+    }
+
+    fn handle_get_open_orders(&mut self, data: GetOpenOrdersData, client_id: &str) {
+        let market = data.market;
+        let user_id = data.user_id;
+
+        if let Some(orderbook) = self.orderbooks.iter().find(|o| o.ticker() == market) {
+            let open_orders = orderbook
+                .get_open_orders(&user_id)
+                .iter()
+                .map(|order| OrderInfo::new(order, &orderbook.config.scale))
+                .collect();
+
+            let message = MessageToApi::OpenOrders {
+                payload: open_orders,
+            };
+
+            self.send_to_api(client_id, message);
+        } else {
+            error!("Orderbook not found for market: {}", market);
+        }
+    }
+
+    fn push_order_status(&mut self, order: &Order, scale: &Scale) {
+        let message = DbMessage::OrderUpdate {
+            data: OrderUpdateData {
+                order_id: order.order_id.clone(),
+                executed_qty: scale.quantity(order.filled).to_string(),
+                price: Some(scale.price(order.price).to_string()),
+                quantity: Some(scale.quantity(order.quantity).to_string()),
+                status: Some(order.status),
+                avg_fill_price: Some(
+                    scale
+                        .average_price(order.fill_value, order.filled)
+                        .to_string(),
+                ),
+                updated_at: Some(order.updated_at),
+                ..Default::default()
+            },
+        };
+
+        self.push_message(message);
+    }
+
+    fn handle_get_order(&mut self, data: GetOrderData, client_id: &str) {
+        let order_id = self
+            .resolve_order_id(data.order_id, data.client_order_id, data.user_id)
+            .unwrap_or_default();
+
+        // resting orders first, then anything which recently left the book. the engine only looks
+        // back that far, older orders are in the db's history and get their own code
+        let order = self
+            .orderbooks
+            .iter()
+            .find_map(|o| Some(OrderInfo::new(o.get_order(&order_id)?, &o.config.scale)))
+            .or_else(|| {
+                self.recent_orders
+                    .get(&order_id)
+                    .map(|(order, scale)| OrderInfo::new(order, scale))
+            });
+
+        let message = match order {
+            Some(order) => MessageToApi::Order {
+                payload: Box::new(order),
+            },
+            None => {
+                error!("Order not found: {}", order_id);
+
+                MessageToApi::OrderNotFound {
+                    payload: OrderNotFoundPayload {
+                        order_id,
+                        reason: format!(
+                            "Order is neither open nor among the last {} closed orders",
+                            RECENT_ORDERS_CAPACITY
+                        ),
+                        code: "OUTSIDE_LOOKUP_WINDOW".to_string(),
+                    },
+                }
+            }
+        };
+
+        self.send_to_api(client_id, message);
+    }
+
+    fn handle_on_ramp(&mut self, data: OnRampData) {
+        let user_id = data.user_id;
+
+        let symbol = data.asset.as_deref().unwrap_or(BASE_CURRENCY);
+        let Some(asset) = self.assets.get(symbol) else {
+            error!("On ramp {} for unknown asset {}", data.txn_id, symbol);
+            return;
+        };
+
+        let amount = asset.normalize(Decimal::from_str(&data.amount).unwrap_or(Decimal::ZERO));
+        let asset = asset.symbol.clone();
+
+        self.on_ramp(&user_id, &asset, amount);
+    }
+
+    fn on_ramp(&mut self, user_id: &str, asset: &str, amount: Decimal) {
+        self.asset_balance(user_id, asset).available += amount;
+    }
+
+    fn handle_withdraw(&mut self, data: WithdrawData, client_id: &str) {
+        let message = match self.withdraw(&data) {
+            Ok((amount, available)) => MessageToApi::Withdrawal {
+                payload: WithdrawalPayload {
+                    user_id: data.user_id,
+                    asset: data.asset,
+                    amount,
+                    available,
+                },
+            },
+            Err(e) => {
+                error!("Failed to withdraw: {}", e);
+
+                MessageToApi::RequestRejected {
+                    payload: RequestRejectedPayload {
+                        reason: e.to_string(),
+                    },
+                }
+            }
+        };
+
+        self.send_to_api(client_id, message);
+    }
+
+    //the amount is cut to the asset's precision before the minimum is checked
+    fn withdraw(
+        &mut self,
+        data: &WithdrawData,
+    ) -> Result<(String, String), Box<dyn std::error::Error>> {
+        let asset = self.assets.get(&data.asset).ok_or("Unknown asset")?.clone();
+        let amount = asset.normalize(Decimal::from_str(&data.amount)?);
+
+        if amount < asset.min_withdrawal {
+            return Err(format!(
+                "Minimum withdrawal is {} {}",
+                asset.display(asset.min_withdrawal),
+                asset.symbol
+            )
+            .into());
+        }
+
+        let balance = self
+            .balances
+            .get_mut(&data.user_id)
+            .and_then(|balance| balance.get_mut(&asset.symbol))
+            .ok_or("Insufficient funds")?;
+        if balance.available < amount {
+            return Err("Insufficient funds".into());
+        }
+        balance.available -= amount;
+
+        Ok((amount.to_string(), asset.display(balance.available)))
+    }
+
+    //queued for the db processor
+    fn push_message(&mut self, message: DbMessage) {
+        self.outbox.push(Event::Db(message));
+    }
+
+    //queued for the ws server
+    fn publish_message(&mut self, channel: &str, message: WsMessage) {
+        self.outbox.push(Event::Ws {
+            channel: channel.to_string(),
+            message,
+        });
+    }
+
+    //reply to the api server waiting on the client id
+    fn send_to_api(&mut self, client_id: &str, message: MessageToApi) {
+        self.outbox.push(Event::Api {
+            client_id: client_id.to_string(),
+            message,
+        });
+    }
+
+    fn set_base_balances(&mut self) {
+        let users = ["1", "2", "3"];
+        let initial_amount = Decimal::from(10_000_000);
+
+        for user_id in users.iter() {
+            let mut user_balance = HashMap::new();
+
+            for asset in self.assets.symbols() {
+                user_balance.insert(
+                    asset.to_string(),
+                    AssetBalance::new(initial_amount, Decimal::ZERO),
+                );
+            }
+
+            self.balances.insert(user_id.to_string(), user_balance);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::trade::market::{BandReference, BatchConfig, CircuitBreaker, PriceBand};
+
+    fn json(events: Vec<Event>) -> Vec<serde_json::Value> {
+        events
+            .into_iter()
+            .map(|event| serde_json::to_value(event).unwrap())
+            .collect()
+    }
+
+    fn create_order(user_id: &str, side: &str, price: &str, client_id: &str) -> MessageFromApi {
+        let data = serde_json::json!({
+            "market": "BTC_USDT",
+            "price": price,
+            "quantity": "0.5",
+            "side": side,
+            "user_id": user_id,
+        });
+        MessageFromApi::CreateOrder {
+            data: serde_json::from_value(data).unwrap(),
+            client_id: client_id.to_string(),
+        }
+    }
+
+    fn command(kind: &str, data: serde_json::Value) -> MessageFromApi {
+        serde_json::from_value(serde_json::json!({"type": kind, "data": data, "client_id": "c1"}))
+            .unwrap()
+    }
+
+    //0.5 on BTC_USDT unless `fields` say otherwise
+    fn order(fields: serde_json::Value) -> MessageFromApi {
+        let mut data = serde_json::json!({"market": "BTC_USDT", "quantity": "0.5"});
+        data.as_object_mut()
+            .unwrap()
+            .extend(fields.as_object().unwrap().clone());
+        command("CREATE_ORDER", data)
+    }
+
+    fn send(engine: &mut Engine, message: MessageFromApi) -> Vec<serde_json::Value> {
+        json(engine.apply(message))
+    }
+
+    fn reply(events: &[serde_json::Value]) -> &serde_json::Value {
+        events
+            .iter()
+            .rev()
+            .find_map(|event| event.get("api"))
+            .map(|api| &api["message"])
+            .unwrap()
+    }
+
+    //every status the db was sent for the order, oldest first
+    fn statuses(events: &[serde_json::Value], order_id: &serde_json::Value) -> Vec<String> {
+        events
+            .iter()
+            .map(|event| &event["db"])
+            .filter(|db| db["type"] == "OrderUpdate" && db["data"]["order_id"] == *order_id)
+            .map(|db| db["data"]["status"].as_str().unwrap().to_string())
+            .collect()
+    }
+
+    //(price, quantity) of every trade published
+    fn trades(events: &[serde_json::Value]) -> Vec<(String, String)> {
+        events
+            .iter()
+            .map(|event| &event["ws"]["message"]["data"])
+            .filter(|data| data["e"] == "trade")
+            .map(|data| {
+                (
+                    data["p"].as_str().unwrap().to_string(),
+                    data["q"].as_str().unwrap().to_string(),
+                )
+            })
+            .collect()
+    }
+
+    //trades 0.5 between users 2 and 1 so the market has a last price
+    fn trade_at(engine: &mut Engine, price: &str) {
+        send(
+            engine,
+            order(serde_json::json!({"user_id": "2", "side": "sell", "price": price})),
+        );
+        let events = send(
+            engine,
+            order(serde_json::json!({"user_id": "1", "side": "buy", "price": price})),
+        );
+        assert_eq!(trades(&events).len(), 1);
+    }
+
+    #[test]
+    fn resting_orders_are_recorded_published_and_acknowledged() {
+        let mut engine = Engine::new();
+        let events = json(engine.apply(create_order("2", "sell", "60000.5", "c1")));
+        let order_id = &events[2]["api"]["message"]["payload"]["order_id"];
+
+        assert_eq!(
+            events,
+            vec![
+                serde_json::json!({"db": {"type": "OrderUpdate", "data": {
+                    "order_id": order_id,
+                    "executed_qty": "0",
+                    "market": "BTC_USDT",
+                    "price": "60000.50",
+                    "quantity": "0.50000",
+                    "side": "sell",
+                    "status": "NEW",
+                    "avg_fill_price": "0",
+                    "order_type": "limit",
+                    "group_id": null,
+                    "expire_at": null,
+                    "updated_at": 0,
+                }}}),
+                serde_json::json!({"ws": {"channel": "depth@BTC_USDT", "message": {
+                    "stream": "depth@BTC_USDT",
+                    "data": {"e": "depth", "a": [["60000.50", "0.50000"]], "b": []},
+                }}}),
+                serde_json::json!({"api": {"client_id": "c1", "message": {
+                    "type": "ORDER_PLACED",
+                    "payload": {"order_id": order_id, "executed_qty": "0", "fills": []},
+                }}}),
+            ]
+        );
+    }
+
+    #[test]
+    fn crossing_orders_report_the_trade_to_every_destination() {
+        let mut engine = Engine::new();
+        engine.apply(create_order("2", "sell", "60000.5", "c1"));
+        let events = json(engine.apply(create_order("1", "buy", "60001", "c2")));
+
+        let destinations: Vec<String> = events
+            .iter()
+            .map(|event| match &event["db"]["type"] {
+                serde_json::Value::String(kind) => format!("db {}", kind),
+                _ => match &event["ws"]["channel"] {
+                    serde_json::Value::String(channel) => format!("ws {}", channel),
+                    _ => format!("api {}", event["api"]["client_id"]),
+                },
+            })
+            .collect();
+        assert_eq!(
+            destinations,
+            [
+                "db TradeAdded",
+                "db OrderUpdate",
+                "db OrderUpdate",
+                "ws depth@BTC_USDT",
+                "ws trade@BTC_USDT",
+                "api \"c2\"",
+            ]
+        );
+
+        assert_eq!(
+            events[4]["ws"]["message"]["data"],
+            serde_json::json!({"e": "trade", "t": 0, "m": false, "p": "60000.50", "q": "0.50000", "s": "BTC_USDT"})
+        );
+        assert_eq!(
+            events[5]["api"]["message"]["payload"]["fills"],
+            serde_json::json!([{"price": "60000.50", "qty": "0.50000", "trade_id": 0}])
+        );
+    }
+
+    #[test]
+    fn the_same_seed_and_commands_give_the_same_events() {
+        let run = || {
+            let mut engine = Engine::with_seed(49);
+            let mut events = Vec::new();
+            for (user_id, side, price) in [("2", "sell", "60000.5"), ("1", "buy", "60001")] {
+                events.extend(json(engine.apply(create_order(user_id, side, price, "c1"))));
+            }
+            events.extend(json(engine.apply(MessageFromApi::Tick {
+                data: TickData { timestamp: 1_000 },
+            })));
+            events
+        };
+
+        assert_eq!(run(), run());
+    }
+
+    #[test]
+    fn failed_orders_are_rejected_with_a_reason() {
+        let mut engine = Engine::new();
+        let events = json(engine.apply(create_order("4", "buy", "60000", "c1")));
+
+        let reply = &events.last().unwrap()["api"]["message"];
+        assert_eq!(reply["type"], "ORDER_REJECTED");
+        assert_eq!(reply["payload"]["reason"], "Insufficient funds");
+    }
+
+    #[test]
+    fn prices_and_quantities_too_large_to_hold_are_rejected() {
+        let mut engine = Engine::new();
+        let usdt = balance(&engine, "1", "USDT");
+
+        for (price, quantity, reason) in [
+            ("1000000000000000", "1000000000000000", "Invalid quantity"),
+            ("1000000000000000", "0.5", "Invalid price"),
+            ("60000", "1000000000000000", "Invalid quantity"),
+        ] {
+            let events = send(
+                &mut engine,
+                order(
+                    serde_json::json!({"user_id": "1", "side": "buy", "price": price, "quantity": quantity}),
+                ),
+            );
+            assert_eq!(reply(&events)["type"], "ORDER_REJECTED");
+            assert_eq!(reply(&events)["payload"]["reason"], reason);
+        }
+        assert_eq!(balance(&engine, "1", "USDT"), usdt);
+
+        let order_id = bid(&mut engine, "60000", "0.5")["order_id"].clone();
+        let events = amend(
+            &mut engine,
+            &order_id,
+            serde_json::json!({"quantity": "1000000000000000"}),
+        );
+        assert_eq!(reply(&events)["payload"]["reason"], "Invalid quantity");
+    }
+
+    #[test]
+    fn orders_outside_the_lookup_window_get_their_own_code() {
+        let mut engine = Engine::new();
+        let events = json(engine.apply(MessageFromApi::GetOrder {
+            data: serde_json::from_value(serde_json::json!({"order_id": "gone"})).unwrap(),
+            client_id: "c1".to_string(),
+        }));
+
+        let reply = &events[0]["api"]["message"];
+        assert_eq!(reply["type"], "ORDER_NOT_FOUND");
+        assert_eq!(reply["payload"]["order_id"], "gone");
+        assert_eq!(reply["payload"]["code"], "OUTSIDE_LOOKUP_WINDOW");
+    }
+
+    #[test]
+    fn rejected_client_order_ids_can_be_retried() {
+        let mut engine = Engine::new();
+        let submit = |engine: &mut Engine, quantity: &str| {
+            let mut message = create_order("1", "buy", "60000", "c1");
+            if let MessageFromApi::CreateOrder { data, .. } = &mut message {
+                data.client_order_id = Some("retry".to_string());
+                data.quantity = quantity.to_string();
+            }
+            let events = json(engine.apply(message));
+            events.last().unwrap()["api"]["message"].clone()
+        };
+
+        //more than the user's funds the first time round
+        assert_eq!(submit(&mut engine, "1000")["type"], "ORDER_REJECTED");
+        let placed = submit(&mut engine, "0.5");
+        assert_eq!(placed["type"], "ORDER_PLACED");
+        //retrying the accepted order gets the original response back
+        assert_eq!(submit(&mut engine, "0.5"), placed);
+    }
+
+    #[test]
+    fn resubmitted_client_order_ids_get_the_original_result() {
+        let mut engine = Engine::new();
+        send(
+            &mut engine,
+            order(
+                serde_json::json!({"user_id": "2", "side": "sell", "price": "60000", "quantity": "0.2"}),
+            ),
+        );
+        let submit = serde_json::json!({
+            "user_id": "1",
+            "side": "buy",
+            "price": "60000",
+            "client_order_id": "dup",
+        });
+
+        let events = send(&mut engine, order(submit.clone()));
+        let placed = reply(&events).clone();
+        assert_eq!(trades(&events).len(), 1);
+        let usdt = balance(&engine, "1", "USDT");
+
+        //nothing is placed, traded or recorded the second time
+        let events = send(&mut engine, order(submit));
+        assert_eq!(events.len(), 1);
+        assert_eq!(reply(&events), &placed);
+        assert_eq!(balance(&engine, "1", "USDT"), usdt);
+        assert_eq!(engine.orderbooks[book(&engine, "BTC_USDT")].bids.len(), 1);
+    }
+
+    #[test]
+    fn client_order_ids_are_scoped_to_their_user() {
+        let mut engine = Engine::new();
+        let submit = |engine: &mut Engine, user_id: &str| {
+            let events = send(
+                engine,
+                order(serde_json::json!({
+                    "user_id": user_id,
+                    "side": "buy",
+                    "price": "50000",
+                    "client_order_id": "shared",
+                })),
+            );
+            reply(&events).clone()
+        };
+
+        let first = submit(&mut engine, "1");
+        let second = submit(&mut engine, "2");
+        assert_eq!(second["type"], "ORDER_PLACED");
+        assert_ne!(first["payload"]["order_id"], second["payload"]["order_id"]);
+        assert_eq!(engine.orderbooks[book(&engine, "BTC_USDT")].bids.len(), 2);
+    }
+
+    #[test]
+    fn stop_market_orders_wait_for_their_trigger_and_then_trade() {
+        let mut engine = Engine::new();
+        trade_at(&mut engine, "60000");
+        for price in ["59500", "59000"] {
+            send(
+                &mut engine,
+                order(
+                    serde_json::json!({"user_id": "1", "side": "buy", "price": price, "quantity": "0.1"}),
+                ),
+            );
+        }
+
+        let events = send(
+            &mut engine,
+            order(serde_json::json!({
+                "user_id": "3",
+                "side": "sell",
+                "order_type": "stop_market",
+                "trigger_price": "59500",
+            })),
+        );
+        let stop = reply(&events)["payload"]["order_id"].clone();
+        assert_eq!(reply(&events)["type"], "ORDER_PLACED");
+        assert_eq!(statuses(&events, &stop), ["NEW"]);
+        //untriggered stops stay out of the book
+        assert!(events.iter().all(|event| event.get("ws").is_none()));
+
+        //trading at the trigger sells the stop into the bids below it
+        let events = send(
+            &mut engine,
+            order(
+                serde_json::json!({"user_id": "2", "side": "sell", "price": "59500", "quantity": "0.1"}),
+            ),
+        );
+        assert_eq!(
+            trades(&events),
+            [
+                ("59500.00".to_string(), "0.10000".to_string()),
+                ("59000.00".to_string(), "0.10000".to_string()),
+            ]
+        );
+        //the rest of a stop market is cancelled rather than resting
+        assert_eq!(statuses(&events, &stop), ["CANCELLED"]);
+    }
+
+    #[test]
+    fn stop_limit_orders_rest_at_their_limit_once_triggered() {
+        let mut engine = Engine::new();
+        trade_at(&mut engine, "60000");
+        for (price, quantity) in [("60500", "0.1"), ("60550", "0.2")] {
+            send(
+                &mut engine,
+                order(
+                    serde_json::json!({"user_id": "2", "side": "sell", "price": price, "quantity": quantity}),
+                ),
+            );
+        }
+
+        let events = send(
+            &mut engine,
+            order(serde_json::json!({
+                "user_id": "1",
+                "side": "buy",
+                "order_type": "stop_limit",
+                "trigger_price": "60500",
+                "price": "60600",
+            })),
+        );
+        let stop = reply(&events)["payload"]["order_id"].clone();
+
+        //below the trigger nothing happens
+        let events = send(
+            &mut engine,
+            order(
+                serde_json::json!({"user_id": "3", "side": "buy", "price": "60400", "quantity": "0.1"}),
+            ),
+        );
+        assert!(statuses(&events, &stop).is_empty());
+
+        let events = send(
+            &mut engine,
+            order(
+                serde_json::json!({"user_id": "3", "side": "buy", "price": "60500", "quantity": "0.1"}),
+            ),
+        );
+        assert_eq!(
+            trades(&events),
+            [
+                ("60500.00".to_string(), "0.10000".to_string()),
+                ("60550.00".to_string(), "0.20000".to_string()),
+            ]
+        );
+        assert_eq!(statuses(&events, &stop), ["PARTIALLY_FILLED"]);
+        let depth = events
+            .iter()
+            .rev()
+            .find(|event| event["ws"]["channel"] == "depth@BTC_USDT")
+            .unwrap();
+        assert_eq!(
+            depth["ws"]["message"]["data"]["b"],
+            serde_json::json!([["60600.00", "0.30000"]])
+        );
+    }
+
+    #[test]
+    fn trailing_stops_follow_the_price_up_but_not_down() {
+        let mut engine = Engine::new();
+        trade_at(&mut engine, "60000");
+
+        let events = send(
+            &mut engine,
+            order(serde_json::json!({
+                "user_id": "3",
+                "side": "sell",
+                "order_type": "trailing_stop",
+                "trailing_offset": "500",
+            })),
+        );
+        let stop = reply(&events)["payload"]["order_id"].clone();
+        assert_eq!(events[0]["db"]["data"]["trigger_price"], "59500.00");
+
+        //the watermark moves to 61000, a fall back to 60600 leaves it there
+        trade_at(&mut engine, "61000");
+        trade_at(&mut engine, "60600");
+        send(
+            &mut engine,
+            order(serde_json::json!({"user_id": "1", "side": "buy", "price": "60000"})),
+        );
+
+        //60500 is 500 under the high, well above the trigger the stop started with
+        send(
+            &mut engine,
+            order(serde_json::json!({"user_id": "2", "side": "sell", "price": "60500"})),
+        );
+        let events = send(
+            &mut engine,
+            order(serde_json::json!({"user_id": "1", "side": "buy", "price": "60500"})),
+        );
+        assert_eq!(
+            trades(&events),
+            [
+                ("60500.00".to_string(), "0.50000".to_string()),
+                ("60000.00".to_string(), "0.50000".to_string()),
+            ]
+        );
+        assert_eq!(statuses(&events, &stop), ["FILLED"]);
+    }
+
+    //user 1's group on BTC_USDT with its exits at 61000 and 59000
+    fn order_group(group_type: &str, side: &str, entry_price: &str) -> MessageFromApi {
+        command(
+            "CREATE_ORDER_GROUP",
+            serde_json::json!({
+                "market": "BTC_USDT",
+                "user_id": "1",
+                "group_type": group_type,
+                "side": side,
+                "quantity": "0.5",
+                "entry_price": entry_price,
+                "take_profit_price": "61000",
+                "stop_trigger_price": "59000",
+            }),
+        )
+    }
+
+    #[test]
+    fn a_fill_on_one_oco_leg_cancels_the_other() {
+        let mut engine = Engine::new();
+        trade_at(&mut engine, "60000");
+
+        let events = send(&mut engine, order_group("oco", "sell", ""));
+        let payload = &reply(&events)["payload"];
+        let (stop_loss, take_profit) = (
+            payload["order_ids"][0].clone(),
+            payload["order_ids"][1].clone(),
+        );
+        assert_eq!(statuses(&events, &stop_loss), ["NEW"]);
+        assert_eq!(statuses(&events, &take_profit), ["NEW"]);
+
+        //a partial fill is enough
+        let events = send(
+            &mut engine,
+            order(
+                serde_json::json!({"user_id": "3", "side": "buy", "price": "61000", "quantity": "0.2"}),
+            ),
+        );
+        assert_eq!(statuses(&events, &stop_loss), ["CANCELLED"]);
+
+        //a trade through the stop no longer fires it, the take profit keeps resting
+        send(
+            &mut engine,
+            order(serde_json::json!({"user_id": "3", "side": "sell", "price": "58000"})),
+        );
+        let events = send(
+            &mut engine,
+            order(serde_json::json!({"user_id": "2", "side": "buy", "price": "58000"})),
+        );
+        assert_eq!(trades(&events), [("58000.00".into(), "0.50000".into())]);
+        assert!(statuses(&events, &stop_loss).is_empty());
+        let events = send(
+            &mut engine,
+            order(
+                serde_json::json!({"user_id": "3", "side": "buy", "price": "61000", "quantity": "0.3"}),
+            ),
+        );
+        assert_eq!(statuses(&events, &take_profit), ["FILLED"]);
+        assert_eq!(balance(&engine, "1", "BTC").1, Decimal::ZERO);
+    }
+
+    #[test]
+    fn a_filled_bracket_entry_places_its_exits() {
+        let mut engine = Engine::new();
+        trade_at(&mut engine, "60000");
+
+        let events = send(&mut engine, order_group("bracket", "buy", "60000"));
+        let payload = &reply(&events)["payload"];
+        let (entry, take_profit, stop_loss) = (
+            payload["order_ids"][0].clone(),
+            payload["order_ids"][1].clone(),
+            payload["order_ids"][2].clone(),
+        );
+        assert_eq!(statuses(&events, &entry), ["NEW"]);
+        assert!(statuses(&events, &take_profit).is_empty());
+
+        let events = send(
+            &mut engine,
+            order(serde_json::json!({"user_id": "2", "side": "sell", "price": "60000"})),
+        );
+        assert_eq!(statuses(&events, &entry), ["FILLED"]);
+        assert_eq!(statuses(&events, &stop_loss), ["NEW"]);
+        assert_eq!(statuses(&events, &take_profit), ["NEW"]);
+
+        //the exits sell what the entry bought and resolve as an oco
+        assert_eq!(balance(&engine, "1", "BTC").1, dec!(0.5));
+        let events = send(
+            &mut engine,
+            order(serde_json::json!({"user_id": "3", "side": "buy", "price": "61000"})),
+        );
+        assert_eq!(statuses(&events, &take_profit), ["FILLED"]);
+        assert_eq!(statuses(&events, &stop_loss), ["CANCELLED"]);
+        assert_eq!(balance(&engine, "1", "BTC").1, Decimal::ZERO);
+    }
+
+    #[test]
+    fn cancelling_a_partly_filled_bracket_drops_its_exits() {
+        let mut engine = Engine::new();
+        trade_at(&mut engine, "60000");
+        let usdt = balance(&engine, "1", "USDT");
+
+        let events = send(&mut engine, order_group("bracket", "buy", "60000"));
+        let group_id = reply(&events)["payload"]["group_id"].clone();
+        let entry = reply(&events)["payload"]["order_ids"][0].clone();
+
+        send(
+            &mut engine,
+            order(
+                serde_json::json!({"user_id": "2", "side": "sell", "price": "60000", "quantity": "0.2"}),
+            ),
+        );
+
+        let events = send(
+            &mut engine,
+            command(
+                "CANCEL_ORDER",
+                serde_json::json!({"market": "BTC_USDT", "group_id": group_id}),
+            ),
+        );
+        assert_eq!(
+            reply(&events)["payload"],
+            serde_json::json!({"order_ids": [entry], "group_ids": [group_id]})
+        );
+        assert_eq!(statuses(&events, &entry), ["CANCELLED"]);
+        assert_eq!(
+            balance(&engine, "1", "USDT"),
+            (usdt.0 - dec!(12000), Decimal::ZERO)
+        );
+
+        //no exit legs were placed for what the entry bought
+        let events = send(
+            &mut engine,
+            order(serde_json::json!({"user_id": "3", "side": "buy", "price": "61000"})),
+        );
+        assert!(trades(&events).is_empty());
+        assert_eq!(balance(&engine, "1", "BTC").1, Decimal::ZERO);
+    }
+
+    #[test]
+    fn refreshed_iceberg_slices_go_to_the_back_of_their_level() {
+        let mut engine = Engine::new();
+        let events = send(
+            &mut engine,
+            order(serde_json::json!({
+                "user_id": "2",
+                "side": "sell",
+                "price": "60000",
+                "quantity": "1",
+                "display_quantity": "0.2",
+            })),
+        );
+        let iceberg = reply(&events)["payload"]["order_id"].clone();
+        let events = send(
+            &mut engine,
+            order(
+                serde_json::json!({"user_id": "3", "side": "sell", "price": "60000", "quantity": "0.2"}),
+            ),
+        );
+        let behind = reply(&events)["payload"]["order_id"].clone();
+
+        //the first slice trades ahead, its refresh queues up behind user 3
+        let events = send(
+            &mut engine,
+            order(
+                serde_json::json!({"user_id": "1", "side": "buy", "price": "60000", "quantity": "0.3"}),
+            ),
+        );
+        assert_eq!(
+            trades(&events),
+            [
+                ("60000.00".into(), "0.20000".into()),
+                ("60000.00".into(), "0.10000".into())
+            ]
+        );
+        assert_eq!(statuses(&events, &iceberg), ["PARTIALLY_FILLED"]);
+        assert_eq!(statuses(&events, &behind), ["PARTIALLY_FILLED"]);
+
+        let events = send(
+            &mut engine,
+            order(
+                serde_json::json!({"user_id": "1", "side": "buy", "price": "60000", "quantity": "0.1"}),
+            ),
+        );
+        assert_eq!(statuses(&events, &behind), ["FILLED"]);
+        assert!(statuses(&events, &iceberg).is_empty());
+
+        let events = send(
+            &mut engine,
+            order(
+                serde_json::json!({"user_id": "1", "side": "buy", "price": "60000", "quantity": "0.2"}),
+            ),
+        );
+        assert_eq!(statuses(&events, &iceberg), ["PARTIALLY_FILLED"]);
+    }
+
+    fn tick(engine: &mut Engine, timestamp: u64) -> Vec<serde_json::Value> {
+        json(engine.apply(MessageFromApi::Tick {
+            data: TickData { timestamp },
+        }))
+    }
+
+    #[test]
+    fn gtd_orders_expire_at_their_time() {
+        let mut engine = Engine::new();
+        let events = send(
+            &mut engine,
+            order(serde_json::json!({
+                "user_id": "2",
+                "side": "sell",
+                "price": "60000",
+                "time_in_force": "GTD",
+                "expire_at": 5_000,
+            })),
+        );
+        let order_id = reply(&events)["payload"]["order_id"].clone();
+        assert_eq!(balance(&engine, "2", "BTC").1, dec!(0.5));
+
+        assert!(statuses(&tick(&mut engine, 4_999), &order_id).is_empty());
+        assert_eq!(statuses(&tick(&mut engine, 5_000), &order_id), ["EXPIRED"]);
+        assert_eq!(balance(&engine, "2", "BTC").1, Decimal::ZERO);
+        assert!(engine.orderbooks[book(&engine, "BTC_USDT")].asks.is_empty());
+
+        //an expiry already in the past is refused outright
+        let events = send(
+            &mut engine,
+            order(serde_json::json!({
+                "user_id": "2",
+                "side": "sell",
+                "price": "60000",
+                "time_in_force": "GTD",
+                "expire_at": 5_000,
+            })),
+        );
+        assert_eq!(reply(&events)["type"], "ORDER_REJECTED");
+    }
+
+    #[test]
+    fn day_orders_expire_at_the_session_end() {
+        let mut engine = Engine::new();
+        let day_order = |engine: &mut Engine| {
+            let events = send(
+                engine,
+                order(serde_json::json!({
+                    "market": "TATA_INR",
+                    "user_id": "2",
+                    "side": "sell",
+                    "price": "100",
+                    "quantity": "1",
+                    "time_in_force": "DAY",
+                })),
+            );
+            reply(&events)["payload"]["order_id"].clone()
+        };
+
+        let today = day_order(&mut engine);
+        assert!(statuses(&tick(&mut engine, TATA_INR_SESSION_END - 1), &today).is_empty());
+
+        let events = tick(&mut engine, TATA_INR_SESSION_END);
+        assert_eq!(statuses(&events, &today), ["EXPIRED"]);
+
+        //placed after the close, it lives until the next session ends
+        let tomorrow = day_order(&mut engine);
+        assert!(statuses(&tick(&mut engine, DAY_MILLIS), &tomorrow).is_empty());
+        let events = tick(&mut engine, DAY_MILLIS + TATA_INR_SESSION_END);
+        assert_eq!(statuses(&events, &tomorrow), ["EXPIRED"]);
+    }
+
+    #[test]
+    fn replaying_the_commands_expires_the_same_orders() {
+        let run = || {
+            let mut engine = Engine::with_seed(35);
+            let mut events = Vec::new();
+            for (side, expire_at) in [("sell", 2_000), ("sell", 1_000), ("buy", 1_000)] {
+                events.extend(send(
+                    &mut engine,
+                    order(serde_json::json!({
+                        "user_id": "2",
+                        "side": side,
+                        "price": if side == "sell" { "60000" } else { "59000" },
+                        "time_in_force": "GTD",
+                        "expire_at": expire_at,
+                    })),
+                ));
+            }
+            for timestamp in [500, 1_000, 1_500, 2_000] {
+                events.extend(tick(&mut engine, timestamp));
+            }
+            events
+        };
+
+        let events = run();
+        let expired = events
+            .iter()
+            .filter(|event| event["db"]["data"]["status"] == "EXPIRED")
+            .count();
+        assert_eq!(expired, 3);
+        assert_eq!(events, run());
+    }
+
+    #[test]
+    fn repriced_pegs_lose_their_time_priority() {
+        let mut engine = Engine::new();
+        let bid = |user_id: &str, extra: serde_json::Value| {
+            let mut fields = serde_json::json!({"user_id": user_id, "side": "buy"});
+            fields
+                .as_object_mut()
+                .unwrap()
+                .extend(extra.as_object().unwrap().clone());
+            order(fields)
+        };
+        send(&mut engine, bid("1", serde_json::json!({"price": "59000"})));
+
+        //both pegs are capped at 59500, the first one starts below it
+        let events = send(
+            &mut engine,
+            bid(
+                "2",
+                serde_json::json!({"price": "59500", "peg": "best_bid"}),
+            ),
+        );
+        let first = reply(&events)["payload"]["order_id"].clone();
+        let events = send(
+            &mut engine,
+            bid(
+                "3",
+                serde_json::json!({"price": "59500", "peg": "best_bid", "peg_offset": "500"}),
+            ),
+        );
+        let second = reply(&events)["payload"]["order_id"].clone();
+
+        //the first peg moves up to join the second and queues behind it
+        send(&mut engine, bid("1", serde_json::json!({"price": "59500"})));
+        let events = send(
+            &mut engine,
+            order(
+                serde_json::json!({"user_id": "1", "side": "sell", "price": "59500", "quantity": "0.7"}),
+            ),
+        );
+        assert_eq!(
+            trades(&events),
+            [
+                ("59500.00".into(), "0.50000".into()),
+                ("59500.00".into(), "0.20000".into())
+            ]
+        );
+        assert_eq!(statuses(&events, &second), ["PARTIALLY_FILLED"]);
+        assert!(statuses(&events, &first).is_empty());
+    }
+
+    fn auction(engine: &mut Engine, action: &str) -> serde_json::Value {
+        let events = send(
+            engine,
+            command(
+                "AUCTION",
+                serde_json::json!({"market": "BTC_USDT", "action": action}),
+            ),
+        );
+        reply(&events).clone()
+    }
+
+    #[test]
+    fn call_auctions_collect_orders_and_uncross_at_one_price() {
+        let mut engine = Engine::new();
+        trade_at(&mut engine, "60000");
+        assert_eq!(auction(&mut engine, "uncross")["type"], "REQUEST_REJECTED");
+        assert_eq!(
+            auction(&mut engine, "start_opening")["payload"]["phase"],
+            "opening_auction"
+        );
+
+        let mut order_ids = Vec::new();
+        for (user_id, side, price, quantity) in [
+            ("2", "sell", "59000", "0.5"),
+            ("3", "sell", "60500", "0.3"),
+            ("1", "buy", "61000", "0.6"),
+            ("1", "buy", "59500", "0.2"),
+        ] {
+            let events = send(
+                &mut engine,
+                order(serde_json::json!({
+                    "user_id": user_id,
+                    "side": side,
+                    "price": price,
+                    "quantity": quantity,
+                })),
+            );
+            assert!(trades(&events).is_empty());
+            order_ids.push(reply(&events)["payload"]["order_id"].clone());
+        }
+
+        //0.6 trades at both 60500 and 61000, sellers are left over so the lower price wins
+        let events = send(
+            &mut engine,
+            command(
+                "AUCTION",
+                serde_json::json!({"market": "BTC_USDT", "action": "uncross"}),
+            ),
+        );
+        assert_eq!(
+            reply(&events)["payload"],
+            serde_json::json!({
+                "market": "BTC_USDT",
+                "phase": "continuous",
+                "price": "60500.00",
+                "volume": "0.60000",
+            })
+        );
+        assert!(trades(&events).iter().all(|(price, _)| price == "60500.00"));
+        assert_eq!(statuses(&events, &order_ids[0]), ["FILLED"]);
+        assert_eq!(statuses(&events, &order_ids[1]), ["PARTIALLY_FILLED"]);
+        assert_eq!(statuses(&events, &order_ids[2]), ["FILLED"]);
+        assert!(statuses(&events, &order_ids[3]).is_empty());
+
+        //a closing auction hands the book over to the next opening
+        auction(&mut engine, "start_closing");
+        assert_eq!(
+            auction(&mut engine, "uncross")["payload"]["phase"],
+            "opening_auction"
+        );
+    }
+
+    #[test]
+    fn batch_markets_clear_on_the_engine_clock() {
+        let mut engine = Engine::new();
+        let btc = book(&engine, "BTC_USDT");
+        let orderbook = &mut engine.orderbooks[btc];
+        orderbook.config.batch = Some(BatchConfig {
+            interval: 1_000,
+            lot_size: dec!(0.1),
+        });
+        orderbook.phase = TradingPhase::Batch;
+        assert_eq!(auction(&mut engine, "uncross")["type"], "REQUEST_REJECTED");
+
+        let mut order_ids = Vec::new();
+        for (user_id, side, quantity) in [
+            ("2", "sell", "0.3"),
+            ("3", "sell", "0.3"),
+            ("1", "buy", "0.4"),
+        ] {
+            let events = send(
+                &mut engine,
+                order(serde_json::json!({
+                    "user_id": user_id,
+                    "side": side,
+                    "price": "60000",
+                    "quantity": quantity,
+                })),
+            );
+            assert!(trades(&events).is_empty());
+            order_ids.push(reply(&events)["payload"]["order_id"].clone());
+        }
+
+        assert!(trades(&tick(&mut engine, 999)).is_empty());
+
+        //sellers are left over at the clearing price and share the buy pro-rata
+        let events = tick(&mut engine, 1_000);
+        let mut filled = trades(&events);
+        filled.sort();
+        assert_eq!(
+            filled,
+            [
+                ("60000.00".into(), "0.20000".into()),
+                ("60000.00".into(), "0.20000".into())
+            ]
+        );
+        assert_eq!(statuses(&events, &order_ids[0]), ["PARTIALLY_FILLED"]);
+        assert_eq!(statuses(&events, &order_ids[1]), ["PARTIALLY_FILLED"]);
+        assert_eq!(statuses(&events, &order_ids[2]), ["FILLED"]);
+        assert_eq!(engine.orderbooks[btc].phase, TradingPhase::Batch);
+
+        //the next batch only clears at the next boundary
+        send(
+            &mut engine,
+            order(
+                serde_json::json!({"user_id": "1", "side": "buy", "price": "60000", "quantity": "0.2"}),
+            ),
+        );
+        assert!(trades(&tick(&mut engine, 1_999)).is_empty());
+        assert_eq!(trades(&tick(&mut engine, 2_000)).len(), 2);
+    }
+
+    fn set_status(engine: &mut Engine, status: &str) -> Vec<serde_json::Value> {
+        send(
+            engine,
+            command(
+                "SET_MARKET_STATUS",
+                serde_json::json!({"market": "BTC_USDT", "status": status, "reason": "incident"}),
+            ),
+        )
+    }
+
+    fn cancel(engine: &mut Engine, order_id: &serde_json::Value) -> Vec<serde_json::Value> {
+        send(
+            engine,
+            command(
+                "CANCEL_ORDER",
+                serde_json::json!({"market": "BTC_USDT", "order_id": order_id}),
+            ),
+        )
+    }
+
+    #[test]
+    fn halted_markets_freeze_their_book_until_moved_on() {
+        let mut engine = Engine::new();
+        let events = send(
+            &mut engine,
+            order(serde_json::json!({
+                "user_id": "2",
+                "side": "sell",
+                "price": "60000",
+                "time_in_force": "GTD",
+                "expire_at": 1_000,
+            })),
+        );
+        let order_id = reply(&events)["payload"]["order_id"].clone();
+
+        let events = set_status(&mut engine, "halted");
+        assert_eq!(reply(&events)["payload"]["status"], "halted");
+        assert!(events.iter().any(|event| {
+            event["ws"]["message"]["data"] == serde_json::json!({"e": "status", "s": "BTC_USDT", "x": "halted", "r": "incident"})
+        }));
+        assert_eq!(
+            reply(&set_status(&mut engine, "halted"))["type"],
+            "REQUEST_REJECTED"
+        );
+
+        //nothing trades, nothing is cancelled and nothing expires
+        let events = send(
+            &mut engine,
+            order(serde_json::json!({"user_id": "1", "side": "buy", "price": "60000"})),
+        );
+        assert_eq!(reply(&events)["payload"]["reason"], "Market is halted");
+        assert_eq!(
+            reply(&cancel(&mut engine, &order_id))["type"],
+            "ORDER_REJECTED"
+        );
+        assert!(statuses(&tick(&mut engine, 1_000), &order_id).is_empty());
+
+        set_status(&mut engine, "open");
+        assert_eq!(statuses(&tick(&mut engine, 1_001), &order_id), ["EXPIRED"]);
+    }
+
+    #[test]
+    fn market_statuses_gate_orders_and_cancels() {
+        let mut engine = Engine::new();
+        let events = send(
+            &mut engine,
+            order(serde_json::json!({"user_id": "2", "side": "sell", "price": "60000"})),
+        );
+        let first = reply(&events)["payload"]["order_id"].clone();
+        let events = send(
+            &mut engine,
+            order(serde_json::json!({"user_id": "2", "side": "sell", "price": "61000"})),
+        );
+        let second = reply(&events)["payload"]["order_id"].clone();
+
+        //cancel only and closed take cancels but no new orders
+        for status in ["cancel_only", "closed"] {
+            set_status(&mut engine, status);
+            let events = send(
+                &mut engine,
+                order(serde_json::json!({"user_id": "1", "side": "buy", "price": "60000"})),
+            );
+            assert_eq!(reply(&events)["type"], "ORDER_REJECTED");
+        }
+        assert_eq!(
+            statuses(&cancel(&mut engine, &first), &first),
+            ["CANCELLED"]
+        );
+
+        //pre-open collects crossing orders and opening uncrosses them
+        set_status(&mut engine, "pre_open");
+        let events = send(
+            &mut engine,
+            order(serde_json::json!({"user_id": "1", "side": "buy", "price": "61000"})),
+        );
+        assert!(trades(&events).is_empty());
+        let events = set_status(&mut engine, "open");
+        assert_eq!(trades(&events), [("61000.00".into(), "0.50000".into())]);
+        assert_eq!(statuses(&events, &second), ["FILLED"]);
+
+        //delisting cancels whatever is left and is final
+        let events = send(
+            &mut engine,
+            order(serde_json::json!({"user_id": "2", "side": "sell", "price": "62000"})),
+        );
+        let third = reply(&events)["payload"]["order_id"].clone();
+        let events = set_status(&mut engine, "delisted");
+        assert_eq!(
+            reply(&events)["payload"]["order_ids"],
+            serde_json::json!([third])
+        );
+        assert_eq!(statuses(&events, &third), ["CANCELLED"]);
+        assert_eq!(balance(&engine, "2", "BTC").1, Decimal::ZERO);
+        assert_eq!(
+            reply(&set_status(&mut engine, "open"))["payload"]["reason"],
+            "Market is delisted"
+        );
+    }
+
+    #[test]
+    fn price_bands_reject_limits_and_cap_market_orders() {
+        let mut engine = Engine::new();
+        let btc = book(&engine, "BTC_USDT");
+        engine.orderbooks[btc].config.price_band = Some(PriceBand {
+            reference: BandReference::LastTrade,
+            percent: dec!(0.05),
+        });
+        trade_at(&mut engine, "60000");
+
+        let events = send(
+            &mut engine,
+            order(serde_json::json!({"user_id": "1", "side": "buy", "price": "63000.01"})),
+        );
+        assert_eq!(
+            reply(&events)["payload"]["reason"],
+            "Price outside of the band 57000.00 - 63000.00"
+        );
+        let events = send(
+            &mut engine,
+            order(serde_json::json!({"user_id": "2", "side": "sell", "price": "63000"})),
+        );
+        assert_eq!(reply(&events)["type"], "ORDER_PLACED");
+
+        //the band follows the last trade down, leaving the resting ask above it
+        send(
+            &mut engine,
+            order(serde_json::json!({"user_id": "3", "side": "buy", "price": "59000"})),
+        );
+        let events = send(
+            &mut engine,
+            order(serde_json::json!({"user_id": "2", "side": "sell", "price": "59000"})),
+        );
+        assert_eq!(trades(&events).len(), 1);
+
+        let usdt = balance(&engine, "1", "USDT");
+        let events = send(
+            &mut engine,
+            order(serde_json::json!({
+                "user_id": "1",
+                "side": "buy",
+                "price": "",
+                "order_type": "market",
+            })),
+        );
+        assert!(trades(&events).is_empty());
+        let order_id = &reply(&events)["payload"]["order_id"];
+        assert_eq!(statuses(&events, order_id), ["CANCELLED"]);
+        assert_eq!(balance(&engine, "1", "USDT"), usdt);
+    }
+
+    #[test]
+    fn circuit_breakers_start_a_volatility_auction_or_halt() {
+        let breaker = |action| CircuitBreaker {
+            percent: dec!(0.05),
+            window: 60_000,
+            action,
+            auction_duration: Some(1_000),
+        };
+
+        let mut engine = Engine::new();
+        let btc = book(&engine, "BTC_USDT");
+        engine.orderbooks[btc].config.circuit_breaker = Some(breaker(BreakerAction::Auction));
+        trade_at(&mut engine, "60000");
+        trade_at(&mut engine, "62000");
+        assert_eq!(engine.orderbooks[btc].phase, TradingPhase::Continuous);
+
+        //more than 5% above the low of the window
+        trade_at(&mut engine, "63500");
+        assert_eq!(
+            engine.orderbooks[btc].phase,
+            TradingPhase::VolatilityAuction
+        );
+        send(
+            &mut engine,
+            order(serde_json::json!({"user_id": "2", "side": "sell", "price": "63000"})),
+        );
+        let events = send(
+            &mut engine,
+            order(serde_json::json!({"user_id": "1", "side": "buy", "price": "63000"})),
+        );
+        assert!(trades(&events).is_empty());
+
+        assert!(trades(&tick(&mut engine, 999)).is_empty());
+        let events = tick(&mut engine, 1_000);
+        assert_eq!(trades(&events), [("63000.00".into(), "0.50000".into())]);
+        assert_eq!(engine.orderbooks[btc].phase, TradingPhase::Continuous);
+
+        let mut engine = Engine::new();
+        engine.orderbooks[btc].config.circuit_breaker = Some(breaker(BreakerAction::Halt));
+        trade_at(&mut engine, "60000");
+        trade_at(&mut engine, "63500");
+        assert_eq!(engine.orderbooks[btc].status, MarketStatus::Halted);
+        let events = send(
+            &mut engine,
+            order(serde_json::json!({"user_id": "1", "side": "buy", "price": "63500"})),
+        );
+        assert_eq!(reply(&events)["payload"]["reason"], "Market is halted");
+    }
+
+    fn limit_user_1(engine: &mut Engine, limits: serde_json::Value) {
+        send(
+            engine,
+            command(
+                "SET_RISK_LIMITS",
+                serde_json::json!({"user_id": "1", "limits": limits}),
+            ),
+        );
+    }
+
+    fn bid(engine: &mut Engine, price: &str, quantity: &str) -> serde_json::Value {
+        let events = send(
+            engine,
+            order(
+                serde_json::json!({"user_id": "1", "side": "buy", "price": price, "quantity": quantity}),
+            ),
+        );
+        reply(&events)["payload"].clone()
+    }
+
+    #[test]
+    fn each_risk_limit_rejects_with_its_own_code() {
+        for (limits, code) in [
+            (
+                serde_json::json!({"max_order_quantity": "0.4"}),
+                "MAX_ORDER_QUANTITY",
+            ),
+            (
+                serde_json::json!({"max_order_notional": "20000"}),
+                "MAX_ORDER_NOTIONAL",
+            ),
+            (serde_json::json!({"max_open_orders": 1}), "MAX_OPEN_ORDERS"),
+            (
+                serde_json::json!({"max_open_notional": {"USDT": "40000"}}),
+                "MAX_OPEN_NOTIONAL",
+            ),
+            (
+                serde_json::json!({"max_orders_per_second": 1}),
+                "MAX_ORDER_RATE",
+            ),
+        ] {
+            let mut engine = Engine::new();
+            limit_user_1(&mut engine, limits);
+
+            //the first order is within every limit but the per order ones, the second breaches the rest
+            let first = bid(&mut engine, "50000", "0.5");
+            let rejected = if code == "MAX_ORDER_QUANTITY" || code == "MAX_ORDER_NOTIONAL" {
+                first
+            } else {
+                assert!(first["code"].is_null(), "{}", code);
+                bid(&mut engine, "50000", "0.5")
+            };
+            assert_eq!(rejected["code"], code);
+        }
+    }
+
+    #[test]
+    fn amends_that_grow_an_order_are_risk_checked() {
+        let mut engine = Engine::new();
+        limit_user_1(
+            &mut engine,
+            serde_json::json!({"max_order_quantity": "0.6", "max_open_notional": {"USDT": "40000"}}),
+        );
+        let order_id = bid(&mut engine, "50000", "0.5")["order_id"].clone();
+        let events = amend(
+            &mut engine,
+            &order_id,
+            serde_json::json!({"quantity": "0.7"}),
+        );
+        assert_eq!(reply(&events)["payload"]["code"], "MAX_ORDER_QUANTITY");
+
+        //the order's current value is replaced rather than counted twice
+        let events = amend(
+            &mut engine,
+            &order_id,
+            serde_json::json!({"price": "79000"}),
+        );
+        assert_eq!(reply(&events)["type"], "ORDER_AMENDED");
+        let events = amend(
+            &mut engine,
+            &order_id,
+            serde_json::json!({"price": "81000"}),
+        );
+        assert_eq!(reply(&events)["payload"]["code"], "MAX_OPEN_NOTIONAL");
+        assert_eq!(balance(&engine, "1", "USDT").1, dec!(39500));
+    }
+
+    #[test]
+    fn open_value_is_limited_per_quote_asset() {
+        let mut engine = Engine::new();
+        limit_user_1(
+            &mut engine,
+            serde_json::json!({"max_open_notional": {"BTC": "1"}}),
+        );
+        let eth_btc = |price: &str, quantity: &str| {
+            order(serde_json::json!({
+                "market": "ETH_BTC",
+                "user_id": "1",
+                "side": "buy",
+                "price": price,
+                "quantity": quantity,
+            }))
+        };
+
+        //the USDT the first order is worth says nothing about BTC
+        assert!(bid(&mut engine, "60000", "0.5")["code"].is_null());
+        let events = send(&mut engine, eth_btc("0.05", "10"));
+        assert_eq!(reply(&events)["type"], "ORDER_PLACED");
+
+        let events = send(&mut engine, eth_btc("0.05", "10.5"));
+        assert_eq!(reply(&events)["payload"]["code"], "MAX_OPEN_NOTIONAL");
+    }
+
+    #[test]
+    fn orders_refused_for_funds_leave_the_rate_alone() {
+        let mut engine = Engine::new();
+        limit_user_1(&mut engine, serde_json::json!({"max_orders_per_second": 1}));
+
+        let rejected = bid(&mut engine, "60000", "1000");
+        assert_eq!(rejected["reason"], "Insufficient funds");
+        assert!(bid(&mut engine, "60000", "0.5")["code"].is_null());
+        assert_eq!(bid(&mut engine, "60000", "0.5")["code"], "MAX_ORDER_RATE");
+    }
+
+    #[test]
+    fn registry_markets_count_towards_their_own_quote_asset() {
+        let mut engine = Engine::new();
+        for symbol in ["SOL", "EUR"] {
+            engine.assets.register(Asset {
+                symbol: symbol.to_string(),
+                decimals: 6,
+                display_precision: 2,
+                min_withdrawal: dec!(1),
+            });
+        }
+        for (base, quote) in [("SOL", "USDT"), ("BTC", "EUR")] {
+            engine
+                .add_market(Orderbook::new(
+                    base.to_string(),
+                    quote.to_string(),
+                    Vec::new(),
+                    Vec::new(),
+                    0,
+                    Ticks::ZERO,
+                ))
+                .unwrap();
+        }
+        engine.on_ramp("1", "EUR", dec!(100000));
+        limit_user_1(
+            &mut engine,
+            serde_json::json!({"max_open_notional": {"USDT": "40000", "EUR": "40000"}}),
+        );
+        let buy = |market: &str, price: &str, quantity: &str| {
+            order(serde_json::json!({
+                "market": market,
+                "user_id": "1",
+                "side": "buy",
+                "price": price,
+                "quantity": quantity,
+            }))
+        };
+
+        assert!(bid(&mut engine, "60000", "0.5")["code"].is_null());
+        let events = send(&mut engine, buy("BTC_EUR", "60000", "0.5"));
+        assert_eq!(reply(&events)["type"], "ORDER_PLACED");
+
+        //SOL_USDT shares the USDT bucket with BTC_USDT
+        let events = send(&mut engine, buy("SOL_USDT", "150", "100"));
+        assert_eq!(reply(&events)["payload"]["code"], "MAX_OPEN_NOTIONAL");
+        let events = send(&mut engine, buy("SOL_USDT", "150", "50"));
+        assert_eq!(reply(&events)["type"], "ORDER_PLACED");
+    }
+
+    fn flag_user_1(engine: &mut Engine, flag: &str, enabled: bool) -> Vec<serde_json::Value> {
+        send(
+            engine,
+            command(
+                "SET_ACCOUNT_FLAG",
+                serde_json::json!({
+                    "user_id": "1",
+                    "flag": flag,
+                    "enabled": enabled,
+                    "reason": "compliance review",
+                }),
+            ),
+        )
+    }
+
+    #[test]
+    fn frozen_accounts_can_neither_trade_cancel_nor_withdraw() {
+        let mut engine = Engine::new();
+        let order_id = bid(&mut engine, "50000", "0.5")["order_id"].clone();
+
+        let events = flag_user_1(&mut engine, "frozen", true);
+        assert!(
+            events
+                .iter()
+                .any(|event| event["db"]["type"] == "AccountAudit")
+        );
+        assert_eq!(reply(&events)["payload"]["status"]["frozen"], true);
+
+        assert_eq!(bid(&mut engine, "50000", "0.5")["code"], "ACCOUNT_FROZEN");
+        assert_eq!(
+            reply(&cancel(&mut engine, &order_id))["payload"]["code"],
+            "ACCOUNT_FROZEN"
+        );
+        let events = send(
+            &mut engine,
+            command(
+                "WITHDRAW",
+                serde_json::json!({"user_id": "1", "asset": "USDT", "amount": "100"}),
+            ),
+        );
+        assert_eq!(reply(&events)["payload"]["code"], "ACCOUNT_FROZEN");
+
+        //the order was left alone and the account trades again once cleared
+        flag_user_1(&mut engine, "frozen", false);
+        assert!(engine.accounts.is_empty());
+        assert_eq!(
+            statuses(&cancel(&mut engine, &order_id), &order_id),
+            ["CANCELLED"]
+        );
+    }
+
+    #[test]
+    fn cancel_only_and_reduce_only_accounts_keep_what_they_may_do() {
+        let mut engine = Engine::new();
+        let order_id = bid(&mut engine, "50000", "0.5")["order_id"].clone();
+
+        flag_user_1(&mut engine, "cancel_only", true);
+        assert_eq!(
+            bid(&mut engine, "50000", "0.5")["code"],
+            "ACCOUNT_CANCEL_ONLY"
+        );
+        let events = send(
+            &mut engine,
+            command(
+                "AMEND_ORDER",
+                serde_json::json!({"market": "BTC_USDT", "order_id": order_id, "quantity": "0.4"}),
+            ),
+        );
+        assert_eq!(reply(&events)["payload"]["code"], "ACCOUNT_CANCEL_ONLY");
+        assert_eq!(
+            statuses(&cancel(&mut engine, &order_id), &order_id),
+            ["CANCELLED"]
+        );
+        flag_user_1(&mut engine, "cancel_only", false);
+
+        //reduce only takes sells but no buys
+        flag_user_1(&mut engine, "reduce_only", true);
+        assert_eq!(
+            bid(&mut engine, "50000", "0.5")["code"],
+            "ACCOUNT_REDUCE_ONLY"
+        );
+        let events = send(
+            &mut engine,
+            order(serde_json::json!({"user_id": "1", "side": "sell", "price": "70000"})),
+        );
+        assert_eq!(reply(&events)["type"], "ORDER_PLACED");
+    }
+
+    #[test]
+    fn freezing_can_cancel_the_accounts_orders() {
+        let mut engine = Engine::new();
+        let first = bid(&mut engine, "50000", "0.5")["order_id"].clone();
+        let events = send(
+            &mut engine,
+            order(serde_json::json!({"user_id": "1", "side": "sell", "price": "70000"})),
+        );
+        let second = reply(&events)["payload"]["order_id"].clone();
+        send(
+            &mut engine,
+            order(serde_json::json!({"user_id": "2", "side": "buy", "price": "50000"})),
+        );
+
+        let events = send(
+            &mut engine,
+            command(
+                "SET_ACCOUNT_FLAG",
+                serde_json::json!({
+                    "user_id": "1",
+                    "flag": "frozen",
+                    "enabled": true,
+                    "reason": "compliance review",
+                    "cancel_orders": true,
+                }),
+            ),
+        );
+        let mut order_ids = reply(&events)["payload"]["order_ids"]
+            .as_array()
+            .unwrap()
+            .clone();
+        order_ids.sort_by_key(|id| id.as_str().unwrap().to_string());
+        let mut expected = vec![first.clone(), second.clone()];
+        expected.sort_by_key(|id| id.as_str().unwrap().to_string());
+        assert_eq!(order_ids, expected);
+        assert_eq!(statuses(&events, &first), ["CANCELLED"]);
+        assert_eq!(statuses(&events, &second), ["CANCELLED"]);
+        assert_eq!(balance(&engine, "1", "USDT").1, Decimal::ZERO);
+        assert_eq!(balance(&engine, "1", "BTC").1, Decimal::ZERO);
+
+        //other users keep their orders
+        assert_eq!(engine.orderbooks[book(&engine, "BTC_USDT")].bids.len(), 1);
+    }
+
+    #[test]
+    fn flagged_accounts_only_lose_their_own_batch_items() {
+        let mut engine = Engine::new();
+        flag_user_1(&mut engine, "frozen", true);
+        let orders = ["2", "1"].map(
+            |user_id| serde_json::json!({"user_id": user_id, "side": "buy", "price": "59000"}),
+        );
+
+        let events = batch(&mut engine, &orders, false);
+        let results = reply(&events)["payload"].as_array().unwrap();
+        assert_eq!(results[0]["type"], "ORDER_PLACED");
+        assert_eq!(results[1]["payload"]["code"], "ACCOUNT_FROZEN");
+
+        //all or none still takes the whole batch down with it
+        let events = batch(&mut engine, &orders, true);
+        let results = reply(&events)["payload"].as_array().unwrap();
+        assert_eq!(results[0]["payload"]["reason"], "Batch rejected");
+        assert_ne!(results[1]["payload"]["reason"], "Batch rejected");
+        assert_eq!(engine.orderbooks[book(&engine, "BTC_USDT")].bids.len(), 1);
+    }
+
+    #[test]
+    fn stops_of_flagged_accounts_are_cancelled_when_triggered() {
+        for flag in ["frozen", "cancel_only"] {
+            let mut engine = Engine::new();
+            send(
+                &mut engine,
+                order(serde_json::json!({"user_id": "2", "side": "buy", "price": "59000"})),
+            );
+            let events = send(
+                &mut engine,
+                order(serde_json::json!({
+                    "user_id": "1",
+                    "side": "sell",
+                    "order_type": "stop_market",
+                    "trigger_price": "59500",
+                })),
+            );
+            let stop = reply(&events)["payload"]["order_id"].clone();
+            flag_user_1(&mut engine, flag, true);
+
+            send(
+                &mut engine,
+                order(serde_json::json!({"user_id": "3", "side": "sell", "price": "59500"})),
+            );
+            let events = send(
+                &mut engine,
+                order(serde_json::json!({"user_id": "2", "side": "buy", "price": "59500"})),
+            );
+            assert_eq!(trades(&events).len(), 1, "{}", flag);
+            assert_eq!(statuses(&events, &stop), ["CANCELLED"]);
+            assert_eq!(balance(&engine, "1", "BTC").1, Decimal::ZERO);
+            assert!(
+                engine.orderbooks[book(&engine, "BTC_USDT")]
+                    .conditional
+                    .is_empty()
+            );
+        }
+    }
+
+    fn amend(
+        engine: &mut Engine,
+        order_id: &serde_json::Value,
+        fields: serde_json::Value,
+    ) -> Vec<serde_json::Value> {
+        let mut data = serde_json::json!({"market": "BTC_USDT", "order_id": order_id});
+        data.as_object_mut()
+            .unwrap()
+            .extend(fields.as_object().unwrap().clone());
+        send(engine, command("AMEND_ORDER", data))
+    }
+
+    fn depth_updates(events: &[serde_json::Value]) -> Vec<&serde_json::Value> {
+        events
+            .iter()
+            .map(|event| &event["ws"]["message"]["data"])
+            .filter(|data| data["e"] == "depth")
+            .collect()
+    }
+
+    //two asks of 0.5 at 60000, user 2's ahead of user 3's
+    fn two_asks(engine: &mut Engine) -> (serde_json::Value, serde_json::Value) {
+        let mut order_ids = ["2", "3"].map(|user_id| {
+            let events = send(
+                engine,
+                order(serde_json::json!({"user_id": user_id, "side": "sell", "price": "60000"})),
+            );
+            reply(&events)["payload"]["order_id"].clone()
+        });
+        (
+            std::mem::take(&mut order_ids[0]),
+            std::mem::take(&mut order_ids[1]),
+        )
+    }
+
+    #[test]
+    fn reducing_an_order_keeps_its_place_in_the_queue() {
+        let mut engine = Engine::new();
+        let (first, second) = two_asks(&mut engine);
+
+        let events = amend(&mut engine, &first, serde_json::json!({"quantity": "0.3"}));
+        assert_eq!(reply(&events)["type"], "ORDER_AMENDED");
+        assert_eq!(
+            depth_updates(&events),
+            [
+                &serde_json::json!({"e": "depth", "a": [["60000.00", "0.80000"]], "b": [["60000.00", "0"]]})
+            ]
+        );
+        assert_eq!(balance(&engine, "2", "BTC").1, dec!(0.3));
+
+        let events = send(
+            &mut engine,
+            order(
+                serde_json::json!({"user_id": "1", "side": "buy", "price": "60000", "quantity": "0.3"}),
+            ),
+        );
+        assert_eq!(statuses(&events, &first), ["FILLED"]);
+        assert!(statuses(&events, &second).is_empty());
+    }
+
+    #[test]
+    fn growing_or_repricing_an_order_sends_it_to_the_back() {
+        let mut engine = Engine::new();
+        let (first, second) = two_asks(&mut engine);
+
+        amend(&mut engine, &first, serde_json::json!({"quantity": "0.6"}));
+        assert_eq!(balance(&engine, "2", "BTC").1, dec!(0.6));
+        let events = send(
+            &mut engine,
+            order(
+                serde_json::json!({"user_id": "1", "side": "buy", "price": "60000", "quantity": "0.3"}),
+            ),
+        );
+        assert_eq!(statuses(&events, &second), ["PARTIALLY_FILLED"]);
+        assert!(statuses(&events, &first).is_empty());
+
+        //away and back again, now behind user 3's remainder
+        let mut engine = Engine::new();
+        let (first, second) = two_asks(&mut engine);
+        amend(&mut engine, &first, serde_json::json!({"price": "60001"}));
+        amend(&mut engine, &first, serde_json::json!({"price": "60000"}));
+        let events = send(
+            &mut engine,
+            order(
+                serde_json::json!({"user_id": "1", "side": "buy", "price": "60000", "quantity": "0.3"}),
+            ),
+        );
+        assert_eq!(statuses(&events, &second), ["PARTIALLY_FILLED"]);
+        assert!(statuses(&events, &first).is_empty());
+    }
+
+    #[test]
+    fn amending_a_buy_moves_its_lock_both_ways() {
+        let mut engine = Engine::new();
+        let order_id = bid(&mut engine, "60000", "0.5")["order_id"].clone();
+        assert_eq!(balance(&engine, "1", "USDT").1, dec!(30000));
+
+        amend(
+            &mut engine,
+            &order_id,
+            serde_json::json!({"price": "59000"}),
+        );
+        assert_eq!(balance(&engine, "1", "USDT").1, dec!(29500));
+        amend(
+            &mut engine,
+            &order_id,
+            serde_json::json!({"quantity": "0.4"}),
+        );
+        assert_eq!(balance(&engine, "1", "USDT").1, dec!(23600));
+        amend(
+            &mut engine,
+            &order_id,
+            serde_json::json!({"price": "61000", "quantity": "1"}),
+        );
+        assert_eq!(balance(&engine, "1", "USDT").1, dec!(61000));
+    }
+
+    fn cancel_all(engine: &mut Engine, filters: serde_json::Value) -> Vec<serde_json::Value> {
+        send(engine, command("CANCEL_ALL", filters))
+    }
+
+    #[test]
+    fn cancel_all_only_takes_orders_matching_every_filter() {
+        let mut engine = Engine::new();
+        let bid = send(
+            &mut engine,
+            order(serde_json::json!({"user_id": "1", "side": "buy", "price": "59000"})),
+        );
+        let bid = reply(&bid)["payload"]["order_id"].clone();
+        let ask = send(
+            &mut engine,
+            order(serde_json::json!({"user_id": "1", "side": "sell", "price": "61000"})),
+        );
+        let ask = reply(&ask)["payload"]["order_id"].clone();
+        send(
+            &mut engine,
+            order(serde_json::json!({"user_id": "2", "side": "buy", "price": "59000"})),
+        );
+        send(
+            &mut engine,
+            order(
+                serde_json::json!({"market": "ETH_BTC", "user_id": "1", "side": "buy", "price": "0.05"}),
+            ),
+        );
+        assert_eq!(balance(&engine, "1", "USDT").1, dec!(29500));
+        assert_eq!(balance(&engine, "1", "BTC").1, dec!(0.525));
+
+        let events = cancel_all(
+            &mut engine,
+            serde_json::json!({"user_id": "1", "market": "BTC_USDT", "side": "buy"}),
+        );
+        assert_eq!(reply(&events)["type"], "ORDERS_CANCELLED");
+        assert_eq!(
+            reply(&events)["payload"]["order_ids"],
+            serde_json::json!([bid])
+        );
+        assert_eq!(balance(&engine, "1", "USDT").1, Decimal::ZERO);
+        assert_eq!(balance(&engine, "1", "BTC").1, dec!(0.525));
+
+        //every market, both sides, still only user 1
+        let events = cancel_all(&mut engine, serde_json::json!({"user_id": "1"}));
+        let order_ids = reply(&events)["payload"]["order_ids"].as_array().unwrap();
+        assert_eq!(order_ids.len(), 2);
+        assert!(order_ids.contains(&ask));
+        assert_eq!(balance(&engine, "1", "BTC").1, Decimal::ZERO);
+        assert_eq!(balance(&engine, "2", "USDT").1, dec!(29500));
+        assert_eq!(engine.orderbooks[book(&engine, "BTC_USDT")].bids.len(), 1);
+    }
+
+    #[test]
+    fn cancel_all_sends_one_depth_update_per_market() {
+        let mut engine = Engine::new();
+        for price in ["59000", "59000", "58000"] {
+            bid(&mut engine, price, "0.5");
+        }
+        send(
+            &mut engine,
+            order(serde_json::json!({"user_id": "2", "side": "buy", "price": "58000"})),
+        );
+
+        let events = cancel_all(&mut engine, serde_json::json!({"user_id": "1"}));
+        assert_eq!(
+            reply(&events)["payload"]["order_ids"]
+                .as_array()
+                .unwrap()
+                .len(),
+            3
+        );
+        assert_eq!(
+            depth_updates(&events),
+            [
+                &serde_json::json!({"e": "depth", "a": [["59000.00", "0"], ["58000.00", "0"]], "b": [["59000.00", "0"], ["58000.00", "0.50000"]]})
+            ]
+        );
+        assert_eq!(balance(&engine, "1", "USDT").1, Decimal::ZERO);
+    }
+
+    //creates of 0.5 on BTC_USDT, each with its own fields
+    fn batch(
+        engine: &mut Engine,
+        orders: &[serde_json::Value],
+        all_or_none: bool,
+    ) -> Vec<serde_json::Value> {
+        let orders: Vec<serde_json::Value> = orders
+            .iter()
+            .map(|fields| {
+                let mut data =
+                    serde_json::json!({"op": "create", "market": "BTC_USDT", "quantity": "0.5"});
+                data.as_object_mut()
+                    .unwrap()
+                    .extend(fields.as_object().unwrap().clone());
+                data
+            })
+            .collect();
+        send(
+            engine,
+            command(
+                "BATCH_ORDERS",
+                serde_json::json!({"orders": orders, "all_or_none": all_or_none}),
+            ),
+        )
+    }
+
+    fn three_bids() -> Vec<serde_json::Value> {
+        ["59000", "59100", "59200"]
+            .map(|price| serde_json::json!({"user_id": "1", "side": "buy", "price": price}))
+            .to_vec()
+    }
+
+    #[test]
+    fn all_or_none_batches_count_earlier_items_against_the_limits() {
+        for limits in [
+            serde_json::json!({"max_orders_per_second": 2}),
+            serde_json::json!({"max_open_orders": 2}),
+            serde_json::json!({"max_open_notional": {"USDT": "80000"}}),
+        ] {
+            let mut engine = Engine::new();
+            limit_user_1(&mut engine, limits.clone());
+            let usdt = balance(&engine, "1", "USDT");
+
+            let events = batch(&mut engine, &three_bids(), true);
+            let results = reply(&events)["payload"].as_array().unwrap();
+            assert!(
+                results.iter().all(|r| r["type"] == "ORDER_REJECTED"),
+                "{}",
+                limits
+            );
+            assert_eq!(results[0]["payload"]["reason"], "Batch rejected");
+            assert_ne!(results[2]["payload"]["reason"], "Batch rejected");
+            assert!(engine.orderbooks[book(&engine, "BTC_USDT")].bids.is_empty());
+            assert_eq!(balance(&engine, "1", "USDT"), usdt);
+
+            //nothing was placed, so nothing counts towards the rate either
+            assert!(bid(&mut engine, "59000", "0.5")["code"].is_null());
+        }
+    }
+
+    #[test]
+    fn all_or_none_batches_reject_stops_already_triggered() {
+        let mut engine = Engine::new();
+        trade_at(&mut engine, "60000");
+
+        let mut orders = three_bids();
+        orders.push(serde_json::json!({
+            "user_id": "1",
+            "side": "buy",
+            "order_type": "stop_market",
+            "trigger_price": "59000",
+        }));
+        let events = batch(&mut engine, &orders, true);
+        let results = reply(&events)["payload"].as_array().unwrap();
+        assert_eq!(
+            results[3]["payload"]["reason"],
+            "Stop price already reached"
+        );
+        assert!(engine.orderbooks[book(&engine, "BTC_USDT")].bids.is_empty());
+    }
+
+    #[test]
+    fn partial_batches_keep_the_items_that_pass() {
+        let mut engine = Engine::new();
+        limit_user_1(&mut engine, serde_json::json!({"max_orders_per_second": 2}));
+
+        let events = batch(&mut engine, &three_bids(), false);
+        let results = reply(&events)["payload"].as_array().unwrap();
+        assert_eq!(results[0]["type"], "ORDER_PLACED");
+        assert_eq!(results[1]["type"], "ORDER_PLACED");
+        assert_eq!(results[2]["type"], "ORDER_REJECTED");
+        assert_eq!(results[2]["payload"]["code"], "MAX_ORDER_RATE");
+        assert_eq!(engine.orderbooks[book(&engine, "BTC_USDT")].bids.len(), 2);
+    }
+
+    #[test]
+    fn batches_above_the_limit_are_refused_whole() {
+        let mut engine = Engine::new();
+        let orders = vec![
+            serde_json::json!({"user_id": "1", "side": "buy", "price": "59000"});
+            MAX_BATCH_ORDERS + 1
+        ];
+
+        let events = batch(&mut engine, &orders, false);
+        assert_eq!(reply(&events)["type"], "ORDER_REJECTED");
+        assert_eq!(
+            reply(&events)["payload"]["reason"],
+            format!("Batch exceeds {} orders", MAX_BATCH_ORDERS)
+        );
+        assert!(engine.orderbooks[book(&engine, "BTC_USDT")].bids.is_empty());
+
+        let events = batch(&mut engine, &orders[1..], false);
+        assert_eq!(
+            reply(&events)["payload"].as_array().unwrap().len(),
+            MAX_BATCH_ORDERS
+        );
+    }
+
+    fn book(engine: &Engine, market: &str) -> usize {
+        engine
+            .orderbooks
+            .iter()
+            .position(|o| o.ticker() == market)
+            .unwrap()
+    }
+
+    fn balance(engine: &Engine, user_id: &str, asset: &str) -> (Decimal, Decimal) {
+        let balance = &engine.balances[user_id][asset];
+        (balance.available, balance.locked)
+    }
+
+    fn total(engine: &Engine, asset: &str) -> Decimal {
+        engine
+            .balances
+            .values()
+            .filter_map(|balance| balance.get(asset))
+            .map(|balance| balance.available + balance.locked)
+            .sum()
+    }
+
+    //locks funds the way create_order does, runs the order through the book and settles it
+    fn place(
+        engine: &mut Engine,
+        market: &str,
+        user_id: &str,
+        order_id: &str,
+        side: OrderSide,
+        price: Decimal,
+        qty: Decimal,
+    ) {
+        let (base_asset, quote_asset) = engine.market_assets(market).unwrap();
+        let scale = engine.scale(market);
+        let price = scale.to_ticks(price, &side).unwrap();
+        let qty = normalize_quantity(&scale, qty).unwrap();
+        engine
+            .check_and_lock_funds(
+                &base_asset,
+                &quote_asset,
+                &scale,
+                &side,
+                user_id,
+                price,
+                qty,
+            )
+            .unwrap();
+
+        let book = book(engine, market);
+        let mut order = Order::new(
+            order_id.to_string(),
+            user_id.to_string(),
+            side,
+            price,
+            qty,
+            0,
+        );
+        let result = engine.orderbooks[book].add_order(&mut order);
+        engine.update_balance(&order, &base_asset, &quote_asset, &scale, &result.fills);
+    }
+
+    #[test]
+    fn markets_use_their_own_quote_asset() {
+        let engine = Engine::new();
+        assert_eq!(
+            engine.market_assets("BTC_USDT").unwrap(),
+            ("BTC".to_string(), "USDT".to_string())
+        );
+        assert_eq!(
+            engine.market_assets("ETH_BTC").unwrap(),
+            ("ETH".to_string(), "BTC".to_string())
+        );
+        assert!(engine.market_assets("BTC_INR").is_err());
+    }
+
+    #[test]
+    fn cancelling_a_buy_unlocks_the_quote_asset() {
+        let mut engine = Engine::new();
+        let inr = balance(&engine, "1", BASE_CURRENCY);
+        let usdt = balance(&engine, "1", "USDT");
+
+        place(
+            &mut engine,
+            "BTC_USDT",
+            "1",
+            "o1",
+            OrderSide::Buy,
+            dec!(60000),
+            dec!(0.5),
+        );
+        assert_eq!(
+            balance(&engine, "1", "USDT"),
+            (usdt.0 - dec!(30000), dec!(30000))
+        );
+
+        let book = book(&engine, "BTC_USDT");
+        engine
+            .cancel_resting_order(book, "o1", OrderStatus::Cancelled)
+            .unwrap();
+
+        assert_eq!(balance(&engine, "1", "USDT"), usdt);
+        assert_eq!(balance(&engine, "1", BASE_CURRENCY), inr);
+    }
+
+    #[test]
+    fn cancelling_a_sell_unlocks_the_base_asset() {
+        let mut engine = Engine::new();
+        let eth = balance(&engine, "1", "ETH");
+        let btc = balance(&engine, "1", "BTC");
+
+        place(
+            &mut engine,
+            "ETH_BTC",
+            "1",
+            "o1",
+            OrderSide::Sell,
+            dec!(0.05),
+            dec!(3),
+        );
+        assert_eq!(balance(&engine, "1", "ETH"), (eth.0 - dec!(3), dec!(3)));
+
+        let book = book(&engine, "ETH_BTC");
+        engine
+            .cancel_resting_order(book, "o1", OrderStatus::Cancelled)
+            .unwrap();
+
+        assert_eq!(balance(&engine, "1", "ETH"), eth);
+        assert_eq!(balance(&engine, "1", "BTC"), btc);
+    }
+
+    #[test]
+    fn on_ramp_credits_the_named_asset() {
+        let mut engine = Engine::new();
+        engine.on_ramp("4", "USDT", dec!(250));
+
+        assert_eq!(balance(&engine, "4", "USDT"), (dec!(250), Decimal::ZERO));
+        assert!(!engine.balances["4"].contains_key(BASE_CURRENCY));
+    }
+
+    #[test]
+    fn partial_fills_release_the_whole_buy_lock() {
+        let mut engine = Engine::new();
+        let usdt = total(&engine, "USDT");
+
+        place(
+            &mut engine,
+            "BTC_USDT",
+            "2",
+            "s1",
+            OrderSide::Sell,
+            dec!(60000.13),
+            dec!(0.00001),
+        );
+        place(
+            &mut engine,
+            "BTC_USDT",
+            "2",
+            "s2",
+            OrderSide::Sell,
+            dec!(60000.5),
+            dec!(0.00002),
+        );
+        //takes both asks below its limit and rests the rest, every step rounds
+        place(
+            &mut engine,
+            "BTC_USDT",
+            "1",
+            "b1",
+            OrderSide::Buy,
+            dec!(60001.33),
+            dec!(0.00005),
+        );
+
+        let book = book(&engine, "BTC_USDT");
+        engine
+            .cancel_resting_order(book, "b1", OrderStatus::Cancelled)
+            .unwrap();
+
+        assert_eq!(balance(&engine, "1", "USDT").1, Decimal::ZERO);
+        assert_eq!(total(&engine, "USDT"), usdt);
+        for balance in engine.balances.values().filter_map(|b| b.get("USDT")) {
+            assert_eq!(balance.available, balance.available.round_dp(6));
+        }
+    }
+
+    #[test]
+    fn resting_buys_settle_their_lock_as_makers() {
+        let mut engine = Engine::new();
+        let usdt = total(&engine, "USDT");
+        let seller = balance(&engine, "2", "USDT");
+
+        place(
+            &mut engine,
+            "BTC_USDT",
+            "1",
+            "b1",
+            OrderSide::Buy,
+            dec!(60000.33),
+            dec!(0.00007),
+        );
+        for (order_id, qty) in [("s1", dec!(0.00003)), ("s2", dec!(0.00001))] {
+            place(
+                &mut engine,
+                "BTC_USDT",
+                "2",
+                order_id,
+                OrderSide::Sell,
+                dec!(60000),
+                qty,
+            );
+        }
+
+        //1.8000099 and 0.6000033 rounded down
+        assert_eq!(balance(&engine, "2", "USDT").0, seller.0 + dec!(2.400012));
+
+        let book = book(&engine, "BTC_USDT");
+        engine
+            .cancel_resting_order(book, "b1", OrderStatus::Cancelled)
+            .unwrap();
+
+        assert_eq!(balance(&engine, "1", "USDT").1, Decimal::ZERO);
+        assert_eq!(total(&engine, "USDT"), usdt);
+    }
+
+    #[test]
+    fn withdrawals_are_normalized_and_respect_the_minimum() {
+        let mut engine = Engine::new();
+        let btc = balance(&engine, "1", "BTC");
+        let withdraw = |amount: &str| WithdrawData {
+            user_id: "1".to_string(),
+            asset: "BTC".to_string(),
+            amount: amount.to_string(),
+        };
+
+        assert!(engine.withdraw(&withdraw("0.000499999")).is_err());
+
+        let (amount, _) = engine.withdraw(&withdraw("0.123456789")).unwrap();
+        assert_eq!(amount, "0.12345678");
+        assert_eq!(balance(&engine, "1", "BTC").0, btc.0 - dec!(0.12345678));
     }
 }
@@ -0,0 +1,31 @@
+use std::collections::BTreeSet;
+
+use serde::{Deserialize, Serialize};
+
+// resting orders by expiry time, ties broken by insertion sequence so orders expiring at the
+// same instant always come out in the order they were placed
+// entries of orders which left the book early are dropped once they come due
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct ExpiryIndex {
+    sequence: u64,
+    //(expire_at, sequence, order id)
+    entries: BTreeSet<(u64, u64, String)>,
+}
+
+impl ExpiryIndex {
+    pub fn insert(&mut self, expire_at: u64, order_id: String) {
+        self.entries.insert((expire_at, self.sequence, order_id));
+        self.sequence += 1;
+    }
+
+    //every order due at or before the given engine time, earliest first
+    pub fn take_expired(&mut self, now: u64) -> Vec<String> {
+        let pending = self.entries.split_off(&(now + 1, 0, String::new()));
+        let expired = std::mem::replace(&mut self.entries, pending);
+
+        expired
+            .into_iter()
+            .map(|(_, _, order_id)| order_id)
+            .collect()
+    }
+}
@@ -0,0 +1,53 @@
+use std::collections::HashMap;
+
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+use crate::models::order::Order;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GroupType {
+    //take profit limit and stop loss, a fill on either cancels the other
+    Oco,
+    //entry order whose fill places an oco pair on the other side
+    Bracket,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct OrderGroup {
+    pub group_id: String,
+    pub group_type: GroupType,
+    pub user_id: String,
+    //live members, the entry of a bracket or both legs of an oco
+    pub order_ids: Vec<String>,
+    //bracket exit legs waiting for the entry to fill
+    pub pending: Vec<Order>,
+    //both oco legs sell the same inventory, so funds are held once for the group
+    //instead of once per leg
+    pub locked: Decimal,
+}
+
+// linked orders of a market, a group is dropped as soon as it is resolved or cancelled
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct OrderGroups {
+    groups: HashMap<String, OrderGroup>,
+}
+
+impl OrderGroups {
+    pub fn insert(&mut self, group: OrderGroup) {
+        self.groups.insert(group.group_id.clone(), group);
+    }
+
+    pub fn get(&self, group_id: &str) -> Option<&OrderGroup> {
+        self.groups.get(group_id)
+    }
+
+    pub fn get_mut(&mut self, group_id: &str) -> Option<&mut OrderGroup> {
+        self.groups.get_mut(group_id)
+    }
+
+    pub fn remove(&mut self, group_id: &str) -> Option<OrderGroup> {
+        self.groups.remove(group_id)
+    }
+}
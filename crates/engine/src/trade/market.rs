@@ -0,0 +1,115 @@
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+use super::matching::MatchingConfig;
+use crate::models::fixed::Scale;
+
+// lifecycle of a market, independent of how its orders are matched
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MarketStatus {
+    //listed but not trading yet, orders are collected for the opening auction
+    PreOpen,
+    #[default]
+    Open,
+    //frozen during an incident, nothing is accepted until the market is moved on
+    Halted,
+    CancelOnly,
+    //out of session, resting orders stay on the book and can be cancelled
+    Closed,
+    //final, every resting order was cancelled on the way in
+    Delisted,
+}
+
+impl MarketStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MarketStatus::PreOpen => "pre-open",
+            MarketStatus::Open => "open",
+            MarketStatus::Halted => "halted",
+            MarketStatus::CancelOnly => "cancel-only",
+            MarketStatus::Closed => "closed",
+            MarketStatus::Delisted => "delisted",
+        }
+    }
+
+    //new orders and amends
+    pub fn accepts_orders(&self) -> bool {
+        matches!(self, MarketStatus::PreOpen | MarketStatus::Open)
+    }
+
+    pub fn accepts_cancels(&self) -> bool {
+        matches!(
+            self,
+            MarketStatus::PreOpen
+                | MarketStatus::Open
+                | MarketStatus::CancelOnly
+                | MarketStatus::Closed
+        )
+    }
+}
+
+//per market settings, defaults to a plain fifo market without a day session
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct MarketConfig {
+    //millis after utc midnight at which day orders expire, none when the market has no session
+    pub session_end: Option<u64>,
+    pub matching: MatchingConfig,
+    //tick and lot size, prices and quantities of the market's orders are whole multiples of them
+    #[serde(default)]
+    pub scale: Scale,
+    //new markets collect orders in an opening auction instead of trading straight away
+    #[serde(default)]
+    pub opening_auction: bool,
+    //frequent batch auction mode instead of continuous matching
+    #[serde(default)]
+    pub batch: Option<BatchConfig>,
+    #[serde(default)]
+    pub price_band: Option<PriceBand>,
+    #[serde(default)]
+    pub circuit_breaker: Option<CircuitBreaker>,
+}
+
+// orders are collected for `interval` millis of engine time and then cleared together at one
+// price, the side left over at that price is filled pro-rata in whole lots
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct BatchConfig {
+    pub interval: u64,
+    pub lot_size: Decimal,
+}
+
+// collar around a reference price, limit orders priced outside it are rejected and market
+// orders never trade through it
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PriceBand {
+    pub reference: BandReference,
+    //fraction of the reference on either side, 0.05 for 5%
+    pub percent: Decimal,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum BandReference {
+    LastTrade,
+    //rolling vwap over `window` millis of engine time, the last trade when nothing traded in it
+    Vwap { window: u64 },
+}
+
+// trips once the trade prices within `window` millis of engine time spread further apart than
+// `percent` of the lowest of them
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CircuitBreaker {
+    pub percent: Decimal,
+    pub window: u64,
+    pub action: BreakerAction,
+    //millis a volatility auction runs before it uncrosses, none to wait for an admin
+    #[serde(default)]
+    pub auction_duration: Option<u64>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BreakerAction {
+    Halt,
+    Auction,
+}
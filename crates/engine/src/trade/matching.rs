@@ -0,0 +1,343 @@
+use rust_decimal::{Decimal, prelude::ToPrimitive};
+use serde::{Deserialize, Serialize};
+
+use crate::models::{
+    fixed::{Lots, Scale},
+    order::Order,
+};
+
+// decides how an incoming quantity is shared between the resting orders of one price level
+// the level is given in time priority, the result has one quantity per order
+pub trait MatchingPolicy {
+    fn allocate(&self, level: &[Order], quantity: Lots) -> Vec<Lots>;
+}
+
+//matching algorithm of a market, part of the market config
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(tag = "algorithm", rename_all = "snake_case")]
+pub enum MatchingConfig {
+    #[default]
+    Fifo,
+    ProRata {
+        lot_size: Decimal,
+        //share of the incoming quantity given out in time priority before the pro-rata split,
+        //zero for pure pro-rata
+        #[serde(default)]
+        fifo_slice: Decimal,
+    },
+}
+
+impl MatchingConfig {
+    //allocation lots are whole market lots, at least one
+    pub fn policy(&self, scale: &Scale) -> Box<dyn MatchingPolicy> {
+        match self {
+            MatchingConfig::Fifo => Box::new(Fifo),
+            MatchingConfig::ProRata {
+                lot_size,
+                fifo_slice,
+            } => Box::new(ProRata::new(
+                scale.to_lots(*lot_size).unwrap_or_default(),
+                *fifo_slice,
+            )),
+        }
+    }
+}
+
+//price-time priority, the oldest order at the level is filled first
+pub struct Fifo;
+
+impl MatchingPolicy for Fifo {
+    fn allocate(&self, level: &[Order], quantity: Lots) -> Vec<Lots> {
+        let mut allocations = vec![Lots::ZERO; level.len()];
+        fill_in_time_priority(level, &mut allocations, quantity);
+        allocations
+    }
+}
+
+//parts per ten thousand the fifo slice is kept in
+const SLICE_DENOMINATOR: i128 = 10_000;
+
+// shares the quantity in proportion to each order's visible size, rounded down to whole lots
+// leftover lots go out one at a time in time priority, so the same book always splits the same way
+pub struct ProRata {
+    pub lot: Lots,
+    //fifo slice in parts per ten thousand
+    pub fifo_slice: i64,
+}
+
+impl ProRata {
+    pub fn new(lot: Lots, fifo_slice: Decimal) -> Self {
+        let fifo_slice = (fifo_slice.clamp(Decimal::ZERO, Decimal::ONE)
+            * Decimal::from(SLICE_DENOMINATOR))
+        .floor()
+        .to_i64()
+        .unwrap_or_default();
+
+        Self {
+            lot: lot.max(Lots(1)),
+            fifo_slice,
+        }
+    }
+}
+
+impl MatchingPolicy for ProRata {
+    fn allocate(&self, level: &[Order], quantity: Lots) -> Vec<Lots> {
+        let mut allocations = vec![Lots::ZERO; level.len()];
+
+        let total: Lots = level.iter().map(Order::visible).sum();
+        if total <= quantity {
+            return level.iter().map(Order::visible).collect();
+        }
+
+        //top of book slice
+        let slice = Lots((quantity.0 as i128 * self.fifo_slice as i128 / SLICE_DENOMINATOR) as i64);
+        let mut left = quantity - fill_in_time_priority(level, &mut allocations, slice);
+
+        let capacity: Vec<Lots> = level
+            .iter()
+            .zip(&allocations)
+            .map(|(order, allocated)| order.visible() - *allocated)
+            .collect();
+        let total_capacity: Lots = capacity.iter().sum();
+
+        if !total_capacity.is_zero() {
+            let lot = self.lot.0 as i128;
+            let mut shared = Lots::ZERO;
+
+            for (allocated, cap) in allocations.iter_mut().zip(&capacity) {
+                let share = left.0 as i128 * cap.0 as i128 / total_capacity.0 as i128 / lot * lot;
+                let share = Lots(share as i64).min(*cap);
+                *allocated += share;
+                shared += share;
+            }
+
+            left -= shared;
+        }
+
+        //leftover lots, one per order per pass in time priority
+        while left > Lots::ZERO {
+            let mut given = Lots::ZERO;
+
+            for (order, allocated) in level.iter().zip(allocations.iter_mut()) {
+                let take = self.lot.min(left - given).min(order.visible() - *allocated);
+                if take > Lots::ZERO {
+                    *allocated += take;
+                    given += take;
+                }
+            }
+
+            if given.is_zero() {
+                break;
+            }
+            left -= given;
+        }
+
+        allocations
+    }
+}
+
+//fills orders in sequence on top of what they already got, returns the quantity given out
+fn fill_in_time_priority(level: &[Order], allocations: &mut [Lots], quantity: Lots) -> Lots {
+    let mut left = quantity;
+
+    for (order, allocated) in level.iter().zip(allocations.iter_mut()) {
+        if left <= Lots::ZERO {
+            break;
+        }
+
+        let take = left.min(order.visible() - *allocated);
+        if take > Lots::ZERO {
+            *allocated += take;
+            left -= take;
+        }
+    }
+
+    quantity - left
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal_macros::dec;
+
+    use super::*;
+    use crate::{
+        models::{fixed::Ticks, order::OrderSide},
+        trade::{market::MarketConfig, orderbook::Orderbook},
+    };
+
+    fn level(quantities: &[i64]) -> Vec<Order> {
+        quantities
+            .iter()
+            .enumerate()
+            .map(|(i, qty)| {
+                Order::new(
+                    format!("o{}", i),
+                    format!("u{}", i),
+                    OrderSide::Sell,
+                    Ticks(100),
+                    Lots(*qty),
+                    i as u64,
+                )
+            })
+            .collect()
+    }
+
+    fn lots(quantities: &[i64]) -> Vec<Lots> {
+        quantities.iter().copied().map(Lots).collect()
+    }
+
+    //whole units so ticks and lots read as prices and quantities
+    fn book(matching: MatchingConfig) -> Orderbook {
+        let mut book = Orderbook::new(
+            "TATA".to_string(),
+            "INR".to_string(),
+            Vec::new(),
+            Vec::new(),
+            0,
+            Ticks::ZERO,
+        );
+        book.config = MarketConfig {
+            matching,
+            scale: Scale {
+                tick_size: dec!(1),
+                lot_size: dec!(0.5),
+            },
+            ..Default::default()
+        };
+        book
+    }
+
+    //the same orders against a fresh book, returning (maker, qty, price) per fill
+    fn run(matching: MatchingConfig) -> Vec<(String, Lots, Ticks)> {
+        let mut book = book(matching);
+
+        for (i, (price, qty)) in [(101, 14), (100, 6), (100, 20), (100, 10), (102, 8)]
+            .into_iter()
+            .enumerate()
+        {
+            let mut ask = Order::new(
+                format!("ask{}", i),
+                format!("seller{}", i),
+                OrderSide::Sell,
+                Ticks(price),
+                Lots(qty),
+                i as u64,
+            );
+            book.add_order(&mut ask);
+        }
+
+        let mut fills = Vec::new();
+        for (i, qty) in [18, 16, 12].into_iter().enumerate() {
+            let mut bid = Order::new(
+                format!("bid{}", i),
+                "buyer".to_string(),
+                OrderSide::Buy,
+                Ticks(101),
+                Lots(qty),
+                10 + i as u64,
+            );
+            let result = book.add_order(&mut bid);
+            fills.extend(
+                result
+                    .fills
+                    .into_iter()
+                    .map(|f| (f.marker_order_id, f.qty, f.price)),
+            );
+        }
+
+        fills
+    }
+
+    fn pro_rata(lot_size: Decimal, fifo_slice: Decimal) -> MatchingConfig {
+        MatchingConfig::ProRata {
+            lot_size,
+            fifo_slice,
+        }
+    }
+
+    #[test]
+    fn fifo_fills_in_time_priority() {
+        let allocations = Fifo.allocate(&level(&[3, 10, 5]), Lots(9));
+        assert_eq!(allocations, lots(&[3, 6, 0]));
+    }
+
+    #[test]
+    fn pro_rata_splits_by_size() {
+        let policy = ProRata::new(Lots(1), dec!(0));
+        let allocations = policy.allocate(&level(&[10, 30, 60]), Lots(50));
+        assert_eq!(allocations, lots(&[5, 15, 30]));
+    }
+
+    #[test]
+    fn pro_rata_leftover_lots_follow_time_priority() {
+        let policy = ProRata::new(Lots(1), dec!(0));
+        //9 * 3/18 = 1.5, 9 * 10/18 = 5, 9 * 5/18 = 2.5, floors leave 1 lot for the oldest order
+        let allocations = policy.allocate(&level(&[3, 10, 5]), Lots(9));
+        assert_eq!(allocations, lots(&[2, 5, 2]));
+        assert_eq!(allocations.iter().sum::<Lots>(), Lots(9));
+    }
+
+    #[test]
+    fn hybrid_gives_the_fifo_slice_first() {
+        let policy = ProRata::new(Lots(1), dec!(0.5));
+        //5 goes to the top order in time priority, the other 5 is split over what is left
+        let allocations = policy.allocate(&level(&[10, 10, 20]), Lots(10));
+        assert_eq!(allocations, lots(&[6, 2, 2]));
+        assert_eq!(allocations.iter().sum::<Lots>(), Lots(10));
+    }
+
+    #[test]
+    fn pro_rata_never_exceeds_visible_size() {
+        let policy = ProRata::new(Lots(5), dec!(0));
+        let orders = level(&[1, 2, 40]);
+        let allocations = policy.allocate(&orders, Lots(12));
+
+        for (order, allocated) in orders.iter().zip(&allocations) {
+            assert!(*allocated <= order.visible());
+        }
+        assert_eq!(allocations.iter().sum::<Lots>(), Lots(12));
+    }
+
+    #[test]
+    fn allocation_lots_are_whole_market_lots() {
+        let scale = Scale {
+            tick_size: dec!(1),
+            lot_size: dec!(0.5),
+        };
+        let orders = level(&[10, 30, 60]);
+
+        //a lot size below the market's lot still allocates one market lot at a time
+        let policy = pro_rata(dec!(0.1), dec!(0)).policy(&scale);
+        assert_eq!(policy.allocate(&orders, Lots(50)), lots(&[5, 15, 30]));
+
+        let policy = pro_rata(dec!(5), dec!(0)).policy(&scale);
+        assert_eq!(policy.allocate(&orders, Lots(50)), lots(&[10, 10, 30]));
+    }
+
+    #[test]
+    fn matching_is_identical_across_runs() {
+        for matching in [
+            MatchingConfig::Fifo,
+            pro_rata(dec!(1), dec!(0)),
+            pro_rata(dec!(1), dec!(0.25)),
+            pro_rata(dec!(0.5), dec!(0)),
+        ] {
+            let first = run(matching.clone());
+            for _ in 0..10 {
+                assert_eq!(run(matching.clone()), first);
+            }
+        }
+    }
+
+    #[test]
+    fn fifo_and_pro_rata_fill_the_same_total() {
+        let total = |fills: &[(String, Lots, Ticks)]| -> Lots {
+            fills.iter().map(|(_, qty, _)| *qty).sum()
+        };
+
+        assert_eq!(
+            total(&run(MatchingConfig::Fifo)),
+            total(&run(pro_rata(dec!(1), dec!(0))))
+        );
+    }
+}
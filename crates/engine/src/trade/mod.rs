@@ -1,3 +1,16 @@
+pub mod accounts;
+pub mod assets;
+pub mod auction;
+pub mod bands;
+pub mod bounded_map;
+pub mod client_orders;
+pub mod conditional;
 pub mod engine;
+pub mod expiry;
+pub mod groups;
+pub mod market;
+pub mod matching;
 
 pub mod orderbook;
+pub mod recent_orders;
+pub mod risk;
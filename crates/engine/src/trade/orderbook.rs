@@ -1,15 +1,156 @@
-use std::{collections::HashMap, fmt::Debug};
+use std::{collections::BTreeMap, fmt::Debug};
 
-use crate::models::order::{Fill, Order, OrderSide};
+use crate::models::{
+    fixed::{self, Lots, Ticks},
+    order::{Fill, Order, OrderSide, OrderStatus, PegReference},
+};
 use rust_decimal::Decimal;
 use serde::{Deserialize, Serialize};
 
-pub const BASE_CURRENCY: &str = "INR";
+use super::{
+    auction::{self, Indicative, TradingPhase},
+    bands::PriceHistory,
+    conditional::ConditionalOrders,
+    expiry::ExpiryIndex,
+    groups::OrderGroups,
+    market::{BandReference, MarketConfig, MarketStatus},
+    matching::{MatchingPolicy, ProRata},
+};
+
+//aggregated (price, quantity) of one visible level
+pub type DepthLevel = (Ticks, Lots);
+
+//best price first, then regular orders ahead of pegged ones at the same price
+fn ask_priority(order: &Order) -> (Ticks, bool) {
+    (order.price, order.peg.is_some())
+}
+
+fn bid_priority(order: &Order) -> (std::cmp::Reverse<Ticks>, bool) {
+    (std::cmp::Reverse(order.price), order.peg.is_some())
+}
+
+// quantity each order of one side, sorted by priority, trades in an uncross at `price`
+// whole orders take part, hidden iceberg reserve included
+fn auction_allocations(
+    orders: &[Order],
+    price: Ticks,
+    volume: Lots,
+    policy: &dyn MatchingPolicy,
+) -> Vec<Lots> {
+    let mut left = volume;
+    let mut allocations: Vec<Lots> = orders
+        .iter()
+        .take_while(|o| o.price != price)
+        .map(|o| {
+            let take = o.remaining().min(left);
+            left -= take;
+            take
+        })
+        .collect();
+
+    let level: Vec<Order> = orders[allocations.len()..]
+        .iter()
+        .take_while(|o| o.price == price)
+        .map(|o| {
+            let mut order = o.clone();
+            order.visible_quantity = None;
+            order
+        })
+        .collect();
+
+    allocations.extend(policy.allocate(&level, left));
+    allocations.resize(orders.len(), Lots::ZERO);
+    allocations
+}
+
+// walks the resting side level by level, a level being the orders of one priority class at one
+// price, and lets the market's matching policy split the incoming quantity within each level
+fn match_against<K: Ord>(
+    resting: &mut Vec<Order>,
+    order: &Order,
+    crosses: impl Fn(Ticks) -> bool,
+    priority: impl Fn(&Order) -> K,
+    policy: &dyn MatchingPolicy,
+    last_trade_id: &mut u64,
+) -> OrderMatchResult {
+    let mut fills = Vec::new();
+    let mut executed_qty = Lots::ZERO;
+
+    resting.sort_by_key(|o| priority(o));
+
+    let mut start = 0;
+    while start < resting.len() && executed_qty < order.remaining() && crosses(resting[start].price)
+    {
+        let key = priority(&resting[start]);
+        let end = start
+            + resting[start..]
+                .iter()
+                .take_while(|o| priority(o) == key)
+                .count();
+
+        let allocations = policy.allocate(&resting[start..end], order.remaining() - executed_qty);
+        let mut level_qty = Lots::ZERO;
+
+        for (maker, qty) in resting[start..end].iter_mut().zip(allocations) {
+            if qty <= Lots::ZERO {
+                continue;
+            }
+
+            let price = maker.price;
+            maker.fill(qty, price, order.updated_at);
+
+            fills.push(Fill {
+                price,
+                qty,
+                trade_id: *last_trade_id,
+                other_user_id: maker.user_id.clone(),
+                marker_order_id: maker.order_id.clone(),
+                maker_remaining: maker.remaining(),
+            });
+
+            *last_trade_id += 1;
+            level_qty += qty;
+        }
+        executed_qty += level_qty;
+
+        //exhausted iceberg slices refresh and go to the back of their level
+        let (exhausted, mut level): (Vec<Order>, Vec<Order>) = resting
+            .drain(start..end)
+            .partition(|o| o.visible().is_zero() && !o.remaining().is_zero());
+        let refreshed = !exhausted.is_empty();
+        for mut maker in exhausted {
+            maker.refresh_slice();
+            level.push(maker);
+        }
+
+        let len = level.len();
+        resting.splice(start..start, level);
+
+        //a level with fresh slices can keep trading, otherwise it is used up
+        if level_qty.is_zero() || !refreshed {
+            start += len;
+        }
+    }
+
+    //removing filled orders
+    let (filled_orders, open): (Vec<Order>, Vec<Order>) = resting
+        .drain(..)
+        .partition(|order| order.filled >= order.quantity);
+    *resting = open;
+
+    OrderMatchResult {
+        executed_qty,
+        fills,
+        filled_orders,
+    }
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct OrderMatchResult {
-    pub executed_qty: Decimal,
+    pub executed_qty: Lots,
     pub fills: Vec<Fill>,
+    //maker orders which got fully filled and left the book
+    pub filled_orders: Vec<Order>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -19,173 +160,300 @@ pub struct Orderbook {
     pub base_asset: String,
     pub quote_asset: String,
     pub last_trade_id: u64,
-    pub current_price: Decimal,
+    pub current_price: Ticks,
+    pub conditional: ConditionalOrders,
+    pub groups: OrderGroups,
+    pub expiries: ExpiryIndex,
+    pub config: MarketConfig,
+    pub phase: TradingPhase,
+    pub status: MarketStatus,
+    pub history: PriceHistory,
+    //engine time a volatility auction uncrosses at
+    pub auction_end: Option<u64>,
+    //engine time of the last batch boundary, batch markets only
+    pub last_batch: u64,
 }
 
 impl Orderbook {
     pub fn new(
         base_asset: String,
+        quote_asset: String,
         bids: Vec<Order>,
         asks: Vec<Order>,
         last_trade_id: u64,
-        current_price: Decimal,
+        current_price: Ticks,
     ) -> Self {
         Self {
             bids,
             asks,
             base_asset,
-            quote_asset: BASE_CURRENCY.to_string(),
+            quote_asset,
             last_trade_id,
             current_price,
+            conditional: ConditionalOrders::default(),
+            groups: OrderGroups::default(),
+            expiries: ExpiryIndex::default(),
+            config: MarketConfig::default(),
+            phase: TradingPhase::Continuous,
+            status: MarketStatus::Open,
+            history: PriceHistory::default(),
+            auction_end: None,
+            last_batch: 0,
         }
     }
 
+    //markets configured with an opening auction or batches start out collecting orders
+    pub fn with_config(mut self, config: MarketConfig) -> Self {
+        if config.batch.is_some() {
+            self.phase = TradingPhase::Batch;
+        } else if config.opening_auction {
+            self.phase = TradingPhase::OpeningAuction;
+        }
+        self.config = config;
+        self
+    }
+
     pub fn ticker(&self) -> String {
         format!("{}_{}", self.base_asset, self.quote_asset)
     }
 
+    #[allow(dead_code)]
     pub fn get_snapshot(&self) -> Self {
         self.clone()
     }
 
-    pub fn add_order(&mut self, mut order: Order) -> OrderMatchResult {
-        match order.side {
-            OrderSide::Buy => {
-                let result = self.match_bid(&order);
-                order.filled = result.executed_qty;
+    pub fn add_order(&mut self, order: &mut Order) -> OrderMatchResult {
+        //auctions only collect orders, nothing trades until the book is uncrossed
+        let collecting = self.phase.is_auction() || self.status != MarketStatus::Open;
+        let result = match (collecting, &order.side) {
+            (true, _) => OrderMatchResult {
+                executed_qty: Lots::ZERO,
+                fills: Vec::new(),
+                filled_orders: Vec::new(),
+            },
+            (false, OrderSide::Buy) => self.match_bid(order),
+            (false, OrderSide::Sell) => self.match_ask(order),
+        };
+
+        for fill in &result.fills {
+            order.fill(fill.qty, fill.price, order.updated_at);
+        }
 
-                if order.filled < order.quantity {
-                    self.bids.push(order);
-                }
-                result
-            }
-            OrderSide::Sell => {
-                let result = self.match_ask(&order);
-                order.filled = result.executed_qty;
+        if let Some(last) = result.fills.last() {
+            self.current_price = last.price;
+        }
 
-                if order.filled < order.quantity {
-                    self.asks.push(order);
+        if order.filled < order.quantity {
+            if order.order_type.is_market() {
+                //market orders never rest, whatever is left is cancelled
+                order.close(OrderStatus::Cancelled, order.updated_at);
+            } else {
+                order.refresh_slice();
+                match order.side {
+                    OrderSide::Buy => self.bids.push(order.clone()),
+                    OrderSide::Sell => self.asks.push(order.clone()),
                 }
-
-                result
             }
         }
+
+        result
     }
 
     fn match_bid(&mut self, order: &Order) -> OrderMatchResult {
-        let mut fills = Vec::new();
-        let mut executed_qty: Decimal = Decimal::ZERO;
-
-        //sorting ask price lowest first
-        self.asks.sort_by(|a, b| a.price.cmp(&b.price));
-
-        let mut i = 0;
-        while i < self.asks.len() {
-            if self.asks[i].price <= order.price && executed_qty < order.quantity {
-                let filled_qty = std::cmp::min(
-                    (order.quantity - executed_qty),
-                    (self.asks[i].quantity - self.asks[i].filled),
-                );
-
-                executed_qty += filled_qty;
-                self.asks[i].filled += filled_qty;
+        let policy = self.config.matching.policy(&self.config.scale);
+
+        //asks lowest price first
+        match_against(
+            &mut self.asks,
+            order,
+            |price| price <= order.price,
+            ask_priority,
+            policy.as_ref(),
+            &mut self.last_trade_id,
+        )
+    }
 
-                fills.push(Fill {
-                    price: self.asks[i].price.to_string(),
-                    qty: filled_qty,
-                    trade_id: self.last_trade_id,
-                    other_user_id: self.asks[i].user_id.clone(),
-                    marker_order_id: self.asks[i].order_id.clone(),
-                });
+    fn match_ask(&mut self, order: &Order) -> OrderMatchResult {
+        let policy = self.config.matching.policy(&self.config.scale);
+
+        //bids highest price first
+        match_against(
+            &mut self.bids,
+            order,
+            |price| price >= order.price,
+            bid_priority,
+            policy.as_ref(),
+            &mut self.last_trade_id,
+        )
+    }
 
-                self.last_trade_id += 1;
+    //whether a batch market reached the end of its current batch at the given engine time
+    pub fn batch_due(&self, now: u64) -> bool {
+        match &self.config.batch {
+            Some(batch) => {
+                self.status == MarketStatus::Open
+                    && self.phase == TradingPhase::Batch
+                    && now >= self.last_batch + batch.interval
             }
+            None => false,
+        }
+    }
+
+    // bounds limit orders have to be priced within, none when the market has no band or there
+    // is no reference price yet, both rounded towards the reference
+    pub fn price_band(&self, now: u64) -> Option<(Ticks, Ticks)> {
+        let band = self.config.price_band.as_ref()?;
+        let reference = match band.reference {
+            BandReference::LastTrade => None,
+            BandReference::Vwap { window } => self.history.vwap(now.saturating_sub(window)),
+        }
+        .unwrap_or(self.current_price);
 
-            i += 1;
+        if reference <= Ticks::ZERO {
+            return None;
         }
 
-        //  removing fills order
-        self.asks.retain(|order| order.filled < order.quantity);
+        let width = fixed::percent_of(reference, band.percent);
+        Some((reference - width, reference + width))
+    }
 
-        OrderMatchResult {
-            executed_qty,
-            fills,
+    //keeps trades for the band and breaker windows, markets with neither keep nothing
+    pub fn record_trades(&mut self, now: u64, fills: &[Fill]) {
+        let vwap_window = match &self.config.price_band {
+            Some(band) => match band.reference {
+                BandReference::Vwap { window } => Some(window),
+                BandReference::LastTrade => None,
+            },
+            None => None,
+        };
+        let breaker_window = self.config.circuit_breaker.as_ref().map(|b| b.window);
+
+        let Some(retention) = vwap_window.max(breaker_window) else {
+            return;
+        };
+
+        for fill in fills {
+            self.history.record(now, fill.price, fill.qty, retention);
         }
     }
 
-    fn match_ask(&mut self, order: &Order) -> OrderMatchResult {
-        let mut fills = Vec::new();
-        let mut executed_qty: Decimal = Decimal::ZERO;
+    pub fn breaker_tripped(&self, now: u64) -> bool {
+        let Some(breaker) = &self.config.circuit_breaker else {
+            return false;
+        };
 
-        //sorting ask by price
+        self.history
+            .range(now.saturating_sub(breaker.window))
+            .is_some_and(|(low, high)| {
+                Decimal::from((high - low).0) > Decimal::from(low.0) * breaker.percent
+            })
+    }
 
-        self.asks.sort_by(|a, b| a.price.cmp(&b.price));
+    pub fn indicative(&self) -> Option<Indicative> {
+        auction::clearing_price(&self.bids, &self.asks, self.current_price)
+    }
 
-        let mut i = 0;
+    // executes the auction, every trade happens at the clearing price
+    // orders priced better than the clearing price fill completely and the orders at it share the
+    // rest through the market's allocation policy, batch markets always split it pro-rata
+    // buys are then paired with sells in priority order and each buy is returned with its fills
+    // as if it had been the taker, so settlement works like continuous matching
+    pub fn uncross(&mut self, timestamp: u64) -> Option<(Ticks, Vec<(Order, OrderMatchResult)>)> {
+        let Indicative { price, volume } = self.indicative()?;
+
+        let policy: Box<dyn MatchingPolicy> = match &self.config.batch {
+            Some(batch) => Box::new(ProRata::new(
+                self.config
+                    .scale
+                    .to_lots(batch.lot_size)
+                    .unwrap_or_default(),
+                Decimal::ZERO,
+            )),
+            None => self.config.matching.policy(&self.config.scale),
+        };
+
+        self.bids.sort_by_key(bid_priority);
+        self.asks.sort_by_key(ask_priority);
+
+        let mut bid_allocations = auction_allocations(&self.bids, price, volume, policy.as_ref());
+        let mut ask_allocations = auction_allocations(&self.asks, price, volume, policy.as_ref());
+
+        let mut results = Vec::new();
+        let mut ask = 0;
+
+        for (bid, allocated) in self.bids.iter_mut().zip(bid_allocations.iter_mut()) {
+            let mut fills = Vec::new();
+            let mut filled_orders = Vec::new();
+            let mut executed_qty = Lots::ZERO;
+
+            while *allocated > Lots::ZERO && ask < self.asks.len() {
+                if ask_allocations[ask].is_zero() {
+                    ask += 1;
+                    continue;
+                }
 
-        while i < self.asks.len() {
-            if self.asks[i].price <= order.price && executed_qty < order.quantity {
-                let filled_qty = std::cmp::min(
-                    (order.quantity - executed_qty),
-                    (self.asks[i].quantity - self.asks[i].filled),
-                );
+                let maker = &mut self.asks[ask];
+                let qty = (*allocated).min(ask_allocations[ask]);
 
-                executed_qty += filled_qty;
-                self.asks[i].filled += filled_qty;
+                maker.fill(qty, price, timestamp);
+                bid.fill(qty, price, timestamp);
+                *allocated -= qty;
+                ask_allocations[ask] -= qty;
+                executed_qty += qty;
 
                 fills.push(Fill {
-                    price: self.asks[i].price.to_string(),
-                    qty: filled_qty,
+                    price,
+                    qty,
                     trade_id: self.last_trade_id,
-                    other_user_id: self.asks[i].user_id.clone(),
-                    marker_order_id: self.asks[i].order_id.clone(),
+                    other_user_id: maker.user_id.clone(),
+                    marker_order_id: maker.order_id.clone(),
+                    maker_remaining: maker.remaining(),
                 });
-
                 self.last_trade_id += 1;
-            }
-            i += 1;
-        }
 
-        //removing filled orders
+                if maker.remaining().is_zero() {
+                    filled_orders.push(maker.clone());
+                }
+            }
 
-        self.asks.retain(|order| order.filled < order.quantity);
+            if !fills.is_empty() {
+                results.push((
+                    bid.clone(),
+                    OrderMatchResult {
+                        executed_qty,
+                        fills,
+                        filled_orders,
+                    },
+                ));
+            }
+        }
 
-        OrderMatchResult {
-            executed_qty,
-            fills,
+        self.bids.retain(|o| o.filled < o.quantity);
+        self.asks.retain(|o| o.filled < o.quantity);
+        for order in self.bids.iter_mut().chain(self.asks.iter_mut()) {
+            order.refresh_slice();
         }
+        self.current_price = price;
+
+        Some((price, results))
     }
 
-    pub fn get_depth(&self) -> (Vec<(String, String)>, Vec<(String, String)>) {
-        let mut bids: HashMap<String, Decimal> = HashMap::new();
-        let mut asks: HashMap<String, Decimal> = HashMap::new();
+    //best price first on both sides
+    pub fn get_depth(&self) -> (Vec<DepthLevel>, Vec<DepthLevel>) {
+        let mut bids: BTreeMap<Ticks, Lots> = BTreeMap::new();
+        let mut asks: BTreeMap<Ticks, Lots> = BTreeMap::new();
 
-        //aggegrating order of same price
-        for order in &self.bids {
-            let price = order.price.to_string();
-            let remaining = order.quantity - order.filled;
-            *bids.entry(price).or_insert(Decimal::ZERO) += remaining;
+        //aggegrating order of same price, icebergs only count their visible slice
+        //and midpoint pegs are left out
+        for order in self.bids.iter().filter(|o| !o.is_hidden()) {
+            *bids.entry(order.price).or_default() += order.visible();
         }
 
-        for order in &self.asks {
-            let price = order.price.to_string();
-            let remaining = order.quantity - order.filled;
-            *asks.entry(price).or_insert(Decimal::ZERO) += remaining;
+        for order in self.asks.iter().filter(|o| !o.is_hidden()) {
+            *asks.entry(order.price).or_default() += order.visible();
         }
 
-        //converting vectors to tuples
-
-        let bids_vec: Vec<(String, String)> = bids
-            .iter()
-            .map(|(price, qty)| (price.clone(), qty.to_string()))
-            .collect();
-
-        let ask_vec: Vec<(String, String)> = asks
-            .iter()
-            .map(|(price, qty)| (price.clone(), qty.to_string()))
-            .collect();
-
-        (bids_vec, ask_vec)
+        (bids.into_iter().rev().collect(), asks.into_iter().collect())
     }
 
     pub fn get_open_orders(&self, user_id: &str) -> Vec<Order> {
@@ -203,12 +471,130 @@ impl Orderbook {
             }
         }
 
+        for order in self.conditional.iter() {
+            if order.user_id == user_id {
+                orders.push(order.clone());
+            }
+        }
+
         orders
     }
 
+    //resting orders on both sides followed by untriggered stop orders
+    pub fn orders(&self) -> impl Iterator<Item = &Order> {
+        self.bids
+            .iter()
+            .chain(self.asks.iter())
+            .chain(self.conditional.iter())
+    }
+
+    pub fn best_bid(&self) -> Option<Ticks> {
+        self.bids.iter().map(|o| o.price).max()
+    }
+
+    pub fn best_ask(&self) -> Option<Ticks> {
+        self.asks.iter().map(|o| o.price).min()
+    }
+
+    //references come from regular orders only so pegs never chase each other
+    fn reference_price(&self, peg: PegReference) -> Option<Ticks> {
+        let best_bid = self
+            .bids
+            .iter()
+            .filter(|o| o.peg.is_none())
+            .map(|o| o.price)
+            .max();
+        let best_ask = self
+            .asks
+            .iter()
+            .filter(|o| o.peg.is_none())
+            .map(|o| o.price)
+            .min();
+
+        match peg {
+            PegReference::BestBid => best_bid,
+            PegReference::BestAsk => best_ask,
+            //half ticks round down
+            PegReference::Mid => Some(Ticks((best_bid?.0 + best_ask?.0) / 2)),
+        }
+    }
+
+    //reference plus offset, held within the cap, none while the reference is missing
+    pub fn peg_price(&self, order: &Order) -> Option<Ticks> {
+        let mut price = self.reference_price(order.peg?)? + order.peg_offset.unwrap_or_default();
+
+        if let Some(cap) = order.peg_cap {
+            price = match order.side {
+                OrderSide::Buy => price.min(cap),
+                OrderSide::Sell => price.max(cap),
+            };
+        }
+
+        (price > Ticks::ZERO).then_some(price)
+    }
+
+    // resting pegs whose reference moved, with the price they should move to
+    // a peg never moves onto the other side of the book, it waits there until the book moves back
+    pub fn peg_updates(&self) -> Vec<(String, Ticks)> {
+        //the book can be crossed during an auction, pegs wait for continuous trading
+        if self.phase.is_auction() {
+            return Vec::new();
+        }
+
+        let best_bid = self.best_bid();
+        let best_ask = self.best_ask();
+        let mut updates = Vec::new();
+
+        for order in self.bids.iter().filter(|o| o.peg.is_some()) {
+            if let Some(price) = self.peg_price(order)
+                && price != order.price
+                && best_ask.is_none_or(|ask| price < ask)
+            {
+                updates.push((order.order_id.clone(), price));
+            }
+        }
+
+        for order in self.asks.iter().filter(|o| o.peg.is_some()) {
+            if let Some(price) = self.peg_price(order)
+                && price != order.price
+                && best_bid.is_none_or(|bid| price > bid)
+            {
+                updates.push((order.order_id.clone(), price));
+            }
+        }
+
+        updates
+    }
+
+    pub fn get_order(&self, order_id: &str) -> Option<&Order> {
+        self.orders().find(|o| o.order_id == order_id)
+    }
+
+    pub fn get_order_mut(&mut self, order_id: &str) -> Option<&mut Order> {
+        self.bids
+            .iter_mut()
+            .chain(self.asks.iter_mut())
+            .find(|o| o.order_id == order_id)
+    }
+
+    pub fn get_order_by_client_id(&self, user_id: &str, client_order_id: &str) -> Option<&Order> {
+        self.orders()
+            .find(|o| o.user_id == user_id && o.client_order_id.as_deref() == Some(client_order_id))
+    }
+
+    pub fn remove_order(&mut self, order_id: &str) -> Option<Order> {
+        if let Some(pos) = self.bids.iter().position(|o| o.order_id == order_id) {
+            Some(self.bids.remove(pos))
+        } else if let Some(pos) = self.asks.iter().position(|o| o.order_id == order_id) {
+            Some(self.asks.remove(pos))
+        } else {
+            self.conditional.remove(order_id)
+        }
+    }
+
     //canceling a bid order
 
-    pub fn cancel_bid(&mut self, order: &Order) -> Option<Decimal> {
+    pub fn cancel_bid(&mut self, order: &Order) -> Option<Ticks> {
         if let Some(pos) = self.bids.iter().position(|o| o.order_id == order.order_id) {
             let price = self.bids[pos].price;
             self.bids.remove(pos);
@@ -219,7 +605,7 @@ impl Orderbook {
     }
 
     //cancel an ask order
-    pub fn cancel_ask(&mut self, order: &Order) -> Option<Decimal> {
+    pub fn cancel_ask(&mut self, order: &Order) -> Option<Ticks> {
         if let Some(pos) = self.asks.iter().position(|o| o.order_id == order.order_id) {
             let price = self.asks[pos].price;
             self.asks.remove(pos);
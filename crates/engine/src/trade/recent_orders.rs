@@ -0,0 +1,28 @@
+use crate::models::{fixed::Scale, order::Order};
+
+use super::bounded_map::BoundedMap;
+
+pub const RECENT_ORDERS_CAPACITY: usize = 10_000;
+
+// bounded cache of orders that have left the book (filled, cancelled, rejected, expired)
+// older entries are evicted in insertion order, the db keeps the full history
+// each order is kept with the scale of its market so it can still be reported once it is gone
+pub struct RecentOrders {
+    orders: BoundedMap<String, (Order, Scale)>,
+}
+
+impl RecentOrders {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            orders: BoundedMap::new(capacity),
+        }
+    }
+
+    pub fn insert(&mut self, order: Order, scale: Scale) {
+        self.orders.insert(order.order_id.clone(), (order, scale));
+    }
+
+    pub fn get(&self, order_id: &str) -> Option<&(Order, Scale)> {
+        self.orders.get(order_id)
+    }
+}
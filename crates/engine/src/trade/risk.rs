@@ -0,0 +1,149 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt,
+};
+
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+const RATE_WINDOW: u64 = 1000;
+
+// pre-trade limits of one user, a limit left out is not enforced
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct RiskLimits {
+    pub max_order_quantity: Option<Decimal>,
+    pub max_order_notional: Option<Decimal>,
+    //resting and untriggered orders in one market
+    pub max_open_orders: Option<usize>,
+    //value of open orders at their limit price, per quote asset since values in different
+    //assets can't be added up, an asset left out is not limited
+    #[serde(default)]
+    pub max_open_notional: HashMap<String, Decimal>,
+    pub max_orders_per_second: Option<usize>,
+}
+
+//the limit an order breached
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RiskRejection {
+    OrderQuantity,
+    OrderNotional,
+    OpenOrders,
+    OpenNotional,
+    OrderRate,
+}
+
+impl RiskRejection {
+    pub fn code(&self) -> &'static str {
+        match self {
+            RiskRejection::OrderQuantity => "MAX_ORDER_QUANTITY",
+            RiskRejection::OrderNotional => "MAX_ORDER_NOTIONAL",
+            RiskRejection::OpenOrders => "MAX_OPEN_ORDERS",
+            RiskRejection::OpenNotional => "MAX_OPEN_NOTIONAL",
+            RiskRejection::OrderRate => "MAX_ORDER_RATE",
+        }
+    }
+}
+
+impl fmt::Display for RiskRejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let reason = match self {
+            RiskRejection::OrderQuantity => "Order quantity above the user limit",
+            RiskRejection::OrderNotional => "Order value above the user limit",
+            RiskRejection::OpenOrders => "Too many open orders in this market",
+            RiskRejection::OpenNotional => "Open order value above the user limit",
+            RiskRejection::OrderRate => "Too many orders per second",
+        };
+        write!(f, "{}", reason)
+    }
+}
+
+impl std::error::Error for RiskRejection {}
+
+//what the user would have open if the new order was accepted
+pub struct Exposure {
+    pub quantity: Decimal,
+    pub notional: Decimal,
+    pub open_orders: usize,
+    //value of the open orders in the market's quote asset, across every market quoted in it
+    pub quote_asset: String,
+    pub open_notional: Decimal,
+    //orders accepted ahead of this one that are not recorded against the rate yet
+    pub pending: usize,
+}
+
+#[derive(Default)]
+pub struct RiskLimiter {
+    //applied to users without limits of their own
+    defaults: RiskLimits,
+    users: HashMap<String, RiskLimits>,
+    //engine time of each user's orders within the rate window
+    recent: HashMap<String, VecDeque<u64>>,
+}
+
+impl RiskLimiter {
+    pub fn limits(&self, user_id: &str) -> &RiskLimits {
+        self.users.get(user_id).unwrap_or(&self.defaults)
+    }
+
+    //none replaces the defaults
+    pub fn set_limits(&mut self, user_id: Option<&str>, limits: RiskLimits) {
+        match user_id {
+            Some(user_id) => {
+                self.users.insert(user_id.to_string(), limits);
+            }
+            None => self.defaults = limits,
+        }
+    }
+
+    pub fn check(&self, user_id: &str, exposure: &Exposure, now: u64) -> Result<(), RiskRejection> {
+        let limits = self.limits(user_id);
+
+        if limits
+            .max_order_quantity
+            .is_some_and(|max| exposure.quantity > max)
+        {
+            return Err(RiskRejection::OrderQuantity);
+        }
+        if limits
+            .max_order_notional
+            .is_some_and(|max| exposure.notional > max)
+        {
+            return Err(RiskRejection::OrderNotional);
+        }
+        if limits
+            .max_open_orders
+            .is_some_and(|max| exposure.open_orders > max)
+        {
+            return Err(RiskRejection::OpenOrders);
+        }
+        if limits
+            .max_open_notional
+            .get(&exposure.quote_asset)
+            .is_some_and(|max| exposure.open_notional > *max)
+        {
+            return Err(RiskRejection::OpenNotional);
+        }
+        if let Some(max) = limits.max_orders_per_second {
+            let recent = self.recent.get(user_id).map_or(0, |times| {
+                times
+                    .iter()
+                    .filter(|time| **time + RATE_WINDOW > now)
+                    .count()
+            });
+            if recent + exposure.pending >= max {
+                return Err(RiskRejection::OrderRate);
+            }
+        }
+
+        Ok(())
+    }
+
+    //counts an accepted order against the user's rate limit
+    pub fn record(&mut self, user_id: &str, now: u64) {
+        let times = self.recent.entry(user_id.to_string()).or_default();
+        while times.front().is_some_and(|time| time + RATE_WINDOW <= now) {
+            times.pop_front();
+        }
+        times.push_back(now);
+    }
+}
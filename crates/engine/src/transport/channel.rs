@@ -0,0 +1,44 @@
+use futures::{FutureExt, future::BoxFuture};
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+
+use crate::types::api::MessageFromApi;
+
+use super::{CommandSource, Event, EventSink, TransportResult};
+
+// in memory transport, for tests and for embedding the engine in another process
+pub struct ChannelSink {
+    sender: UnboundedSender<Event>,
+}
+
+pub struct ChannelSource {
+    receiver: UnboundedReceiver<MessageFromApi>,
+}
+
+//sink plus the receiving end its events come out of
+pub fn sink() -> (ChannelSink, UnboundedReceiver<Event>) {
+    let (sender, receiver) = mpsc::unbounded_channel();
+    (ChannelSink { sender }, receiver)
+}
+
+//source plus the sending end its commands go into
+pub fn source() -> (UnboundedSender<MessageFromApi>, ChannelSource) {
+    let (sender, receiver) = mpsc::unbounded_channel();
+    (sender, ChannelSource { receiver })
+}
+
+impl EventSink for ChannelSink {
+    fn send(&self, event: Event) -> BoxFuture<'_, TransportResult<()>> {
+        let result = self
+            .sender
+            .send(event)
+            .map_err(|_| "event receiver dropped".into());
+        futures::future::ready(result).boxed()
+    }
+}
+
+impl CommandSource for ChannelSource {
+    fn next(&mut self) -> BoxFuture<'_, TransportResult<Option<MessageFromApi>>> {
+        let message = self.receiver.try_recv().ok();
+        futures::future::ready(Ok(message)).boxed()
+    }
+}
@@ -0,0 +1,348 @@
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+    },
+};
+
+use futures::{FutureExt, future::BoxFuture};
+use log::{error, info, warn};
+use tokio::{
+    sync::mpsc::{self, Receiver, Sender, error::TrySendError},
+    task::JoinHandle,
+};
+
+use super::{Event, EventSink, TransportResult};
+
+#[derive(Clone, Copy, Debug)]
+pub struct DispatcherConfig {
+    //events each worker holds before matching has to wait for it
+    pub capacity: usize,
+    //most events handed to a sink in one go
+    pub batch_size: usize,
+}
+
+impl Default for DispatcherConfig {
+    fn default() -> Self {
+        Self {
+            capacity: 10_000,
+            batch_size: 128,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct DispatchMetrics {
+    enqueued: AtomicU64,
+    dispatched: AtomicU64,
+    failed: AtomicU64,
+    batches: AtomicU64,
+    //events which found their worker's queue full and held up matching
+    blocked: AtomicU64,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MetricsSnapshot {
+    pub enqueued: u64,
+    pub dispatched: u64,
+    pub failed: u64,
+    pub batches: u64,
+    pub blocked: u64,
+}
+
+impl DispatchMetrics {
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        MetricsSnapshot {
+            enqueued: self.enqueued.load(Ordering::Relaxed),
+            dispatched: self.dispatched.load(Ordering::Relaxed),
+            failed: self.failed.load(Ordering::Relaxed),
+            batches: self.batches.load(Ordering::Relaxed),
+            blocked: self.blocked.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl MetricsSnapshot {
+    //queued and not yet handed to a sink
+    pub fn pending(&self) -> u64 {
+        //the counters are read one by one while workers run, so they can be briefly out of step
+        self.enqueued
+            .saturating_sub(self.dispatched)
+            .saturating_sub(self.failed)
+    }
+}
+
+// takes the engine's events off the matching path, each worker owns one sink and drains its own
+// bounded queue in batches. events are routed by destination so every queue and channel still
+// sees its events in order, and a full queue makes the sender wait rather than drop anything
+pub struct Dispatcher {
+    queues: Vec<Sender<Event>>,
+    workers: Vec<JoinHandle<()>>,
+    metrics: Arc<DispatchMetrics>,
+    //set while senders are waiting on a full queue, so a stall is only reported once
+    behind: AtomicBool,
+}
+
+impl Dispatcher {
+    //one worker per sink, must be called from within a tokio runtime
+    pub fn spawn(sinks: Vec<Box<dyn EventSink>>, config: DispatcherConfig) -> Self {
+        assert!(!sinks.is_empty(), "dispatcher needs at least one sink");
+
+        let metrics = Arc::new(DispatchMetrics::default());
+        let (queues, workers) = sinks
+            .into_iter()
+            .map(|sink| {
+                let (sender, receiver) = mpsc::channel(config.capacity.max(1));
+                let worker = tokio::spawn(drain(
+                    sink,
+                    receiver,
+                    config.batch_size.max(1),
+                    metrics.clone(),
+                ));
+                (sender, worker)
+            })
+            .unzip();
+
+        Self {
+            queues,
+            workers,
+            metrics,
+            behind: AtomicBool::new(false),
+        }
+    }
+
+    pub fn metrics(&self) -> Arc<DispatchMetrics> {
+        self.metrics.clone()
+    }
+
+    //closes the queues and waits for the workers to hand over what is left
+    pub async fn shutdown(self) {
+        drop(self.queues);
+        for worker in self.workers {
+            if let Err(e) = worker.await {
+                error!("Dispatcher worker failed: {}", e);
+            }
+        }
+    }
+
+    //the event never made it into a queue
+    fn stopped(&self) -> Box<dyn std::error::Error + Send + Sync> {
+        self.metrics.enqueued.fetch_sub(1, Ordering::Relaxed);
+        "dispatcher stopped".into()
+    }
+
+    fn queue(&self, event: &Event) -> &Sender<Event> {
+        let mut hasher = DefaultHasher::new();
+        event.destination().hash(&mut hasher);
+        &self.queues[hasher.finish() as usize % self.queues.len()]
+    }
+}
+
+impl EventSink for Dispatcher {
+    fn send(&self, event: Event) -> BoxFuture<'_, TransportResult<()>> {
+        async move {
+            //counted before the worker can see the event, so pending never goes below zero
+            self.metrics.enqueued.fetch_add(1, Ordering::Relaxed);
+
+            let queue = self.queue(&event);
+            match queue.try_send(event) {
+                Ok(()) => {
+                    if self.behind.swap(false, Ordering::Relaxed) {
+                        info!("Dispatcher caught up");
+                    }
+                }
+                Err(TrySendError::Full(event)) => {
+                    self.metrics.blocked.fetch_add(1, Ordering::Relaxed);
+                    if !self.behind.swap(true, Ordering::Relaxed) {
+                        warn!(
+                            "Dispatcher behind with {} events pending, matching waits for it",
+                            self.metrics.snapshot().pending()
+                        );
+                    }
+                    if queue.send(event).await.is_err() {
+                        return Err(self.stopped());
+                    }
+                }
+                Err(TrySendError::Closed(_)) => return Err(self.stopped()),
+            }
+
+            Ok(())
+        }
+        .boxed()
+    }
+}
+
+async fn drain(
+    sink: Box<dyn EventSink>,
+    mut receiver: Receiver<Event>,
+    batch_size: usize,
+    metrics: Arc<DispatchMetrics>,
+) {
+    while let Some(event) = receiver.recv().await {
+        let mut batch = vec![event];
+        while batch.len() < batch_size {
+            match receiver.try_recv() {
+                Ok(event) => batch.push(event),
+                Err(_) => break,
+            }
+        }
+
+        let count = batch.len() as u64;
+        metrics.batches.fetch_add(1, Ordering::Relaxed);
+        match sink.send_batch(batch).await {
+            Ok(()) => {
+                metrics.dispatched.fetch_add(count, Ordering::Relaxed);
+            }
+            Err(e) => {
+                metrics.failed.fetch_add(count, Ordering::Relaxed);
+                error!("Failed to dispatch {} events: {}", count, e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::sync::{Semaphore, mpsc::UnboundedReceiver};
+
+    use super::*;
+    use crate::{
+        transport::channel,
+        types::api::{MessageToApi, OrderCancelledPayload},
+    };
+
+    fn reply(client_id: &str, order_id: &str) -> Event {
+        Event::Api {
+            client_id: client_id.to_string(),
+            message: MessageToApi::OrderCancelled {
+                payload: OrderCancelledPayload {
+                    order_id: order_id.to_string(),
+                    executed_qty: Default::default(),
+                    remaining_qty: Default::default(),
+                },
+            },
+        }
+    }
+
+    fn received(events: &mut UnboundedReceiver<Event>) -> Vec<Event> {
+        std::iter::from_fn(|| events.try_recv().ok()).collect()
+    }
+
+    fn order_ids(events: &[Event], client_id: &str) -> Vec<String> {
+        events
+            .iter()
+            .filter_map(|event| match event {
+                Event::Api {
+                    client_id: to,
+                    message: MessageToApi::OrderCancelled { payload },
+                } if to == client_id => Some(payload.order_id.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    // a sink which only lets an event through once the test hands out a permit
+    struct Gate {
+        permits: Arc<Semaphore>,
+        inner: channel::ChannelSink,
+    }
+
+    impl EventSink for Gate {
+        fn send(&self, event: Event) -> BoxFuture<'_, TransportResult<()>> {
+            async move {
+                self.permits.acquire().await?.forget();
+                self.inner.send(event).await
+            }
+            .boxed()
+        }
+    }
+
+    #[tokio::test]
+    async fn events_keep_their_order_per_destination() {
+        let (first, mut first_events) = channel::sink();
+        let (second, mut second_events) = channel::sink();
+        let dispatcher = Dispatcher::spawn(
+            vec![Box::new(first), Box::new(second)],
+            DispatcherConfig {
+                capacity: 4,
+                batch_size: 3,
+            },
+        );
+        let metrics = dispatcher.metrics();
+
+        for i in 0..20 {
+            for client_id in ["a", "b", "c"] {
+                dispatcher
+                    .send(reply(client_id, &i.to_string()))
+                    .await
+                    .unwrap();
+            }
+        }
+        dispatcher.shutdown().await;
+
+        let mut events = received(&mut first_events);
+        events.extend(received(&mut second_events));
+        let expected: Vec<String> = (0..20).map(|i| i.to_string()).collect();
+        for client_id in ["a", "b", "c"] {
+            assert_eq!(order_ids(&events, client_id), expected);
+        }
+
+        let metrics = metrics.snapshot();
+        assert_eq!(metrics.enqueued, 60);
+        assert_eq!(metrics.dispatched, 60);
+        assert_eq!(metrics.pending(), 0);
+        assert!(metrics.batches < 60);
+    }
+
+    #[test]
+    fn pending_does_not_underflow_on_counters_read_out_of_step() {
+        let metrics = MetricsSnapshot {
+            enqueued: 3,
+            dispatched: 3,
+            failed: 1,
+            ..Default::default()
+        };
+        assert_eq!(metrics.pending(), 0);
+    }
+
+    #[tokio::test]
+    async fn a_full_queue_holds_the_sender_back() {
+        let permits = Arc::new(Semaphore::new(0));
+        let (inner, mut events) = channel::sink();
+        let gate = Gate {
+            permits: permits.clone(),
+            inner,
+        };
+        let dispatcher = Arc::new(Dispatcher::spawn(
+            vec![Box::new(gate)],
+            DispatcherConfig {
+                capacity: 2,
+                batch_size: 1,
+            },
+        ));
+        let metrics = dispatcher.metrics();
+
+        //at most one event held by the worker and two queued, so not all four fit
+        let sender = dispatcher.clone();
+        let sending = tokio::spawn(async move {
+            for i in 0..4 {
+                sender.send(reply("a", &i.to_string())).await.unwrap();
+            }
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!sending.is_finished());
+        assert!(metrics.snapshot().blocked > 0);
+        assert_eq!(metrics.snapshot().dispatched, 0);
+
+        permits.add_permits(4);
+        sending.await.unwrap();
+        Arc::into_inner(dispatcher).unwrap().shutdown().await;
+
+        assert_eq!(order_ids(&received(&mut events), "a"), ["0", "1", "2", "3"]);
+        assert_eq!(metrics.snapshot().dispatched, 4);
+    }
+}
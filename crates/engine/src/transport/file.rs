@@ -0,0 +1,120 @@
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, LineWriter, Lines, Write},
+    path::Path,
+    sync::Mutex,
+};
+
+use futures::{FutureExt, future::BoxFuture};
+
+use crate::types::api::MessageFromApi;
+
+use super::{CommandSource, Event, EventSink, TransportResult};
+
+// writes every event as a line of json, a session can be diffed against an earlier recording
+pub struct FileRecorder {
+    writer: Mutex<LineWriter<File>>,
+}
+
+impl FileRecorder {
+    pub fn create(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        Ok(Self {
+            writer: Mutex::new(LineWriter::new(File::create(path)?)),
+        })
+    }
+}
+
+impl EventSink for FileRecorder {
+    fn send(&self, event: Event) -> BoxFuture<'_, TransportResult<()>> {
+        let result = (|| -> TransportResult<()> {
+            let serialized = serde_json::to_string(&event)?;
+            let mut writer = self.writer.lock().map_err(|_| "recorder poisoned")?;
+            writeln!(writer, "{}", serialized)?;
+            Ok(())
+        })();
+        futures::future::ready(result).boxed()
+    }
+}
+
+// replays commands from a file with one json message per line, blank lines are skipped
+pub struct FileSource {
+    lines: Lines<BufReader<File>>,
+}
+
+impl FileSource {
+    pub fn open(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        Ok(Self {
+            lines: BufReader::new(File::open(path)?).lines(),
+        })
+    }
+}
+
+impl CommandSource for FileSource {
+    fn next(&mut self) -> BoxFuture<'_, TransportResult<Option<MessageFromApi>>> {
+        let result = (|| -> TransportResult<Option<MessageFromApi>> {
+            for line in self.lines.by_ref() {
+                let line = line?;
+                if !line.trim().is_empty() {
+                    return Ok(Some(serde_json::from_str(&line)?));
+                }
+            }
+            Ok(None)
+        })();
+        futures::future::ready(result).boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal::Decimal;
+
+    use crate::types::api::{MessageToApi, OrderCancelledPayload, TickData};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn recordings_replay_as_commands() {
+        let dir = std::env::temp_dir().join(format!("engine-transport-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let recorder = FileRecorder::create(dir.join("events.jsonl")).unwrap();
+        recorder
+            .send(Event::Api {
+                client_id: "c1".to_string(),
+                message: MessageToApi::OrderCancelled {
+                    payload: OrderCancelledPayload {
+                        order_id: "o1".to_string(),
+                        executed_qty: Decimal::ZERO,
+                        remaining_qty: Decimal::ZERO,
+                    },
+                },
+            })
+            .await
+            .unwrap();
+        let recorded = std::fs::read_to_string(dir.join("events.jsonl")).unwrap();
+        assert_eq!(recorded.lines().count(), 1);
+        assert!(recorded.starts_with(r#"{"api":{"client_id":"c1""#));
+
+        let commands: String = [1, 2]
+            .map(|timestamp| {
+                let tick = MessageFromApi::Tick {
+                    data: TickData { timestamp },
+                };
+                serde_json::to_string(&tick).unwrap() + "\n\n"
+            })
+            .concat();
+        std::fs::write(dir.join("commands.jsonl"), commands).unwrap();
+
+        let mut source = FileSource::open(dir.join("commands.jsonl")).unwrap();
+        let mut timestamps = Vec::new();
+        while let Some(command) = source.next().await.unwrap() {
+            match command {
+                MessageFromApi::Tick { data } => timestamps.push(data.timestamp),
+                other => panic!("unexpected command {:?}", other),
+            }
+        }
+        assert_eq!(timestamps, [1, 2]);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,67 @@
+pub mod channel;
+pub mod dispatcher;
+pub mod file;
+
+use futures::{FutureExt, future::BoxFuture};
+use serde::{Deserialize, Serialize};
+
+use crate::types::{
+    api::{MessageFromApi, MessageToApi},
+    db::DbMessage,
+    ws::WsMessage,
+};
+
+// everything the engine sends out, tagged with where it goes
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Event {
+    //queued for the db processor
+    Db(DbMessage),
+    //queued for the ws server on a stream's channel
+    Ws {
+        channel: String,
+        message: WsMessage,
+    },
+    //reply to the api server waiting on `client_id`
+    Api {
+        client_id: String,
+        message: MessageToApi,
+    },
+}
+
+//queue the db processor pops from
+pub const DB_QUEUE: &str = "db_processor";
+
+impl Event {
+    //queue or channel the event is delivered on, events for one destination stay in order
+    pub fn destination(&self) -> &str {
+        match self {
+            Event::Db(_) => DB_QUEUE,
+            Event::Ws { channel, .. } => channel,
+            Event::Api { client_id, .. } => client_id,
+        }
+    }
+}
+
+pub type TransportResult<T> = Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+// where the engine's events go, injected into `Engine` so it never talks to redis itself
+pub trait EventSink: Send + Sync {
+    fn send(&self, event: Event) -> BoxFuture<'_, TransportResult<()>>;
+
+    //sinks which can deliver several events in one round trip override this
+    fn send_batch(&self, events: Vec<Event>) -> BoxFuture<'_, TransportResult<()>> {
+        async move {
+            for event in events {
+                self.send(event).await?;
+            }
+            Ok(())
+        }
+        .boxed()
+    }
+}
+
+// where the engine's commands come from, none when nothing is waiting
+pub trait CommandSource: Send {
+    fn next(&mut self) -> BoxFuture<'_, TransportResult<Option<MessageFromApi>>>;
+}
@@ -1,4 +1,16 @@
-use crate::models::order::Order;
+use crate::{
+    models::{
+        fixed::Scale,
+        order::{Order, OrderSide, OrderStatus, OrderType, PegReference, TimeInForce},
+    },
+    trade::{
+        accounts::{AccountFlag, AccountStatus},
+        auction::{AuctionAction, TradingPhase},
+        groups::GroupType,
+        market::MarketStatus,
+        risk::RiskLimits,
+    },
+};
 use rust_decimal::Decimal;
 use serde::{Deserialize, Serialize};
 
@@ -7,6 +19,19 @@ pub const CANCEL_ORDER: &str = "CANCEL_ORDER";
 pub const ON_RAMP: &str = "ON_RAMP";
 pub const GET_DEPTH: &str = "GET_DEPTH";
 pub const GET_OPEN_ORDER: &str = "GET_OPEN_ORDERS";
+pub const GET_ORDER: &str = "GET_ORDER";
+pub const AMEND_ORDER: &str = "AMEND_ORDER";
+pub const CANCEL_ALL: &str = "CANCEL_ALL";
+pub const BATCH_ORDERS: &str = "BATCH_ORDERS";
+pub const CREATE_ORDER_GROUP: &str = "CREATE_ORDER_GROUP";
+pub const TICK: &str = "TICK";
+pub const AUCTION: &str = "AUCTION";
+pub const SET_MARKET_STATUS: &str = "SET_MARKET_STATUS";
+pub const SET_RISK_LIMITS: &str = "SET_RISK_LIMITS";
+pub const SET_ACCOUNT_FLAG: &str = "SET_ACCOUNT_FLAG";
+pub const WITHDRAW: &str = "WITHDRAW";
+
+pub const MAX_BATCH_ORDERS: usize = 50;
 
 #[derive(Debug, Deserialize, Serialize)]
 #[serde(tag = "type")]
@@ -37,21 +62,177 @@ pub enum MessageFromApi {
         data: GetOpenOrdersData,
         client_id: String,
     },
+
+    #[serde(rename = "GET_ORDER")]
+    GetOrder {
+        data: GetOrderData,
+        client_id: String,
+    },
+
+    #[serde(rename = "AMEND_ORDER")]
+    AmendOrder {
+        data: AmendOrderData,
+        client_id: String,
+    },
+
+    #[serde(rename = "CANCEL_ALL")]
+    CancelAll {
+        data: CancelAllData,
+        client_id: String,
+    },
+
+    #[serde(rename = "BATCH_ORDERS")]
+    BatchOrders {
+        data: BatchOrdersData,
+        client_id: String,
+    },
+
+    #[serde(rename = "CREATE_ORDER_GROUP")]
+    CreateOrderGroup {
+        data: CreateOrderGroupData,
+        client_id: String,
+    },
+
+    //advances the engine clock, nobody waits for a reply
+    #[serde(rename = "TICK")]
+    Tick { data: TickData },
+
+    #[serde(rename = "AUCTION")]
+    Auction {
+        data: AuctionData,
+        client_id: String,
+    },
+
+    #[serde(rename = "SET_MARKET_STATUS")]
+    SetMarketStatus {
+        data: MarketStatusData,
+        client_id: String,
+    },
+
+    #[serde(rename = "SET_RISK_LIMITS")]
+    SetRiskLimits {
+        data: RiskLimitsData,
+        client_id: String,
+    },
+
+    #[serde(rename = "SET_ACCOUNT_FLAG")]
+    SetAccountFlag {
+        data: AccountFlagData,
+        client_id: String,
+    },
+
+    #[serde(rename = "WITHDRAW")]
+    Withdraw {
+        data: WithdrawData,
+        client_id: String,
+    },
+}
+
+impl MessageFromApi {
+    //none for commands nobody waits on
+    pub fn client_id(&self) -> Option<&str> {
+        match self {
+            MessageFromApi::CreateOrder { client_id, .. }
+            | MessageFromApi::CancelOrder { client_id, .. }
+            | MessageFromApi::GetOpenOrders { client_id, .. }
+            | MessageFromApi::OnRame { client_id, .. }
+            | MessageFromApi::GetDepth { client_id, .. }
+            | MessageFromApi::GetOrder { client_id, .. }
+            | MessageFromApi::AmendOrder { client_id, .. }
+            | MessageFromApi::CancelAll { client_id, .. }
+            | MessageFromApi::BatchOrders { client_id, .. }
+            | MessageFromApi::CreateOrderGroup { client_id, .. }
+            | MessageFromApi::Auction { client_id, .. }
+            | MessageFromApi::SetMarketStatus { client_id, .. }
+            | MessageFromApi::SetRiskLimits { client_id, .. }
+            | MessageFromApi::SetAccountFlag { client_id, .. }
+            | MessageFromApi::Withdraw { client_id, .. } => Some(client_id),
+            MessageFromApi::Tick { .. } => None,
+        }
+    }
 }
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct CreateOrderData {
     pub market: String,
+    //limit price, ignored for market and stop market orders
+    #[serde(default)]
     pub price: String,
     pub quantity: String,
     pub side: String,
     pub user_id: String,
+    pub client_order_id: Option<String>,
+    #[serde(default)]
+    pub order_type: OrderType,
+    pub trigger_price: Option<String>,
+    //trailing stops only, one of the two
+    pub trailing_offset: Option<String>,
+    pub trailing_percent: Option<String>,
+    //makes a limit order an iceberg showing this much at a time
+    pub display_quantity: Option<String>,
+    #[serde(default)]
+    pub time_in_force: TimeInForce,
+    //millis, gtd orders only
+    pub expire_at: Option<u64>,
+    //pegged limit orders use `price` as their cap
+    pub peg: Option<PegReference>,
+    pub peg_offset: Option<String>,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct CancelOrderDAta {
-    pub order_id: String,
+    pub order_id: Option<String>,
+    pub client_order_id: Option<String>,
+    //required to resolve a client order id
+    pub user_id: Option<String>,
+    pub market: String,
+    //cancels every member of the group, takes precedence over the order id
+    pub group_id: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct TickData {
+    pub timestamp: u64,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct AuctionData {
     pub market: String,
+    pub action: AuctionAction,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct MarketStatusData {
+    pub market: String,
+    pub status: MarketStatus,
+    //shown on the status stream
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct AccountFlagData {
+    pub user_id: String,
+    pub flag: AccountFlag,
+    //false clears the flag
+    pub enabled: bool,
+    pub reason: String,
+    //freezing only, also cancels every resting order of the user
+    #[serde(default)]
+    pub cancel_orders: bool,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct WithdrawData {
+    pub user_id: String,
+    pub asset: String,
+    pub amount: String,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct RiskLimitsData {
+    //none sets the defaults for users without limits of their own
+    pub user_id: Option<String>,
+    pub limits: RiskLimits,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -59,6 +240,9 @@ pub struct OnRampData {
     pub amount: String,
     pub user_id: String,
     pub txn_id: String,
+    //defaults to the base currency
+    #[serde(default)]
+    pub asset: Option<String>,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -72,8 +256,87 @@ pub struct GetOpenOrdersData {
     pub market: String,
 }
 
+#[derive(Debug, Deserialize, Serialize)]
+pub struct GetOrderData {
+    pub order_id: Option<String>,
+    pub client_order_id: Option<String>,
+    //required to resolve a client order id
+    pub user_id: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct AmendOrderData {
+    pub market: String,
+    pub order_id: Option<String>,
+    pub client_order_id: Option<String>,
+    //required to resolve a client order id
+    pub user_id: Option<String>,
+    //fields left out keep their current value
+    pub price: Option<String>,
+    pub quantity: Option<String>,
+}
+
+//every filter is optional, no filters cancels everything on the exchange
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CancelAllData {
+    pub user_id: Option<String>,
+    pub market: Option<String>,
+    pub side: Option<String>,
+    pub min_price: Option<String>,
+    pub max_price: Option<String>,
+}
+
+// oco legs trade on `side`, a bracket enters on `side` and exits on the other side
+// with the quantity its entry filled
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CreateOrderGroupData {
+    pub market: String,
+    pub user_id: String,
+    pub group_type: GroupType,
+    pub side: String,
+    pub quantity: String,
+    //bracket entry limit price, left out for a market entry
+    #[serde(default)]
+    pub entry_price: String,
+    pub take_profit_price: String,
+    pub stop_trigger_price: String,
+    //makes the stop leg a stop limit instead of a stop market
+    pub stop_limit_price: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct BatchOrdersData {
+    pub orders: Vec<BatchOperation>,
+    //when set nothing is executed unless every item passes validation
+    #[serde(default)]
+    pub all_or_none: bool,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(tag = "op")]
+pub enum BatchOperation {
+    #[serde(rename = "create")]
+    Create(CreateOrderData),
+    #[serde(rename = "cancel")]
+    Cancel(CancelOrderDAta),
+}
+
+impl BatchOperation {
+    //best identifier available before the item is executed
+    pub fn reference(&self) -> String {
+        match self {
+            BatchOperation::Create(data) => data.client_order_id.clone().unwrap_or_default(),
+            BatchOperation::Cancel(data) => data
+                .order_id
+                .clone()
+                .or_else(|| data.client_order_id.clone())
+                .unwrap_or_default(),
+        }
+    }
+}
+
 //message to api
-#[derive(Deserialize, Debug, Serialize)]
+#[derive(Deserialize, Debug, Serialize, Clone)]
 #[serde(tag = "type")]
 pub enum MessageToApi {
     #[serde(rename = "DEPTH")]
@@ -86,32 +349,236 @@ pub enum MessageToApi {
     OrderCancelled { payload: OrderCancelledPayload },
 
     #[serde(rename = "OPEN_ORDERS")]
-    OpenOrders { payload: Vec<Order> },
+    OpenOrders { payload: Vec<OrderInfo> },
+
+    #[serde(rename = "ORDER")]
+    Order { payload: Box<OrderInfo> },
+
+    //the order is neither resting nor in the recent orders cache
+    #[serde(rename = "ORDER_NOT_FOUND")]
+    OrderNotFound { payload: OrderNotFoundPayload },
+
+    #[serde(rename = "ORDER_AMENDED")]
+    OrderAmended { payload: OrderAmendedPayload },
+
+    #[serde(rename = "ORDER_REJECTED")]
+    OrderRejected { payload: OrderRejectedPayload },
+
+    #[serde(rename = "ORDERS_CANCELLED")]
+    OrdersCancelled { payload: OrdersCancelledPayload },
+
+    //one result per batch item, in request order
+    #[serde(rename = "BATCH_RESULTS")]
+    BatchResults { payload: Vec<MessageToApi> },
+
+    #[serde(rename = "ORDER_GROUP_PLACED")]
+    OrderGroupPlaced { payload: OrderGroupPlacedPayload },
+
+    #[serde(rename = "AUCTION_RESULT")]
+    AuctionResult { payload: AuctionPayload },
+
+    #[serde(rename = "MARKET_STATUS")]
+    MarketStatus { payload: MarketStatusPayload },
+
+    #[serde(rename = "RISK_LIMITS")]
+    RiskLimits { payload: RiskLimitsPayload },
+
+    #[serde(rename = "ACCOUNT_STATUS")]
+    AccountStatus { payload: AccountStatusPayload },
+
+    #[serde(rename = "WITHDRAWAL")]
+    Withdrawal { payload: WithdrawalPayload },
+
+    //a command which is not about a single order failed
+    #[serde(rename = "REQUEST_REJECTED")]
+    RequestRejected { payload: RequestRejectedPayload },
+}
+
+// an order as the api sees it, prices and quantities in the market's units instead of ticks
+// and lots
+#[derive(Deserialize, Debug, Serialize, Clone)]
+pub struct OrderInfo {
+    pub price: Decimal,
+    pub quantity: Decimal,
+    pub order_id: String,
+    pub filled: Decimal,
+    pub side: OrderSide,
+    pub user_id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_order_id: Option<String>,
+    pub order_type: OrderType,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trigger_price: Option<Decimal>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trailing_offset: Option<Decimal>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trailing_percent: Option<Decimal>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub watermark: Option<Decimal>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub display_quantity: Option<Decimal>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub visible_quantity: Option<Decimal>,
+    pub time_in_force: TimeInForce,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expire_at: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub peg: Option<PegReference>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub peg_offset: Option<Decimal>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub peg_cap: Option<Decimal>,
+    pub status: OrderStatus,
+    pub avg_fill_price: Decimal,
+    pub created_at: u64,
+    pub updated_at: u64,
+}
+
+impl OrderInfo {
+    pub fn new(order: &Order, scale: &Scale) -> Self {
+        let price = |ticks| scale.price(ticks);
+        let quantity = |lots| scale.quantity(lots);
+
+        Self {
+            price: price(order.price),
+            quantity: quantity(order.quantity),
+            order_id: order.order_id.clone(),
+            filled: quantity(order.filled),
+            side: order.side.clone(),
+            user_id: order.user_id.clone(),
+            client_order_id: order.client_order_id.clone(),
+            order_type: order.order_type,
+            trigger_price: order.trigger_price.map(price),
+            trailing_offset: order.trailing_offset.map(price),
+            trailing_percent: order.trailing_percent,
+            watermark: order.watermark.map(price),
+            group_id: order.group_id.clone(),
+            display_quantity: order.display_quantity.map(quantity),
+            visible_quantity: order.visible_quantity.map(quantity),
+            time_in_force: order.time_in_force,
+            expire_at: order.expire_at,
+            peg: order.peg,
+            peg_offset: order.peg_offset.map(price),
+            peg_cap: order.peg_cap.map(price),
+            status: order.status,
+            avg_fill_price: scale.average_price(order.fill_value, order.filled),
+            created_at: order.created_at,
+            updated_at: order.updated_at,
+        }
+    }
 }
 
-#[derive(Deserialize, Debug, Serialize)]
+#[derive(Deserialize, Debug, Serialize, Clone)]
 pub struct DepthPayload {
     pub bids: Vec<(String, String)>,
     pub asks: Vec<(String, String)>,
 }
 
-#[derive(Deserialize, Debug, Serialize)]
+#[derive(Deserialize, Debug, Serialize, Clone)]
 pub struct OrderPlacedPayload {
     pub order_id: String,
     pub executed_qty: Decimal,
     pub fills: Vec<FillInfo>,
 }
 
-#[derive(Deserialize, Debug, Serialize)]
+#[derive(Deserialize, Debug, Serialize, Clone)]
 pub struct FillInfo {
     pub price: String,
     pub qty: String,
     pub trade_id: u64,
 }
 
-#[derive(Deserialize, Debug, Serialize)]
+#[derive(Deserialize, Debug, Serialize, Clone)]
 pub struct OrderCancelledPayload {
     pub order_id: String,
     pub executed_qty: Decimal,
     pub remaining_qty: Decimal,
 }
+
+#[derive(Deserialize, Debug, Serialize, Clone)]
+pub struct OrderAmendedPayload {
+    pub order_id: String,
+    pub price: String,
+    pub quantity: String,
+    pub executed_qty: Decimal,
+    pub fills: Vec<FillInfo>,
+}
+
+#[derive(Deserialize, Debug, Serialize, Clone)]
+pub struct OrderRejectedPayload {
+    pub order_id: String,
+    pub reason: String,
+    //machine readable reason, set for risk limit breaches
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Serialize, Clone)]
+pub struct OrderNotFoundPayload {
+    pub order_id: String,
+    pub reason: String,
+    pub code: String,
+}
+
+#[derive(Deserialize, Debug, Serialize, Clone)]
+pub struct OrdersCancelledPayload {
+    pub order_ids: Vec<String>,
+    //groups torn down by the cancel
+    pub group_ids: Vec<String>,
+}
+
+#[derive(Deserialize, Debug, Serialize, Clone)]
+pub struct OrderGroupPlacedPayload {
+    pub group_id: String,
+    //entry first for brackets, exit legs get these ids once the entry fills
+    pub order_ids: Vec<String>,
+    pub executed_qty: Decimal,
+}
+
+#[derive(Deserialize, Debug, Serialize, Clone)]
+pub struct AuctionPayload {
+    pub market: String,
+    //phase the market is in after the action
+    pub phase: TradingPhase,
+    //indicative price when an auction starts, clearing price after the uncross
+    pub price: Option<String>,
+    pub volume: Decimal,
+}
+
+#[derive(Deserialize, Debug, Serialize, Clone)]
+pub struct RequestRejectedPayload {
+    pub reason: String,
+}
+
+#[derive(Deserialize, Debug, Serialize, Clone)]
+pub struct WithdrawalPayload {
+    pub user_id: String,
+    pub asset: String,
+    pub amount: String,
+    //at the asset's display precision
+    pub available: String,
+}
+
+#[derive(Deserialize, Debug, Serialize, Clone)]
+pub struct MarketStatusPayload {
+    pub market: String,
+    pub status: MarketStatus,
+    //resting orders cancelled by a delisting
+    pub order_ids: Vec<String>,
+}
+
+#[derive(Deserialize, Debug, Serialize, Clone)]
+pub struct RiskLimitsPayload {
+    pub user_id: Option<String>,
+    pub limits: RiskLimits,
+}
+
+#[derive(Deserialize, Debug, Serialize, Clone)]
+pub struct AccountStatusPayload {
+    pub user_id: String,
+    pub status: AccountStatus,
+    //resting orders cancelled by a freeze
+    pub order_ids: Vec<String>,
+}
@@ -1,13 +1,20 @@
 use serde::{Deserialize, Serialize};
 
+use crate::{
+    models::order::{OrderStatus, OrderType},
+    trade::accounts::AccountFlag,
+};
+
 pub const TRADE_ADDED: &str = "TRADE_ADDED";
 pub const ORDER_UPDATE: &str = "ORDER_UPDATE";
+pub const ACCOUNT_AUDIT: &str = "ACCOUNT_AUDIT";
 
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(tag = "type")]
 pub enum DbMessage {
     TradeAdded { data: TradeAddedData },
     OrderUpdate { data: OrderUpdateData },
+    AccountAudit { data: AccountAuditData },
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -21,10 +28,10 @@ pub struct TradeAddedData {
     pub market: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Default)]
 pub struct OrderUpdateData {
     pub order_id: String,
-    pub executed_qty: f64,
+    pub executed_qty: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub market: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -33,4 +40,28 @@ pub struct OrderUpdateData {
     pub quantity: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub side: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_order_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub order_type: Option<OrderType>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trigger_price: Option<String>,
+    pub group_id: Option<String>,
+    pub expire_at: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<OrderStatus>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub avg_fill_price: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub updated_at: Option<u64>,
+}
+
+//one row per admin change to an account's flags
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AccountAuditData {
+    pub user_id: String,
+    pub flag: AccountFlag,
+    pub enabled: bool,
+    pub reason: String,
+    pub timestamp: u64,
 }
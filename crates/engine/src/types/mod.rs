@@ -1,3 +1,8 @@
+// wire formats shared with the api server, db processor and ws server,
+// the engine does not use every message defined here
+#[allow(dead_code)]
 pub mod api;
+#[allow(dead_code)]
 pub mod db;
+#[allow(dead_code)]
 pub mod ws;
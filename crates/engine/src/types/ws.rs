@@ -1,11 +1,19 @@
 use serde::{Deserialize, Serialize};
 
+use crate::{
+    models::order::OrderStatus,
+    trade::{auction::TradingPhase, market::MarketStatus},
+};
+
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(untagged)]
 pub enum WsMessage {
     TickerUpdate(TicketUpdateMessage),
     DepthUpdate(DepthUpdateMessage),
     TradeAdded(TradeAddedMessage),
+    OrderUpdate(OrderUpdateMessage),
+    AuctionUpdate(AuctionUpdateMessage),
+    MarketStatus(MarketStatusMessage),
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -14,6 +22,7 @@ pub struct TicketUpdateMessage {
     pub data: TickerUpdateData,
 }
 
+#[allow(non_snake_case)]
 #[derive(Debug, Serialize, Deserialize)]
 pub struct TickerUpdateData {
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -78,3 +87,56 @@ pub struct WsTradeAddedData {
     pub q: String,
     pub s: String,
 }
+
+//user stream, published on order@{user_id}
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OrderUpdateMessage {
+    pub stream: String,
+    pub data: WsOrderUpdateData,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WsOrderUpdateData {
+    pub e: String,
+    pub i: String, //order id
+    pub s: String, //symbol
+    pub x: OrderStatus,
+    pub p: String,
+    pub q: String,
+    pub z: String, //filled quantity
+    pub t: u64,
+}
+
+//market stream, published on auction@{market}
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AuctionUpdateMessage {
+    pub stream: String,
+    pub data: WsAuctionData,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WsAuctionData {
+    pub e: String,
+    pub s: String, //symbol
+    pub x: TradingPhase,
+    //indicative clearing price, none when the book does not cross
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub p: Option<String>,
+    pub v: String, //indicative volume
+}
+
+//market stream, published on status@{market}
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MarketStatusMessage {
+    pub stream: String,
+    pub data: WsMarketStatusData,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WsMarketStatusData {
+    pub e: String,
+    pub s: String, //symbol
+    pub x: MarketStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub r: Option<String>, //reason
+}